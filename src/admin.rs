@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use rmcs_auth_api::policy::policy_service_client::PolicyServiceClient;
+use rmcs_auth_api::policy::{PolicySyncRequest, PolicyAction};
+use rmcs_resource_api::provision::provision_service_client::ProvisionServiceClient;
+use rmcs_resource_api::provision::ProvisionAction;
+use rmcs_resource_api::catalog::catalog_service_client::CatalogServiceClient;
+use rmcs_resource_api::catalog::{CatalogExportRequest, CatalogImportRequest, CatalogAction};
+use rmcs_api_server::utility::auth::user_login;
+use rmcs_api_server::utility::interceptor::TokenInterceptor;
+use rmcs_api_server::utility::manifest::{Manifest, ManifestFormat};
+use rmcs_api_server::utility::catalog::CatalogFormat;
+use rmcs_api_server::utility::policy::PolicyFormat;
+use tonic::{Request, transport::Channel};
+use uuid::Uuid;
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long)]
+    auth_address: Option<String>,
+    #[arg(long)]
+    resource_address: Option<String>,
+    #[arg(long)]
+    api_id: Option<String>,
+    #[arg(long)]
+    username: Option<String>,
+    #[arg(long)]
+    password: Option<String>,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Apply a manifest of types, gateways, devices, configs and group members
+    Provision {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>,
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Export resource catalog to a versioned JSON or YAML file
+    Export {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>
+    },
+    /// Import resource catalog from a JSON or YAML file
+    Import {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>,
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Show or apply changes needed to make APIs, procedures, roles and accesses match a policy file
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommand
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum PolicyCommand {
+    Plan {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>
+    },
+    Apply {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FileFormat {
+    Json,
+    Csv,
+    Yaml
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    let auth_address = match args.auth_address {
+        Some(value) => value,
+        None => std::env::var("SERVER_ADDRESS_AUTH").unwrap()
+    };
+    let resource_address = match args.resource_address {
+        Some(value) => value,
+        None => std::env::var("SERVER_ADDRESS_RESOURCE").unwrap()
+    };
+    let api_id: Uuid = match args.api_id {
+        Some(value) => value,
+        None => std::env::var("API_ID").unwrap()
+    }.parse()?;
+    let username = match args.username {
+        Some(value) => value,
+        None => std::env::var("ADMIN_USERNAME").unwrap()
+    };
+    let password = match args.password {
+        Some(value) => value,
+        None => std::env::var("ADMIN_PASSWORD").unwrap()
+    };
+
+    // login and pick access token of the resource API, auth services use the auth token instead
+    let response = user_login(&with_scheme(&auth_address), &username, &password).await
+        .expect("Failed to login to Auth server");
+    let auth_interceptor = TokenInterceptor(response.auth_token);
+    let access_token = response.access_tokens.into_iter()
+        .filter(|t| t.api_id == api_id.as_bytes().to_vec())
+        .map(|t| t.access_token)
+        .next();
+    let interceptor = || access_token.clone()
+        .map(|t| TokenInterceptor(t))
+        .ok_or("User has no access to the resource API");
+
+    match args.command {
+        Command::Provision { file, format, dry_run } => {
+            let format = match format {
+                Some(FileFormat::Json) => ManifestFormat::Json,
+                Some(FileFormat::Csv) => ManifestFormat::Csv,
+                Some(FileFormat::Yaml) => return Err("YAML is not supported for manifest".into()),
+                None => ManifestFormat::from_path(&file)
+                    .ok_or("Unknown manifest format, use --format json or --format csv")?
+            };
+            let request = Manifest::read(&file, format)?.into_request(dry_run)?;
+            let channel = Channel::from_shared(with_scheme(&resource_address))?.connect().await?;
+            let mut client = ProvisionServiceClient::with_interceptor(channel, interceptor()?);
+            let response = client.provision(Request::new(request)).await?.into_inner();
+            for change in response.changes {
+                let action = match ProvisionAction::try_from(change.action) {
+                    Ok(ProvisionAction::Create) => "create",
+                    Ok(ProvisionAction::Update) => "update",
+                    _ => "unchanged"
+                };
+                let id = Uuid::from_slice(&change.id).unwrap_or_default();
+                println!("{:<10} {:<15} {} {}", action, change.kind, id, change.name);
+            }
+            if dry_run {
+                println!("dry run, no changes applied");
+            }
+        },
+        Command::Export { file, format } => {
+            let format = catalog_format(&file, format)?;
+            let channel = Channel::from_shared(with_scheme(&resource_address))?.connect().await?;
+            let mut client = CatalogServiceClient::with_interceptor(channel, interceptor()?);
+            let request = Request::new(CatalogExportRequest { format: format.into() });
+            let response = client.export_catalog(request).await?.into_inner();
+            std::fs::write(&file, response.content)?;
+            println!("catalog exported to {}", file.display());
+        },
+        Command::Import { file, format, dry_run } => {
+            let format = catalog_format(&file, format)?;
+            let content = std::fs::read_to_string(&file)?;
+            let channel = Channel::from_shared(with_scheme(&resource_address))?.connect().await?;
+            let mut client = CatalogServiceClient::with_interceptor(channel, interceptor()?);
+            let request = Request::new(CatalogImportRequest { content, format: format.into(), dry_run });
+            let response = client.import_catalog(request).await?.into_inner();
+            for change in response.changes {
+                let action = match CatalogAction::try_from(change.action) {
+                    Ok(CatalogAction::Create) => "create",
+                    Ok(CatalogAction::Update) => "update",
+                    _ => "unchanged"
+                };
+                let id = Uuid::from_slice(&change.id).unwrap_or_default();
+                println!("{:<10} {:<15} {} {}", action, change.kind, id, change.name);
+            }
+            if dry_run {
+                println!("dry run, no changes applied");
+            }
+        },
+        Command::Policy { command } => {
+            let (file, format, dry_run) = match command {
+                PolicyCommand::Plan { file, format } => (file, format, true),
+                PolicyCommand::Apply { file, format } => (file, format, false)
+            };
+            let format = policy_format(&file, format)?;
+            let content = std::fs::read_to_string(&file)?;
+            let channel = Channel::from_shared(with_scheme(&auth_address))?.connect().await?;
+            let mut client = PolicyServiceClient::with_interceptor(channel, auth_interceptor);
+            let request = Request::new(PolicySyncRequest { content, format: format.into(), dry_run });
+            let response = client.sync_policy(request).await?.into_inner();
+            if response.changes.is_empty() {
+                println!("policy is up to date");
+            }
+            for change in response.changes {
+                let action = match PolicyAction::try_from(change.action) {
+                    Ok(PolicyAction::Create) => "create",
+                    Ok(PolicyAction::Update) => "update",
+                    _ => "remove"
+                };
+                println!("{:<10} {:<10} {}", action, change.kind, change.name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn catalog_format(file: &PathBuf, format: Option<FileFormat>) -> Result<CatalogFormat, Box<dyn std::error::Error>> {
+    let extension = file.extension().and_then(|e| e.to_str());
+    match (format, extension) {
+        (Some(FileFormat::Json), _) | (None, Some("json")) => Ok(CatalogFormat::Json),
+        (Some(FileFormat::Yaml), _) | (None, Some("yaml")) | (None, Some("yml")) => Ok(CatalogFormat::Yaml),
+        _ => Err("Unknown file format, use --format json or --format yaml".into())
+    }
+}
+
+fn policy_format(file: &PathBuf, format: Option<FileFormat>) -> Result<PolicyFormat, Box<dyn std::error::Error>> {
+    let extension = file.extension().and_then(|e| e.to_str());
+    match (format, extension) {
+        (Some(FileFormat::Json), _) | (None, Some("json")) => Ok(PolicyFormat::Json),
+        (Some(FileFormat::Yaml), _) | (None, Some("yaml")) | (None, Some("yml")) => Ok(PolicyFormat::Yaml),
+        _ => Err("Unknown file format, use --format json or --format yaml".into())
+    }
+}
+
+fn with_scheme(address: &str) -> String {
+    let scheme = address.split(":").next().unwrap();
+    if vec!["http", "https"].contains(&scheme) { address.to_owned() }
+    else { String::from("http://") + address }
+}
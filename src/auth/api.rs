@@ -32,8 +32,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.read_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -45,8 +46,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_api_by_name(&request.name).await;
+        let result = auth_db.read_api_by_name(&request.name).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -58,8 +60,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_api_by_ids(
+        let result = auth_db.list_api_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -73,8 +76,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_api_by_name(&request.name).await;
+        let result = auth_db.list_api_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -86,8 +90,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_api_by_category(&request.category).await;
+        let result = auth_db.list_api_by_category(&request.category).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -99,8 +104,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_api_option(
+        let result = auth_db.list_api_option(
             request.name.as_deref(),
             request.category.as_deref()
         ).await;
@@ -115,8 +121,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiCreateResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.create_api(
+        let result = auth_db.create_api(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name, 
             &request.address,
@@ -136,8 +143,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.update_api(
+        let result = auth_db.update_api(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.address.as_deref(),
@@ -157,8 +165,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ApiChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.delete_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.delete_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -170,8 +179,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_procedure(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.read_procedure(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -183,8 +193,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_procedure_by_name(
+        let result = auth_db.read_procedure_by_name(
             Uuid::from_slice(&request.api_id).unwrap_or_default(),
             &request.name,
         ).await;
@@ -199,8 +210,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_procedure_by_ids(
+        let result = auth_db.list_procedure_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -214,8 +226,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_procedure_by_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.list_procedure_by_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -227,8 +240,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_procedure_by_name(&request.name).await;
+        let result = auth_db.list_procedure_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -240,8 +254,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_procedure_option(
+        let result = auth_db.list_procedure_option(
             request.api_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
         ).await;
@@ -256,8 +271,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureCreateResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.create_procedure(
+        let result = auth_db.create_procedure(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.api_id).unwrap_or_default(),
             &request.name,
@@ -274,8 +290,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.update_procedure(
+        let result = auth_db.update_procedure(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.description.as_deref()
@@ -291,8 +308,9 @@ impl ApiService for ApiServer {
         -> Result<Response<ProcedureChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.delete_procedure(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.delete_procedure(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -1,21 +1,52 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use tonic::{Request, Response, Status};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
 use chrono::{Duration, Utc};
 use rmcs_auth_db::Auth;
-use rmcs_auth_db::utility::generate_access_key;
 use rmcs_auth_api::auth::auth_service_server::AuthService;
 use rmcs_auth_api::auth::{
     ApiKeyRequest, ApiKeyResponse, ApiLoginRequest, ApiLoginResponse,
     UserKeyRequest, UserKeyResponse, UserLoginRequest, UserLoginResponse,
     UserRefreshRequest, UserRefreshResponse, UserLogoutRequest, UserLogoutResponse,
-    ProcedureMap, AccessTokenMap
+    ProcedureMap, RoleParentMap, AccessTokenMap, RevokedTokenRequest, RevokedTokenSchema,
+    SigningKeyRequest, SigningKeySetResponse, SigningKeySchema,
+    TokenIntrospectRequest, TokenIntrospectResponse, UserMfaRequest,
+    PasswordResetRequest, PasswordResetConfirm, PasswordResetResponse, ApiKeyLoginRequest,
+    ApiProcedureRequest, ApiProcedureResponse
 };
+use rmcs_auth_db::schema::auth_user::{UserSchema, UserRoleSchema};
+use rmcs_auth_db::schema::auth_api::ApiSchema;
+use rmcs_auth_db::schema::auth_key::ApiKeySchema;
+use rmcs_resource_db::DataValue;
 use crate::utility::{self, token, handle_error, config::{ROOT_ID, ROOT_NAME, ROOT_DATA, API_KEY, USER_KEY, TransportKey}};
+use crate::utility::revoke::{revoked_tokens, is_refresh_token_used, refresh_token_hash, RevokedToken};
+use crate::utility::signing::{signing_keys, PublicKey};
+use crate::utility::token::TokenClaims;
+use crate::utility::mfa::{self, mfa_challenges};
+use super::mfa::UserMfa;
+use super::user::{check_password, push_password_history};
+use crate::utility::password::hash_password;
+use super::role::role_parents;
+use crate::utility::validator::effective_roles;
+use crate::utility::notifier::{notifier, Channel, Notification};
+use crate::utility::verification::{verifications, VerificationKind, EMAIL_VERIFIED};
+use crate::utility::throttle::{login_throttle, LoginKind};
+use crate::utility::network;
+use crate::utility::scope::{ScopeKind, TokenScope};
+use crate::utility::api_key::{api_key_prefix, verify_api_key};
 use super::{
-    KEY_IMPORT_ERR, DECRYPT_ERR, ENCRYPT_ERR, PASSWORD_MISMATCH,
-    GENERATE_TOKEN_ERR, TOKEN_MISMATCH, TOKEN_UNVERIFIED, TOKEN_NOT_FOUND
+    DECRYPT_ERR, PASSWORD_MISMATCH,
+    GENERATE_TOKEN_ERR, TOKEN_MISMATCH, TOKEN_UNVERIFIED, TOKEN_NOT_FOUND, REVOKE_STREAM_LAGGED,
+    REFRESH_TOKEN_REUSED, MFA_NOT_ENROLLED, MFA_CODE_INVALID, MFA_CHALLENGE_INVALID, LOGIN_LOCKED,
+    VERIFY_TOKEN_INVALID, IP_NOT_ALLOWED, ROLE_NOT_ASSIGNED, TENANT_MISMATCH,
+    API_KEY_INVALID, API_KEY_EXPIRED, SERVICE_LOGIN_DENIED, NOTIFY_ERR, HASH_ERR
 };
 
+const REVOKE_CHANNEL_SIZE: usize = 256;
+
 pub struct AuthServer {
     pub auth_db: Auth
 }
@@ -26,11 +57,342 @@ impl AuthServer {
             auth_db
         }
     }
+
+    /// Verify API password sent encrypted with API transport key and return the API
+    async fn verify_api(&self, api_id: &[u8], password: &[u8], remote_ip: &[u8]) -> Result<ApiSchema, Status>
+    {
+        let id = Uuid::from_slice(api_id).unwrap_or_default();
+        let name = id.to_string();
+        if let Some(until) = login_throttle().locked(&self.auth_db, LoginKind::Api, &name).await {
+            return Err(Status::permission_denied(format!("{} {}", LOGIN_LOCKED, until.to_rfc3339())));
+        }
+        let api = self.auth_db.read_api(id).await
+            .map_err(|e| handle_error(e))?;
+        // decrypt encrypted password hash and return error if password is not verified
+        let api_key = API_KEY.get_or_init(|| TransportKey::new());
+        let priv_key = api_key.private_key.clone();
+        let password = utility::decrypt_message(password, priv_key)
+            .map_err(|_| Status::internal(DECRYPT_ERR))?;
+        if utility::verify_password(&password, &api.password).is_err() {
+            login_throttle().fail(&self.auth_db, LoginKind::Api, &name, remote_ip).await;
+            return Err(Status::invalid_argument(PASSWORD_MISMATCH));
+        }
+        login_throttle().succeed(&self.auth_db, LoginKind::Api, &name, remote_ip).await;
+        Ok(api)
+    }
+
+    /// Verify API password and get procedure accesses of the API. Access tokens are verified using
+    /// published public keys, so no signing secret is sent to the API.
+    async fn api_definition(&self, request: ApiLoginRequest, remote_ip: Vec<u8>)
+        -> Result<ApiLoginResponse, Status>
+    {
+        let api = self.verify_api(&request.api_id, &request.password, &remote_ip).await?;
+        let access_procedures = api.procedures.into_iter()
+            .map(|e| ProcedureMap { procedure: e.name, roles: e.roles })
+            .collect();
+        // role parents are sent along procedures so inherited accesses are resolved by the api
+        let role_parents = role_parents(&self.auth_db, api.id).await
+            .map_err(|e| handle_error(e))?
+            .into_iter()
+            .map(|e| RoleParentMap { role: e.role, parent: e.parent })
+            .collect();
+        Ok(ApiLoginResponse { access_procedures, role_parents })
+    }
+
+    /// Create an MFA challenge for a user who enrolled or is required to use MFA,
+    /// return none if the user can login with password only
+    async fn mfa_challenge(&self, user: &UserSchema, remote_ip: &[u8], roles: &[String]) -> Result<Option<String>, Status>
+    {
+        if user.id == ROOT_ID {
+            let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
+            if root.mfa_secret.is_none() {
+                return Ok(None);
+            }
+            return mfa_challenges().create(&self.auth_db, &user.name, remote_ip, roles).await
+                .map(Some)
+                .map_err(handle_error);
+        }
+        let state = UserMfa::read_user(&self.auth_db, user).await?;
+        match (state.secret, state.required) {
+            (Some(_), _) => mfa_challenges().create(&self.auth_db, &user.name, remote_ip, roles).await
+                .map(Some)
+                .map_err(handle_error),
+            (None, true) => Err(Status::permission_denied(MFA_NOT_ENROLLED)),
+            (None, false) => Ok(None)
+        }
+    }
+
+    /// Create auth token and an access token for each api carrying every user role of the api,
+    /// or only requested roles so a client can step down its privileges. Tokens exchanged from an
+    /// API key are tagged with the key and never outlive it.
+    async fn issue_tokens(&self, mut user: UserSchema, mut remote_ip: Vec<u8>, roles: &[String], api_key: Option<&ApiKeySchema>) -> Result<UserLoginResponse, Status>
+    {
+        if roles.len() > 0 {
+            user.roles.retain(|r| roles.contains(&r.role));
+            if user.roles.len() == 0 {
+                return Err(Status::permission_denied(ROLE_NOT_ASSIGNED));
+            }
+        }
+        // only issue tokens of roles whose IP allowlist contain the remote address
+        let ip = network::ip_from_octets(&remote_ip);
+        let role_number = user.roles.len();
+        user.roles.retain(|r| role_ip_allowed(r, ip));
+        if role_number > 0 && user.roles.len() == 0 {
+            return Err(Status::permission_denied(IP_NOT_ALLOWED));
+        }
+        // delete all previous token if one of the roles marked as non multi device login
+        let multi = user.roles.iter().map(|e| e.multi).filter(|&e| !e).count();
+        if multi > 0 {
+            let tokens = self.auth_db.list_token_by_user(user.id).await
+                .map_err(|e| handle_error(e))?;
+            self.auth_db.delete_token_by_user(user.id).await
+            .map_err(|e| handle_error(e))?;
+            for token in tokens {
+                revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                    .map_err(handle_error)?;
+            }
+        }
+        let ip_lock = user.roles.iter().map(|e| e.ip_lock).filter(|&e| e).count();
+        if ip_lock == 0 {
+            remote_ip = Vec::new();
+        }
+        // get minimum refresh duration of roles associated with the user and calculate refresh expire
+        let duration = user.roles.iter().map(|e| e.refresh_duration).min().unwrap_or_default();
+        let mut expire = Utc::now() + Duration::seconds(duration as i64);
+        if let Some(key_expire) = api_key.and_then(|k| k.expire) {
+            expire = expire.min(key_expire);
+        }
+        // group user roles by api so roles of the same api share an access token
+        let mut api_roles: Vec<(Uuid, Vec<_>)> = Vec::new();
+        for e in &user.roles {
+            match api_roles.iter_mut().filter(|(id, _)| *id == e.api_id).next() {
+                Some((_, roles)) => roles.push(e),
+                None => api_roles.push((e.api_id, vec![e]))
+            }
+        }
+        // user of a tenant only get tokens of APIs which are shared or owned by the same tenant
+        if user.tenant_id.is_some() {
+            let mut tenant_roles = Vec::new();
+            for (api_id, roles) in api_roles {
+                if self.tenant_api(&user, api_id).await? {
+                    tenant_roles.push((api_id, roles));
+                }
+            }
+            if tenant_roles.len() == 0 {
+                return Err(Status::permission_denied(TENANT_MISMATCH));
+            }
+            api_roles = tenant_roles;
+        }
+        // insert new tokens as a number of api and get generated access id, refresh token, and auth token
+        let result = match api_key {
+            Some(key) => self.auth_db.create_api_key_token(key.id, user.id, expire, &remote_ip, api_roles.len() as u32).await,
+            None => self.auth_db.create_auth_token(user.id, expire, &remote_ip, api_roles.len() as u32).await
+        };
+        let mut iter_tokens = result
+            .map_err(|e| handle_error(e))?
+            .into_iter();
+        let mut auth_token = String::new();
+        // generate access tokens using data from user roles and generated access id
+        let mut tokens: Vec<AccessTokenMap> = Vec::new();
+        for (api_id, roles) in &api_roles {
+            let generate = iter_tokens.next().unwrap_or_default();
+            auth_token = generate.2;
+            let claims = self.token_claims(&user, *api_id, roles, generate.0, &token::family_id(&auth_token)).await?;
+            let duration = roles.iter().map(|e| e.access_duration).min().unwrap_or_default();
+            let access_token = token::generate_token(claims, duration)
+                .unwrap_or(String::new());
+            if access_token != String::new() {
+                tokens.push(AccessTokenMap {
+                    api_id: api_id.as_bytes().to_vec(),
+                    access_token,
+                    refresh_token: generate.1
+                });
+            }
+        }
+        if api_roles.len() != tokens.len() {
+            return Err(Status::internal(GENERATE_TOKEN_ERR));
+        }
+        Ok(UserLoginResponse {
+            user_id: user.id.as_bytes().to_vec(),
+            auth_token,
+            access_tokens: tokens,
+            mfa_challenge: String::new()
+        })
+    }
+
+    /// Build access token claims of user roles of an api, used on login and refresh so a refreshed
+    /// token carries the same roles, tenant, custom claims, scope, and IP allowlist as a new login
+    async fn token_claims(&self, user: &UserSchema, api_id: Uuid, roles: &[&UserRoleSchema], jti: i32, fid: &str) -> Result<TokenClaims, Status>
+    {
+        let names: Vec<String> = roles.iter().map(|e| e.role.clone()).collect();
+        let mut claims = TokenClaims::new(jti, &names[0], user.id, api_id, fid);
+        if names.len() > 1 {
+            claims.rol = names.clone();
+        }
+        claims.tnt = user.tenant_id.unwrap_or_default();
+        for name in &names {
+            claims.ext.extend(self.custom_claims(user.id, api_id, name).await);
+        }
+        claims.scp = self.token_scope(user.id, api_id, &names).await?;
+        claims.ipn = roles.iter()
+            .filter(|e| !e.ip_allow.is_empty())
+            .map(|e| e.ip_allow.clone())
+            .collect();
+        Ok(claims)
+    }
+
+    /// Rebuild claims of a token being refreshed from current roles of the user, so removed roles,
+    /// changed scopes, tenant, and IP allowlists apply without a new login. Return the claims with
+    /// access duration of the remaining roles.
+    async fn refresh_claims(&self, user_id: Uuid, claims: &TokenClaims, ip: Option<IpAddr>) -> Result<(TokenClaims, i32), Status>
+    {
+        let user: UserSchema = if user_id == ROOT_ID {
+            ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default().into()
+        } else {
+            self.auth_db.read_user(user_id).await
+                .map_err(handle_error)?
+        };
+        if !self.tenant_api(&user, claims.aid).await? {
+            return Err(Status::permission_denied(TENANT_MISMATCH));
+        }
+        let names = claims.roles();
+        let mut roles: Vec<&UserRoleSchema> = user.roles.iter()
+            .filter(|r| r.api_id == claims.aid && names.contains(&r.role))
+            .collect();
+        if roles.is_empty() {
+            return Err(Status::permission_denied(ROLE_NOT_ASSIGNED));
+        }
+        roles.retain(|r| role_ip_allowed(r, ip));
+        if roles.is_empty() {
+            return Err(Status::permission_denied(IP_NOT_ALLOWED));
+        }
+        // keep primary role of the token when it is still assigned
+        roles.sort_by_key(|r| r.role != claims.sub);
+        let duration = roles.iter().map(|e| e.access_duration).min().unwrap_or_default();
+        let claims = self.token_claims(&user, claims.aid, &roles, claims.jti, &claims.fid).await?;
+        Ok((claims, duration))
+    }
+
+    /// Check whether a user may get a token of an api, user of a tenant only get tokens of APIs
+    /// which are shared or owned by the same tenant
+    async fn tenant_api(&self, user: &UserSchema, api_id: Uuid) -> Result<bool, Status>
+    {
+        if user.tenant_id.is_none() {
+            return Ok(true);
+        }
+        let api = self.auth_db.read_api(api_id).await
+            .map_err(handle_error)?;
+        Ok(api.tenant_id.is_none() || api.tenant_id == user.tenant_id)
+    }
+
+    /// Get resource scope of user roles. User scopes take precedence over role scopes, and a role
+    /// without scope gives access to every resource
+    async fn token_scope(&self, user_id: Uuid, api_id: Uuid, roles: &[String]) -> Result<Option<TokenScope>, Status>
+    {
+        if user_id == ROOT_ID {
+            return Ok(None);
+        }
+        let mut scope = TokenScope::default();
+        let user_scopes = self.auth_db.list_user_scope(user_id).await
+            .map_err(|e| handle_error(e))?;
+        if user_scopes.len() > 0 {
+            for e in user_scopes {
+                scope.push(ScopeKind::from(e.kind.as_str()), e.resource_id);
+            }
+            return Ok(Some(scope));
+        }
+        let api_roles = self.auth_db.list_role_by_api(api_id).await
+            .map_err(|e| handle_error(e))?;
+        for role in api_roles.into_iter().filter(|r| roles.contains(&r.name)) {
+            let role_scopes = self.auth_db.list_role_scope(role.id).await
+                .map_err(|e| handle_error(e))?;
+            if role_scopes.len() == 0 {
+                return Ok(None);
+            }
+            for e in role_scopes {
+                scope.push(ScopeKind::from(e.kind.as_str()), e.resource_id);
+            }
+        }
+        Ok(Some(scope).filter(|s| !s.is_empty()))
+    }
+
+    /// Get custom claims of a user role from user profiles whose name is defined in the role profiles
+    async fn custom_claims(&self, user_id: Uuid, api_id: Uuid, role: &str) -> BTreeMap<String, serde_json::Value>
+    {
+        let mut claims = BTreeMap::new();
+        if user_id == ROOT_ID {
+            return claims;
+        }
+        let role_id = match self.auth_db.list_role_by_api(api_id).await {
+            Ok(roles) => match roles.into_iter().filter(|r| r.name == role).next() {
+                Some(r) => r.id,
+                None => return claims
+            },
+            Err(_) => return claims
+        };
+        let names: Vec<String> = self.auth_db.list_role_profile_by_role(role_id).await
+            .map(|profiles| profiles.into_iter().map(|p| p.name).collect())
+            .unwrap_or_default();
+        if names.len() == 0 {
+            return claims;
+        }
+        let profiles = self.auth_db.list_user_profile_by_user(user_id).await.unwrap_or_default();
+        for profile in profiles {
+            // MFA data must never leave the auth server
+            if names.contains(&profile.name) && !mfa::is_mfa_profile(&profile.name) {
+                claims.insert(profile.name, token::claim_value(profile.value));
+            }
+        }
+        claims
+    }
+
+    /// Check an access token against the signing keys, revoked tokens, and token table, and get the
+    /// procedures of the API which the token role may call. Return none for an inactive token.
+    async fn introspect(&self, request: TokenIntrospectRequest) -> Option<TokenIntrospectResponse>
+    {
+        let claims = token::decode_token(&request.access_token, &signing_keys().public_keys(), true).ok()?;
+        if revoked_tokens().is_revoked(claims.jti) {
+            return None;
+        }
+        let token = self.auth_db.read_access_token(claims.jti).await.ok()?;
+        if token.expire <= Utc::now() {
+            return None;
+        }
+        let api_id = Uuid::from_slice(&request.api_id).unwrap_or_default();
+        let root = token.user_id == ROOT_ID && claims.sub == ROOT_NAME;
+        let roles = claims.roles();
+        if !root {
+            // every token role must be one of the user roles of requested api
+            let user = self.auth_db.read_user(token.user_id).await.ok()?;
+            let assigned = roles.iter()
+                .all(|role| user.roles.iter().any(|r| r.api_id == api_id && &r.role == role));
+            if !assigned {
+                return None;
+            }
+        }
+        let api = self.auth_db.read_api(api_id).await.ok()?;
+        let parents = role_parents(&self.auth_db, api_id).await.ok()?;
+        let procedures = api.procedures.into_iter()
+            .filter(|p| root || effective_roles(&p.roles, &parents).iter().any(|r| claims.has_role(r)))
+            .map(|p| p.name)
+            .collect();
+        Some(TokenIntrospectResponse {
+            active: true,
+            role: claims.sub,
+            roles,
+            user_id: token.user_id.as_bytes().to_vec(),
+            expire: (claims.exp * 1_000_000) as i64,
+            procedures
+        })
+    }
+
 }
 
 #[tonic::async_trait]
 impl AuthService for AuthServer {
 
+    type StreamRevokedTokenStream = ReceiverStream<Result<RevokedTokenSchema, Status>>;
+
     async fn api_login_key(&self, _: Request<ApiKeyRequest>)
         -> Result<Response<ApiKeyResponse>, Status>
     {
@@ -42,38 +404,36 @@ impl AuthService for AuthServer {
     async fn api_login(&self, request: Request<ApiLoginRequest>)
         -> Result<Response<ApiLoginResponse>, Status>
     {
+        let remote_ip = network::remote_octets(&request);
+        let response = self.api_definition(request.into_inner(), remote_ip).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn api_access(&self, request: Request<ApiLoginRequest>)
+        -> Result<Response<ApiLoginResponse>, Status>
+    {
+        let remote_ip = network::remote_octets(&request);
+        let response = self.api_definition(request.into_inner(), remote_ip).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn api_register_procedure(&self, request: Request<ApiProcedureRequest>)
+        -> Result<Response<ApiProcedureResponse>, Status>
+    {
+        let remote_ip = network::remote_octets(&request);
         let request = request.into_inner();
-        let id = Uuid::from_slice(&request.api_id).unwrap_or_default();
-        let result = self.auth_db.read_api(id).await;
-        let (root_key, access_key, access_procedures) = match result {
-            Ok(api) => {
-                // decrypt encrypted password hash and return error if password is not verified
-                let api_key = API_KEY.get_or_init(|| TransportKey::new());
-                let priv_key = api_key.private_key.clone();
-                let hash = api.password.clone();
-                let password = utility::decrypt_message(&request.password, priv_key)
-                    .map_err(|_| Status::internal(DECRYPT_ERR))?;
-                utility::verify_password(&password, &hash)
-                    .map_err(|_| Status::invalid_argument(PASSWORD_MISMATCH))?;
-                let pub_key = utility::import_public_key(&request.public_key)
-                    .map_err(|_| Status::internal(KEY_IMPORT_ERR))?;
-                // update api with generated access key
-                let key = generate_access_key();
-                self.auth_db.update_api(id, None, None, None, None, None, Some(&key)).await
-                    .map_err(|e| handle_error(e))?;
-                let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
-                let root_key = utility::encrypt_message(&root.access_key, pub_key.clone())
-                    .map_err(|_| Status::internal(ENCRYPT_ERR))?;
-                let access_key = utility::encrypt_message(&key, pub_key)
-                    .map_err(|_| Status::internal(ENCRYPT_ERR))?;
-                let procedures = api.procedures.into_iter()
-                    .map(|e| ProcedureMap { procedure: e.name, roles: e.roles })
-                    .collect();
-                (root_key, access_key, procedures)
-            },
-            Err(e) => return Err(handle_error(e))
-        };
-        Ok(Response::new(ApiLoginResponse { root_key, access_key, access_procedures }))
+        // an API can only register its own procedures, so no root credential is needed
+        let api = self.verify_api(&request.api_id, &request.password, &remote_ip).await?;
+        let mut created = Vec::new();
+        for name in request.procedures {
+            if api.procedures.iter().any(|p| p.name == name) || created.contains(&name) {
+                continue;
+            }
+            self.auth_db.create_procedure(Uuid::new_v4(), api.id, &name, "").await
+                .map_err(|e| handle_error(e))?;
+            created.push(name);
+        }
+        Ok(Response::new(ApiProcedureResponse { procedures: created }))
     }
 
     async fn user_login_key(&self, _: Request<UserKeyRequest>)
@@ -87,11 +447,11 @@ impl AuthService for AuthServer {
     async fn user_login(&self, request: Request<UserLoginRequest>)
         -> Result<Response<UserLoginResponse>, Status>
     {
-        let mut remote_ip = request.remote_addr().map(|s| match s.ip() {
-                std::net::IpAddr::V4(v) => v.octets().to_vec(),
-                std::net::IpAddr::V6(v) => v.octets().to_vec()
-            }).unwrap_or(Vec::new());
+        let remote_ip = network::remote_octets(&request);
         let request = request.into_inner();
+        if let Some(until) = login_throttle().locked(&self.auth_db, LoginKind::User, &request.username).await {
+            return Err(Status::permission_denied(format!("{} {}", LOGIN_LOCKED, until.to_rfc3339())));
+        }
         // Get user schema from root data or database
         let result = if &request.username == ROOT_NAME {
             let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
@@ -100,7 +460,8 @@ impl AuthService for AuthServer {
             self.auth_db.read_user_by_name(&request.username).await
                 .map_err(|e| handle_error(e))
         };
-        let (user_id, auth_token, access_tokens) = match result {
+        let result = match result {
+            Ok(user) if user.service => Err(Status::permission_denied(SERVICE_LOGIN_DENIED)),
             Ok(user) => {
                 // decrypt encrypted password hash and return error if password is not verified
                 let user_key = USER_KEY.get_or_init(|| TransportKey::new());
@@ -108,101 +469,258 @@ impl AuthService for AuthServer {
                 let hash = user.password.clone();
                 let password = utility::decrypt_message(&request.password, priv_key)
                     .map_err(|_| Status::internal(DECRYPT_ERR))?;
-                if user.name == ROOT_NAME {
-                    // add delay to overcome brute force attack
-                    std::thread::sleep(std::time::Duration::from_millis(500));
-                    if user.password.into_bytes() != password {
-                        return Err(Status::invalid_argument(PASSWORD_MISMATCH))
-                    }
+                let verified = if user.name == ROOT_NAME {
+                    user.password.as_bytes() == password.as_slice()
                 } else {
-                    utility::verify_password(&password, &hash)
-                        .map_err(|_| Status::invalid_argument(PASSWORD_MISMATCH))?;
-                }
-                // delete all previous token if one of the roles marked as non multi device login
-                let multi = user.roles.iter().map(|e| e.multi).filter(|&e| !e).count();
-                if multi > 0 {
-                    self.auth_db.delete_token_by_user(user.id).await
-                    .map_err(|e| handle_error(e))?;
-                }
-                let ip_lock = user.roles.iter().map(|e| e.ip_lock).filter(|&e| e).count();
-                if ip_lock == 0 {
-                    remote_ip = Vec::new();
+                    utility::verify_password(&password, &hash).is_ok()
+                };
+                // failure is delayed without blocking the worker to overcome brute force attack
+                if !verified {
+                    login_throttle().fail(&self.auth_db, LoginKind::User, &request.username, &remote_ip).await;
+                    return Err(Status::invalid_argument(PASSWORD_MISMATCH));
                 }
-                // get minimum refresh duration of roles associated with the user and calculate refresh expire
-                let duration = user.roles.iter().map(|e| e.refresh_duration).min().unwrap_or_default();
-                let expire = Utc::now() + Duration::seconds(duration as i64);
-                // insert new tokens as a number of user role and get generated access id, refresh token, and auth token
-                let mut iter_tokens = self.auth_db
-                    .create_auth_token(user.id, expire, &remote_ip, user.roles.len() as u32)
-                    .await
-                    .map_err(|e| handle_error(e))?
-                    .into_iter();
-                let mut auth_token = String::new();
-                // generate access tokens using data from user role and generated access id
-                let tokens: Vec<AccessTokenMap> = user.roles.iter().map(|e| {
-                    let generate = iter_tokens.next().unwrap_or_default();
-                    auth_token = generate.2;
-                    AccessTokenMap {
-                        api_id: e.api_id.as_bytes().to_vec(),
-                        access_token: token::generate_token(generate.0, &e.role, e.access_duration, &e.access_key)
-                            .unwrap_or(String::new()),
-                        refresh_token: generate.1
-                    }
-                })
-                .filter(|e| e.access_token != String::new())
-                .collect();
-                if user.roles.len() != tokens.len() {
-                    return Err(Status::internal(GENERATE_TOKEN_ERR));
+                // ask for OTP before issuing tokens if the user enrolled or is required to use MFA,
+                // failures are only reset once the OTP is accepted
+                if let Some(mfa_challenge) = self.mfa_challenge(&user, &remote_ip, &request.roles).await? {
+                    return Ok(Response::new(UserLoginResponse { mfa_challenge, ..Default::default() }));
                 }
-                (user.id, auth_token, tokens)
+                login_throttle().succeed(&self.auth_db, LoginKind::User, &request.username, &remote_ip).await;
+                self.issue_tokens(user, remote_ip, &request.roles, None).await
             },
-            Err(e) => return Err(e)
+            Err(e) => {
+                // unknown user name is counted as failure so it can not be probed faster
+                login_throttle().fail(&self.auth_db, LoginKind::User, &request.username, &remote_ip).await;
+                Err(e)
+            }
         };
-        Ok(Response::new(UserLoginResponse { user_id: user_id.as_bytes().to_vec(), auth_token, access_tokens }))
+        result.map(|response| Response::new(response))
     }
 
-    async fn user_refresh(&self, request: Request<UserRefreshRequest>)
-        -> Result<Response<UserRefreshResponse>, Status>
+    async fn user_login_mfa(&self, request: Request<UserMfaRequest>)
+        -> Result<Response<UserLoginResponse>, Status>
     {
-        let remote_ip = request.remote_addr().map(|s| match s.ip() {
-                std::net::IpAddr::V4(v) => v.octets().to_vec(),
-                std::net::IpAddr::V6(v) => v.octets().to_vec()
-            }).unwrap_or(Vec::new());
+        let remote_ip = network::remote_octets(&request);
         let request = request.into_inner();
-        let result = self.auth_db.read_api(Uuid::from_slice(&request.api_id).unwrap_or_default()).await;
-        let (access_key, token_claims) = match result {
-            Ok(api) => {
-                // verify access token and get token claims
-                let token_claims = token::decode_token(&request.access_token, &api.access_key, false)
-                    .map_err(|_| Status::internal(TOKEN_UNVERIFIED))?;
-                (api.access_key, token_claims)
-            },
-            Err(e) => {
-                if request.api_id != ROOT_ID.as_bytes().to_vec() {
-                    return Err(handle_error(e));
-                }
-                let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
-                let token_claims = token::decode_token(&request.access_token, &root.access_key, false)
-                    .map_err(|_| Status::internal("TOKEN_UNVERIFIED root"))?;
-                (root.access_key, token_claims)
+        // challenge must be completed from the same address where password is submitted
+        let challenge = mfa_challenges().take(&self.auth_db, &request.challenge).await
+            .filter(|c| c.remote_ip == remote_ip)
+            .ok_or(Status::unauthenticated(MFA_CHALLENGE_INVALID))?;
+        if let Some(until) = login_throttle().locked(&self.auth_db, LoginKind::Mfa, &challenge.username).await {
+            return Err(Status::permission_denied(format!("{} {}", LOGIN_LOCKED, until.to_rfc3339())));
+        }
+        let (user, verified) = if &challenge.username == ROOT_NAME {
+            let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
+            let verified = root.mfa_secret.as_deref()
+                .and_then(|secret| mfa::verify_code(secret, &request.code))
+                .map(mfa::accept_root_step)
+                .unwrap_or(false);
+            (root.into(), verified)
+        } else {
+            let user: UserSchema = self.auth_db.read_user_by_name(&challenge.username).await
+                .map_err(|e| handle_error(e))?;
+            let mut state = UserMfa::read(&self.auth_db, user.id).await?;
+            let verified = state.verify(&self.auth_db, user.id, &request.code).await?;
+            (user, verified)
+        };
+        if !verified {
+            // OTP failures are counted apart so a known password can not reset them
+            login_throttle().fail(&self.auth_db, LoginKind::Mfa, &challenge.username, &remote_ip).await;
+            return Err(Status::invalid_argument(MFA_CODE_INVALID));
+        }
+        login_throttle().succeed(&self.auth_db, LoginKind::Mfa, &challenge.username, &remote_ip).await;
+        login_throttle().succeed(&self.auth_db, LoginKind::User, &challenge.username, &remote_ip).await;
+        let response = self.issue_tokens(user, remote_ip, &challenge.roles, None).await?;
+        Ok(Response::new(response))
+    }
+
+    async fn api_key_login(&self, request: Request<ApiKeyLoginRequest>)
+        -> Result<Response<UserLoginResponse>, Status>
+    {
+        let remote_ip = network::remote_octets(&request);
+        let request = request.into_inner();
+        let prefix = api_key_prefix(&request.key)
+            .ok_or(Status::unauthenticated(API_KEY_INVALID))?;
+        // key prefix is not secret, so failures are counted for the prefix from a client address
+        // which is forwarded by resource servers, rather than locking the key for every client
+        let name = match network::ip_from_octets(&remote_ip) {
+            Some(ip) => format!("{}@{}", prefix, ip),
+            None => prefix.to_owned()
+        };
+        if let Some(until) = login_throttle().locked(&self.auth_db, LoginKind::ApiKey, &name).await {
+            return Err(Status::permission_denied(format!("{} {}", LOGIN_LOCKED, until.to_rfc3339())));
+        }
+        // only hash of the key is stored, the prefix select the key to compare
+        let key = match self.auth_db.read_api_key_by_prefix(prefix).await {
+            Ok(value) if verify_api_key(&request.key, &value.hash) => value,
+            _ => {
+                login_throttle().fail(&self.auth_db, LoginKind::ApiKey, &name, &remote_ip).await;
+                return Err(Status::unauthenticated(API_KEY_INVALID));
             }
         };
+        login_throttle().succeed(&self.auth_db, LoginKind::ApiKey, &name, &remote_ip).await;
+        if api_key_expired(&key) {
+            return Err(Status::unauthenticated(API_KEY_EXPIRED));
+        }
+        // requested roles can only narrow roles of the key
+        let roles: Vec<String> = match (key.roles.len(), request.roles.len()) {
+            (0, _) => request.roles,
+            (_, 0) => key.roles.clone(),
+            _ => key.roles.iter().filter(|r| request.roles.contains(r)).cloned().collect()
+        };
+        if roles.len() == 0 && request.roles.len() > 0 {
+            return Err(Status::permission_denied(ROLE_NOT_ASSIGNED));
+        }
+        let user = self.auth_db.read_user(key.user_id).await
+            .map_err(|e| handle_error(e))?;
+        let response = self.issue_tokens(user, remote_ip, &roles, Some(&key)).await?;
+        self.auth_db.update_api_key_used(key.id, Utc::now()).await
+            .map_err(|e| handle_error(e))?;
+        Ok(Response::new(response))
+    }
+
+    async fn request_password_reset(&self, request: Request<PasswordResetRequest>)
+        -> Result<Response<PasswordResetResponse>, Status>
+    {
+        let remote_ip = network::remote_octets(&request);
+        let request = request.into_inner();
+        // every request is counted so a user mailbox can not be flooded, a locked user name
+        // silently get no more tokens
+        if login_throttle().locked(&self.auth_db, LoginKind::PasswordReset, &request.username).await.is_some() {
+            return Ok(Response::new(PasswordResetResponse { }));
+        }
+        login_throttle().fail(&self.auth_db, LoginKind::PasswordReset, &request.username, &remote_ip).await;
+        // always respond success so registered user names and emails can not be probed
+        let user = match self.auth_db.read_user_by_name(&request.username).await {
+            Ok(user) => user,
+            Err(_) => return Ok(Response::new(PasswordResetResponse { }))
+        };
+        // token is only sent to an email which the user proved to own
+        let profiles = self.auth_db.list_user_profile_by_user(user.id).await
+            .map_err(handle_error)?;
+        let verified = !user.email.is_empty() && profiles.iter()
+            .any(|p| p.name == EMAIL_VERIFIED && matches!(&p.value, DataValue::String(v) if v == &user.email));
+        if !verified {
+            return Ok(Response::new(PasswordResetResponse { }));
+        }
+        let notifier = notifier(Channel::Email).ok_or(Status::unavailable(NOTIFY_ERR))?;
+        let token = verifications().create(&self.auth_db, VerificationKind::PasswordReset, user.id, &user.email).await
+            .map_err(handle_error)?;
+        let notification = Notification {
+            destination: user.email,
+            subject: String::from("Password reset"),
+            body: format!("Use this token to reset password of user {}: {}", user.name, token)
+        };
+        if let Err(e) = notifier.notify(&notification).await {
+            tracing::warn!("Failed to send password reset notification: {}", e);
+        }
+        Ok(Response::new(PasswordResetResponse { }))
+    }
+
+    async fn confirm_password_reset(&self, request: Request<PasswordResetConfirm>)
+        -> Result<Response<PasswordResetResponse>, Status>
+    {
+        let request = request.into_inner();
+        let verification = verifications().take(&self.auth_db, VerificationKind::PasswordReset, &request.token).await
+            .ok_or(Status::invalid_argument(VERIFY_TOKEN_INVALID))?;
+        let user = self.auth_db.read_user(verification.user_id).await
+            .map_err(|e| handle_error(e))?;
+        let previous = check_password(&self.auth_db, user.id, &request.password, None, false).await?;
+        let hash = hash_password(request.password.as_bytes())
+            .map_err(|_| Status::internal(HASH_ERR))?;
+        let transaction = self.auth_db.begin().await
+            .map_err(handle_error)?;
+        transaction.update_user(user.id, None, None, None, Some(&hash)).await
+            .map_err(handle_error)?;
+        push_password_history(&transaction, user.id, &previous).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        // sign out every session since the old password may be known by someone else
+        let tokens = self.auth_db.list_token_by_user(user.id).await
+            .map_err(|e| handle_error(e))?;
+        self.auth_db.delete_token_by_user(user.id).await
+            .map_err(|e| handle_error(e))?;
+        for token in tokens {
+            revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                .map_err(handle_error)?;
+        }
+        login_throttle().unlock(&self.auth_db, LoginKind::User, &user.name).await
+            .map_err(handle_error)?;
+        login_throttle().unlock(&self.auth_db, LoginKind::PasswordReset, &user.name).await
+            .map_err(handle_error)?;
+        Ok(Response::new(PasswordResetResponse { }))
+    }
+
+    async fn user_refresh(&self, request: Request<UserRefreshRequest>)
+        -> Result<Response<UserRefreshResponse>, Status>
+    {
+        let remote_ip = network::remote_octets(&request);
+        let request = request.into_inner();
+        // verify access token and get token claims, expired token is accepted to be refreshed
+        let token_claims = token::decode_token(&request.access_token, &signing_keys().public_keys(), false)
+            .map_err(|_| Status::internal(TOKEN_UNVERIFIED))?;
         let result = self.auth_db.read_access_token(token_claims.jti).await;
         let (refresh_token, access_token) = match result {
             Ok(token) => {
-                // check if remote ip match with stored login ip
+                // check if remote ip match with stored login ip and role IP allowlist
                 let ip_match = if token.ip == Vec::<u8>::new() {
                     true
                 } else {
                     token.ip == remote_ip
                 };
+                if !token_claims.ip_allowed(network::ip_from_octets(&remote_ip)) {
+                    return Err(Status::permission_denied(IP_NOT_ALLOWED));
+                }
                 // update token in database and generate new access token if refresh token match
+                // a rotated refresh token is replayed, revoke every token of the same login
+                let used = is_refresh_token_used(&self.auth_db, &request.refresh_token, token_claims.jti).await
+                    .map_err(handle_error)?;
+                if used {
+                    let tokens = self.auth_db.list_auth_token(&token.auth_token).await
+                        .map_err(handle_error)?;
+                    self.auth_db.delete_auth_token(&token.auth_token).await
+                        .map_err(handle_error)?;
+                    for token in &tokens {
+                        revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                            .map_err(handle_error)?;
+                        self.auth_db.delete_used_refresh_token(token.access_id).await
+                            .map_err(handle_error)?;
+                    }
+                    utility::security_event(
+                        REFRESH_TOKEN_REUSED,
+                        &format!("user {} family {} access id {} ip {:?}", token.user_id, token_claims.fid, token_claims.jti, remote_ip)
+                    );
+                    return Err(Status::permission_denied(REFRESH_TOKEN_REUSED));
+                }
                 if token.refresh_token == request.refresh_token && ip_match {
-                    let (refresh_token, _) = self.auth_db
-                        .update_access_token(token_claims.jti, Some(token.expire), None).await
-                        .map_err(|e| handle_error(e))?;
-                    let duration = (token_claims.exp - token_claims.iat) as i32;
-                    let access_token = token::generate_token(token_claims.jti, &token_claims.sub, duration, &access_key)
+                    // token of an API key is only refreshed while the key exists and not expired
+                    if let Some(key_id) = token.api_key_id {
+                        let key = self.auth_db.read_api_key(key_id).await
+                            .map_err(|_| Status::unauthenticated(API_KEY_INVALID))?;
+                        if api_key_expired(&key) {
+                            return Err(Status::unauthenticated(API_KEY_EXPIRED));
+                        }
+                        self.auth_db.update_api_key_used(key.id, Utc::now()).await
+                            .map_err(handle_error)?;
+                    }
+                    // claims are rebuilt before the refresh token is rotated so a failure does not
+                    // consume the refresh token
+                    let ip = network::ip_from_octets(&remote_ip);
+                    let (claims, duration) = self.refresh_claims(token.user_id, &token_claims, ip).await?;
+                    // refresh token is swapped only if it is still the presented one and stored as used in
+                    // the same transaction, so of two concurrent refreshes with the same token only one
+                    // succeed and the other is rejected
+                    let transaction = self.auth_db.begin().await
+                        .map_err(handle_error)?;
+                    let refresh_token = transaction
+                        .swap_refresh_token(token_claims.jti, &request.refresh_token, token.expire).await
+                        .map_err(handle_error)?
+                        .ok_or(Status::invalid_argument(TOKEN_MISMATCH))?;
+                    transaction.create_used_refresh_token(
+                        token_claims.jti, &refresh_token_hash(&request.refresh_token), token.expire
+                    ).await.map_err(handle_error)?;
+                    transaction.commit().await
+                        .map_err(handle_error)?;
+                    let access_token = token::generate_token(claims, duration)
                         .map_err(|_| Status::internal(GENERATE_TOKEN_ERR))?;
                     (refresh_token, access_token)
                 } else {
@@ -224,11 +742,16 @@ impl AuthService for AuthServer {
             Ok(tokens) => tokens,
             Err(e) => return Err(handle_error(e))
         };
-        match tokens.into_iter().next() {
+        match tokens.first() {
             Some(token) => {
                 if token.user_id.as_bytes().to_vec() == request.user_id {
                     self.auth_db.delete_auth_token(&request.auth_token).await
                         .map_err(|e| handle_error(e))?;
+                    // revoke access tokens so resource servers reject them before expired
+                    for token in &tokens {
+                        revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                            .map_err(handle_error)?;
+                    }
                 } else {
                     return Err(Status::invalid_argument(TOKEN_MISMATCH));
                 }
@@ -238,4 +761,94 @@ impl AuthService for AuthServer {
         Ok(Response::new(UserLogoutResponse { }))
     }
 
+    async fn stream_revoked_token(&self, _: Request<RevokedTokenRequest>)
+        -> Result<Response<Self::StreamRevokedTokenStream>, Status>
+    {
+        // subscribe before listing revoked tokens so revocation in between is not missed
+        let mut receiver = revoked_tokens().subscribe();
+        let tokens = revoked_tokens().list();
+        let (tx, rx) = mpsc::channel(REVOKE_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            for token in tokens {
+                if tx.send(Ok(revoked_schema(token))).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                tokio::select! {
+                    result = receiver.recv() => match result {
+                        Ok(token) => {
+                            if tx.send(Ok(revoked_schema(token))).await.is_err() {
+                                break;
+                            }
+                        },
+                        Err(broadcast::error::RecvError::Lagged(_)) => {
+                            // client must reconnect to receive the complete revoked token list
+                            tx.send(Err(Status::data_loss(REVOKE_STREAM_LAGGED))).await.ok();
+                            break;
+                        },
+                        Err(broadcast::error::RecvError::Closed) => break
+                    },
+                    _ = tx.closed() => break
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn introspect_token(&self, request: Request<TokenIntrospectRequest>)
+        -> Result<Response<TokenIntrospectResponse>, Status>
+    {
+        // an invalid, revoked, or foreign token is reported as inactive instead of an error
+        let response = self.introspect(request.into_inner()).await
+            .unwrap_or_default();
+        Ok(Response::new(response))
+    }
+
+    async fn list_signing_key(&self, _: Request<SigningKeyRequest>)
+        -> Result<Response<SigningKeySetResponse>, Status>
+    {
+        // only public part of signing keys are published, including next and retired keys
+        let keys = signing_keys().public_keys()
+            .into_iter()
+            .map(|k| signing_key_schema(k))
+            .collect();
+        Ok(Response::new(SigningKeySetResponse { keys }))
+    }
+
+}
+
+fn api_key_expired(key: &ApiKeySchema) -> bool
+{
+    key.expire.map(|expire| expire <= Utc::now()).unwrap_or(false)
+}
+
+fn signing_key_schema(key: PublicKey) -> SigningKeySchema
+{
+    SigningKeySchema {
+        kid: key.kid,
+        algorithm: key.algorithm.name().to_owned(),
+        modulus: key.modulus,
+        exponent: key.exponent,
+        public_key: key.public_key,
+        expire: key.expire.map(|e| e.timestamp_micros()).unwrap_or_default()
+    }
+}
+
+fn revoked_schema(token: RevokedToken) -> RevokedTokenSchema
+{
+    RevokedTokenSchema {
+        jti: token.jti,
+        expire: token.expire.timestamp_micros()
+    }
+}
+
+/// Check remote address against IP allowlist of a user role, a stored range which can not be
+/// parsed deny every address
+fn role_ip_allowed(role: &UserRoleSchema, ip: Option<IpAddr>) -> bool
+{
+    match network::parse_cidrs(&role.ip_allow) {
+        Ok(cidrs) => cidrs.is_empty() || ip.map(|ip| network::ip_allowed(ip, &cidrs)).unwrap_or(false),
+        Err(_) => false
+    }
 }
@@ -0,0 +1,84 @@
+use tonic::{Request, Response, Status};
+use rmcs_auth_db::Auth;
+use rmcs_auth_api::lockout::lockout_service_server::LockoutService;
+use rmcs_auth_api::lockout::{
+    LoginLockSchema, LoginLockListRequest, LoginLockListResponse, LoginUnlockRequest, LoginUnlockResponse
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::throttle::{login_throttle, LoginKind, LoginFailure};
+use crate::utility::handle_error;
+
+pub struct LockoutServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl LockoutServer {
+    pub fn new(auth_db: Auth) -> Self {
+        LockoutServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl LockoutService for LockoutServer {
+
+    async fn list_login_lock(&self, request: Request<LoginLockListRequest>)
+        -> Result<Response<LoginLockListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = login_throttle()
+            .list(&self.auth_db, request.kind.map(LoginKind::from), request.name.as_deref()).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(lock_schema).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(LoginLockListResponse { results }))
+    }
+
+    async fn unlock_login(&self, request: Request<LoginUnlockRequest>)
+        -> Result<Response<LoginUnlockResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = login_throttle().unlock(&self.auth_db, LoginKind::from(request.kind), &request.name).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(LoginUnlockResponse { }))
+    }
+
+}
+
+fn lock_schema(failure: LoginFailure) -> LoginLockSchema
+{
+    LoginLockSchema {
+        kind: failure.kind.into(),
+        name: failure.name,
+        ip: failure.ip,
+        failure: failure.count,
+        last_failure: failure.last_failure.timestamp_micros(),
+        locked_until: failure.locked_until.map(|t| t.timestamp_micros()).unwrap_or_default()
+    }
+}
+
+impl AuthValidator for LockoutServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
@@ -0,0 +1,215 @@
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_user::UserSchema;
+use rmcs_resource_db::DataValue;
+use rmcs_auth_api::mfa::mfa_service_server::MfaService;
+use rmcs_auth_api::mfa::{
+    MfaEnrollRequest, MfaEnrollResponse, MfaCodeRequest, MfaRecoveryResponse, MfaChangeResponse
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::mfa::{self, MFA_REQUIRED};
+use crate::utility::handle_error;
+use super::{MFA_NOT_ENROLLED, MFA_CODE_INVALID, MFA_KEY_INVALID, HASH_ERR};
+
+pub struct MfaServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl MfaServer {
+    pub fn new(auth_db: Auth) -> Self {
+        MfaServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl MfaService for MfaServer {
+
+    async fn enroll_mfa(&self, request: Request<MfaEnrollRequest>)
+        -> Result<Response<MfaEnrollResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let user = self.auth_db.read_user(user_id).await
+            .map_err(handle_error)?;
+        // new secret is pending until confirmed with an OTP, so current secret keep working
+        let mut state = UserMfa::read(&self.auth_db, user_id).await?;
+        let secret = mfa::generate_secret();
+        state.pending = Some(secret.clone());
+        state.save(&self.auth_db, user_id).await?;
+        let url = mfa::otpauth_url(&secret, &user.name);
+        Ok(Response::new(MfaEnrollResponse { secret, url }))
+    }
+
+    async fn confirm_mfa(&self, request: Request<MfaCodeRequest>)
+        -> Result<Response<MfaRecoveryResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let request = request.into_inner();
+        let mut state = UserMfa::read(&self.auth_db, user_id).await?;
+        let secret = state.pending.take().ok_or(Status::failed_precondition(MFA_NOT_ENROLLED))?;
+        // replacing an enrolled secret requires a code of the current secret or a recovery code
+        if state.secret.is_some() && !state.verify(&self.auth_db, user_id, &request.current_code).await? {
+            return Err(Status::invalid_argument(MFA_CODE_INVALID));
+        }
+        let step = mfa::verify_code(&secret, &request.code)
+            .ok_or(Status::invalid_argument(MFA_CODE_INVALID))?;
+        let (recovery_codes, hashes) = mfa::generate_recovery_codes()
+            .map_err(|_| Status::internal(HASH_ERR))?;
+        state.secret = Some(secret);
+        state.recovery = hashes;
+        state.save(&self.auth_db, user_id).await?;
+        // the confirmation code must not be usable for a login, the step may already be taken
+        // by the current secret code which is fine as well
+        self.auth_db.update_user_mfa_step(user_id, step as i64).await
+            .map_err(handle_error)?;
+        Ok(Response::new(MfaRecoveryResponse { recovery_codes }))
+    }
+
+    async fn regenerate_recovery_code(&self, request: Request<MfaCodeRequest>)
+        -> Result<Response<MfaRecoveryResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let request = request.into_inner();
+        let mut state = UserMfa::read(&self.auth_db, user_id).await?;
+        let secret = state.secret.clone().ok_or(Status::failed_precondition(MFA_NOT_ENROLLED))?;
+        let step = mfa::verify_code(&secret, &request.code)
+            .ok_or(Status::invalid_argument(MFA_CODE_INVALID))?;
+        if !self.auth_db.update_user_mfa_step(user_id, step as i64).await.map_err(handle_error)? {
+            return Err(Status::invalid_argument(MFA_CODE_INVALID));
+        }
+        let (recovery_codes, hashes) = mfa::generate_recovery_codes()
+            .map_err(|_| Status::internal(HASH_ERR))?;
+        state.recovery = hashes;
+        state.save(&self.auth_db, user_id).await?;
+        Ok(Response::new(MfaRecoveryResponse { recovery_codes }))
+    }
+
+    async fn disable_mfa(&self, request: Request<MfaCodeRequest>)
+        -> Result<Response<MfaChangeResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let request = request.into_inner();
+        let mut state = UserMfa::read(&self.auth_db, user_id).await?;
+        if state.secret.is_none() {
+            return Err(Status::failed_precondition(MFA_NOT_ENROLLED));
+        }
+        if !state.verify(&self.auth_db, user_id, &request.code).await? {
+            return Err(Status::invalid_argument(MFA_CODE_INVALID));
+        }
+        self.auth_db.delete_user_mfa(user_id).await
+            .map_err(handle_error)?;
+        Ok(Response::new(MfaChangeResponse { }))
+    }
+
+}
+
+/// MFA data of a user, secrets are stored encrypted in the user MFA table
+#[derive(Debug, Default)]
+pub(crate) struct UserMfa {
+    pub secret: Option<String>,
+    pub pending: Option<String>,
+    pub recovery: String,
+    pub required: bool
+}
+
+impl UserMfa {
+
+    pub(crate) async fn read(auth_db: &Auth, user_id: Uuid) -> Result<Self, Status>
+    {
+        let mut state = Self::default();
+        let stored = auth_db.read_user_mfa(user_id).await
+            .map_err(handle_error)?;
+        if let Some(stored) = stored {
+            // a secret which can not be decrypted must not be treated as not enrolled
+            let decrypt = |data: Option<Vec<u8>>| match data {
+                Some(data) => mfa::decrypt_secret(&data)
+                    .map(Some)
+                    .ok_or(Status::failed_precondition(MFA_KEY_INVALID)),
+                None => Ok(None)
+            };
+            state.secret = decrypt(stored.secret)?;
+            state.pending = decrypt(stored.pending)?;
+            state.recovery = stored.recovery;
+        }
+        let profiles = auth_db.list_user_profile_by_user(user_id).await
+            .map_err(handle_error)?;
+        state.required = profiles.iter()
+            .any(|p| p.name == MFA_REQUIRED && matches!(p.value, DataValue::Bool(true)));
+        Ok(state)
+    }
+
+    /// Read MFA data of a user, and mark it required when one of the user roles has an MFA required profile
+    pub(crate) async fn read_user(auth_db: &Auth, user: &UserSchema) -> Result<Self, Status>
+    {
+        let mut state = Self::read(auth_db, user.id).await?;
+        for user_role in &user.roles {
+            if state.required {
+                break;
+            }
+            let roles = auth_db.list_role_by_api(user_role.api_id).await
+                .map_err(handle_error)?;
+            for role in roles.into_iter().filter(|r| r.name == user_role.role) {
+                let profiles = auth_db.list_role_profile_by_role(role.id).await
+                    .map_err(handle_error)?;
+                state.required = state.required || profiles.iter().any(|p| p.name == MFA_REQUIRED);
+            }
+        }
+        Ok(state)
+    }
+
+    pub(crate) async fn save(&self, auth_db: &Auth, user_id: Uuid) -> Result<(), Status>
+    {
+        let encrypt = |secret: &Option<String>| match secret {
+            Some(secret) => mfa::encrypt_secret(secret)
+                .map(Some)
+                .ok_or(Status::failed_precondition(MFA_KEY_INVALID)),
+            None => Ok(None)
+        };
+        let secret = encrypt(&self.secret)?;
+        let pending = encrypt(&self.pending)?;
+        auth_db.update_user_mfa(user_id, secret.as_deref(), pending.as_deref(), &self.recovery).await
+            .map_err(handle_error)
+    }
+
+    /// Verify an OTP or a recovery code. An OTP time step is accepted only once and a used
+    /// recovery code is removed.
+    pub(crate) async fn verify(&mut self, auth_db: &Auth, user_id: Uuid, code: &str) -> Result<bool, Status>
+    {
+        if let Some(step) = self.secret.as_deref().and_then(|s| mfa::verify_code(s, code)) {
+            return auth_db.update_user_mfa_step(user_id, step as i64).await
+                .map_err(handle_error);
+        }
+        if let Some(remaining) = mfa::use_recovery_code(&self.recovery, code) {
+            self.recovery = remaining;
+            self.save(auth_db, user_id).await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+}
+
+impl AuthValidator for MfaServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
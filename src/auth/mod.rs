@@ -4,13 +4,42 @@ pub mod user;
 pub mod profile;
 pub mod token;
 pub mod auth;
+pub mod policy;
+pub mod mfa;
+pub mod lockout;
+pub mod session;
+pub mod tenant;
+pub mod service_account;
 
 // operation error message
 const TOKEN_NOT_FOUND: &str = "requested token not found";
-const KEY_IMPORT_ERR: &str = "key import error";
 const DECRYPT_ERR: &str = "decrypt password error";
-const ENCRYPT_ERR: &str = "encrypt key error";
 const PASSWORD_MISMATCH: &str = "password does not match";
 const GENERATE_TOKEN_ERR: &str = "error generate token";
 const TOKEN_MISMATCH: &str = "token is not match";
 const TOKEN_UNVERIFIED: &str = "token unverified";
+const REFRESH_TOKEN_REUSED: &str = "refresh token reused, all tokens of the login are revoked";
+const REVOKE_STREAM_LAGGED: &str = "revoked token notifications are lost, reconnect to reload";
+const MFA_NOT_ENROLLED: &str = "multi factor authentication is not enrolled";
+const MFA_CODE_INVALID: &str = "invalid one time password or recovery code";
+const MFA_CHALLENGE_INVALID: &str = "login challenge is invalid or expired";
+const MFA_KEY_INVALID: &str = "MFA encryption key is not configured or does not match stored secret";
+const HASH_ERR: &str = "error hashing password or secret";
+const CURRENT_PASSWORD_EMPTY: &str = "current password is required to change own password";
+const VERIFY_TARGET_EMPTY: &str = "user has no email or phone to verify";
+const VERIFY_KIND_INVALID: &str = "only email or phone verification is supported";
+const VERIFY_TOKEN_INVALID: &str = "verification token is invalid or expired";
+const NOTIFY_ERR: &str = "failed to send notification";
+const SESSION_NOT_FOUND: &str = "requested session not found";
+const IP_NOT_ALLOWED: &str = "remote address is not allowed for the role";
+const CIDR_INVALID: &str = "invalid CIDR range";
+const ROLE_NOT_ASSIGNED: &str = "requested roles are not assigned to the user";
+const TENANT_MISMATCH: &str = "user tenant does not own any api of the user roles";
+const ROLE_PARENT_INVALID: &str = "parent role must be a role of the same api which does not inherit from the role";
+const API_KEY_INVALID: &str = "API key is invalid";
+const API_KEY_EXPIRED: &str = "API key is expired";
+const SERVICE_LOGIN_DENIED: &str = "service account can only login using API key";
+const SERVICE_ACCOUNT_REQUIRED: &str = "API key can only be created for a service account";
+const LOGIN_LOCKED: &str = "login is locked after too many failures until";
+const POLICY_INVALID: &str = "invalid policy document";
+const POLICY_PASSWORD_EMPTY: &str = "password is required to create api";
@@ -0,0 +1,282 @@
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::utility::generate_access_key;
+use rmcs_auth_api::policy::policy_service_server::PolicyService;
+use rmcs_auth_api::policy::{
+    PolicySyncRequest, PolicySyncResponse, PolicyChange, PolicyAction
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind, ParentSchema, role_ancestors};
+use crate::utility::policy::{Policy, PolicyApi, PolicyFormat};
+use crate::utility::handle_error;
+use super::role::normalize_cidrs;
+use super::{POLICY_INVALID, POLICY_PASSWORD_EMPTY};
+
+const KIND_API: &str = "api";
+const KIND_PROCEDURE: &str = "procedure";
+const KIND_ROLE: &str = "role";
+const KIND_ACCESS: &str = "access";
+const KIND_PARENT: &str = "parent";
+
+pub struct PolicyServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl PolicyServer {
+    pub fn new(auth_db: Auth) -> Self {
+        PolicyServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PolicyService for PolicyServer {
+
+    async fn sync_policy(&self, request: Request<PolicySyncRequest>)
+        -> Result<Response<PolicySyncResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let policy = Policy::decode(&request.content, PolicyFormat::from(request.format))
+            .map_err(|e| Status::invalid_argument(format!("{}: {}", POLICY_INVALID, e)))?;
+        // the whole policy is applied in a single transaction so a failed sync leaves the database
+        // untouched, dropping the transaction without commit rolls it back
+        let transaction = self.auth_db.begin().await
+            .map_err(handle_error)?;
+        let mut sync = PolicySync::new(&transaction, request.dry_run);
+        for api in policy.apis {
+            sync.apply_api(api).await?;
+        }
+        let PolicySync { dry_run, changes, .. } = sync;
+        if !dry_run {
+            transaction.commit().await
+                .map_err(handle_error)?;
+        }
+        Ok(Response::new(PolicySyncResponse { changes }))
+    }
+
+}
+
+/// Reconcile APIs, procedures, roles and role accesses with a policy.
+/// APIs, procedures and roles missing from the policy are kept, but accesses of
+/// the listed roles are made equal to the policy.
+struct PolicySync<'a> {
+    auth_db: &'a Auth,
+    dry_run: bool,
+    changes: Vec<PolicyChange>
+}
+
+impl<'a> PolicySync<'a> {
+
+    fn new(auth_db: &'a Auth, dry_run: bool) -> Self {
+        Self {
+            auth_db,
+            dry_run,
+            changes: Vec::new()
+        }
+    }
+
+    fn change(&mut self, kind: &str, action: PolicyAction, name: String) {
+        self.changes.push(PolicyChange {
+            kind: kind.to_owned(),
+            action: action as i32,
+            name
+        });
+    }
+
+    async fn apply_api(&mut self, api: PolicyApi) -> Result<(), Status>
+    {
+        let (api_id, created) = match self.auth_db.read_api_by_name(&api.name).await {
+            Ok(current) => {
+                if current.address != api.address || current.category != api.category || current.description != api.description {
+                    self.change(KIND_API, PolicyAction::Update, api.name.clone());
+                    if !self.dry_run {
+                        self.auth_db.update_api(
+                            current.id, None, Some(&api.address), Some(&api.category), Some(&api.description), None, None
+                        ).await.map_err(handle_error)?;
+                    }
+                }
+                (current.id, false)
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                let password = api.password.as_deref()
+                    .ok_or(Status::invalid_argument(format!("{} {}", POLICY_PASSWORD_EMPTY, api.name)))?;
+                let id = api.id.unwrap_or(Uuid::new_v4());
+                self.change(KIND_API, PolicyAction::Create, api.name.clone());
+                if !self.dry_run {
+                    self.auth_db.create_api(
+                        id, &api.name, &api.address, &api.category, &api.description, password, &generate_access_key()
+                    ).await.map_err(handle_error)?;
+                }
+                (id, true)
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+
+        // procedure and role of an API which is not created yet on dry run are always new
+        let procedures = if created && self.dry_run { Vec::new() } else {
+            self.auth_db.list_procedure_by_api(api_id).await.map_err(handle_error)?
+        };
+        let mut procedure_ids: Vec<(String, Uuid)> = procedures.into_iter().map(|p| (p.name, p.id)).collect();
+        for name in api.accesses.keys() {
+            if procedure_ids.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            let id = Uuid::new_v4();
+            self.change(KIND_PROCEDURE, PolicyAction::Create, format!("{}/{}", api.name, name));
+            if !self.dry_run {
+                self.auth_db.create_procedure(id, api_id, name, "").await
+                    .map_err(handle_error)?;
+            }
+            procedure_ids.push((name.to_owned(), id));
+        }
+
+        let roles = if created && self.dry_run { Vec::new() } else {
+            self.auth_db.list_role_by_api(api_id).await.map_err(handle_error)?
+        };
+        // role ids and current parent ids by role name, used to set parents after every role exist
+        let mut role_ids: Vec<(String, Uuid, Option<Uuid>)> = Vec::new();
+        for role in &api.roles {
+            let label = format!("{}/{}", api.name, role.name);
+            let ip_allow = normalize_cidrs(&role.ip_allow)?;
+            let (role_id, current_procedures) = match roles.iter().find(|r| r.name == role.name) {
+                Some(current) => {
+                    if current.multi != role.multi || current.ip_lock != role.ip_lock
+                        || current.access_duration != role.access_duration || current.refresh_duration != role.refresh_duration
+                        || current.ip_allow != ip_allow
+                    {
+                        self.change(KIND_ROLE, PolicyAction::Update, label.clone());
+                        if !self.dry_run {
+                            self.auth_db.update_role(
+                                current.id,
+                                None,
+                                Some(role.multi),
+                                Some(role.ip_lock),
+                                Some(role.access_duration),
+                                Some(role.refresh_duration)
+                            ).await.map_err(handle_error)?;
+                            self.auth_db.update_role_ip_allow(current.id, &ip_allow).await
+                                .map_err(handle_error)?;
+                        }
+                    }
+                    role_ids.push((role.name.clone(), current.id, current.parent_id));
+                    (current.id, current.procedures.clone())
+                },
+                None => {
+                    let id = Uuid::new_v4();
+                    self.change(KIND_ROLE, PolicyAction::Create, label.clone());
+                    if !self.dry_run {
+                        self.auth_db.create_role(
+                            id, api_id, &role.name, role.multi, role.ip_lock, role.access_duration, role.refresh_duration
+                        ).await.map_err(handle_error)?;
+                        if !ip_allow.is_empty() {
+                            self.auth_db.update_role_ip_allow(id, &ip_allow).await
+                                .map_err(handle_error)?;
+                        }
+                    }
+                    role_ids.push((role.name.clone(), id, None));
+                    (id, Vec::new())
+                }
+            };
+            // compare procedures accessed by the role with the policy
+            let desired: Vec<(String, Uuid)> = procedure_ids.iter()
+                .filter(|(name, _)| api.accesses.get(name).map(|r| r.contains(&role.name)).unwrap_or(false))
+                .cloned()
+                .collect();
+            for (name, procedure_id) in &desired {
+                if current_procedures.contains(procedure_id) {
+                    continue;
+                }
+                self.change(KIND_ACCESS, PolicyAction::Create, format!("{}:{}", label, name));
+                if !self.dry_run {
+                    self.auth_db.add_role_access(role_id, *procedure_id).await
+                        .map_err(handle_error)?;
+                }
+            }
+            for procedure_id in &current_procedures {
+                if desired.iter().any(|(_, id)| id == procedure_id) {
+                    continue;
+                }
+                let name = procedure_ids.iter()
+                    .find(|(_, id)| id == procedure_id)
+                    .map(|(n, _)| n.to_owned())
+                    .unwrap_or(procedure_id.to_string());
+                self.change(KIND_ACCESS, PolicyAction::Remove, format!("{}:{}", label, name));
+                if !self.dry_run {
+                    self.auth_db.remove_role_access(role_id, *procedure_id).await
+                        .map_err(handle_error)?;
+                }
+            }
+        }
+
+        // parents of listed roles are made equal to the policy, a parent is resolved by role name from
+        // the policy or from existing roles of the API. Unknown parent is rejected instead of removed
+        // and the resulting parents are checked for cycles including roles missing from the policy
+        let mut parents: Vec<ParentSchema> = roles.iter()
+            .filter(|r| !api.roles.iter().any(|e| e.name == r.name))
+            .filter_map(|r| {
+                let parent = roles.iter().find(|p| Some(p.id) == r.parent_id)?;
+                Some(ParentSchema { role: r.name.clone(), parent: parent.name.clone() })
+            })
+            .collect();
+        parents.extend(api.roles.iter()
+            .filter_map(|r| r.parent.as_ref().map(|p| ParentSchema { role: r.name.clone(), parent: p.clone() })));
+        for role in &api.roles {
+            let Some(&(_, role_id, current_parent)) = role_ids.iter().find(|(n, _, _)| n == &role.name) else {
+                continue;
+            };
+            let parent_id = match &role.parent {
+                Some(p) => {
+                    let id = role_ids.iter().find(|(n, _, _)| n == p).map(|&(_, id, _)| id)
+                        .or(roles.iter().find(|r| &r.name == p).map(|r| r.id))
+                        .ok_or(Status::invalid_argument(
+                            format!("{}: parent {} of role {} not found in api {}", POLICY_INVALID, p, role.name, api.name)
+                        ))?;
+                    if role_ancestors(p, &parents).contains(&role.name) {
+                        return Err(Status::invalid_argument(
+                            format!("{}: role {} inherits from itself in api {}", POLICY_INVALID, role.name, api.name)
+                        ));
+                    }
+                    Some(id)
+                },
+                None => None
+            };
+            if parent_id == current_parent {
+                continue;
+            }
+            let action = match (current_parent, parent_id) {
+                (None, _) => PolicyAction::Create,
+                (_, None) => PolicyAction::Remove,
+                _ => PolicyAction::Update
+            };
+            let name = role.parent.clone().unwrap_or_default();
+            self.change(KIND_PARENT, action, format!("{}/{}:{}", api.name, role.name, name));
+            if !self.dry_run {
+                self.auth_db.update_role_parent(role_id, parent_id).await
+                    .map_err(handle_error)?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl AuthValidator for PolicyServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
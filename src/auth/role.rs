@@ -3,11 +3,15 @@ use uuid::Uuid;
 use rmcs_auth_db::Auth;
 use rmcs_auth_api::role::role_service_server::RoleService;
 use rmcs_auth_api::role::{
-    RoleSchema, RoleId, RoleIds, RoleName, ApiId, UserId, RoleOption, RoleUpdate, RoleAccess,
+    RoleSchema, RoleId, RoleIds, RoleName, ApiId, UserId, RoleOption, RoleUpdate, RoleAccess, RoleIpAllow,
+    RoleParent, RoleProcedureResponse, RoleScope, ScopeSchema, ScopeListResponse,
     RoleReadResponse, RoleListResponse, RoleCreateResponse, RoleChangeResponse
 };
-use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::validator::{AuthValidator, ValidatorKind, ParentSchema, role_ancestors, effective_roles};
 use crate::utility::handle_error;
+use crate::utility::network::parse_cidrs;
+use crate::utility::scope::ScopeKind;
+use super::{CIDR_INVALID, ROLE_PARENT_INVALID};
 
 pub struct RoleServer {
     pub auth_db: Auth,
@@ -23,6 +27,29 @@ impl RoleServer {
     }
 }
 
+/// Normalize CIDR ranges of a role IP allowlist, an empty list allow every address. Invalid
+/// ranges are rejected here since a stored range which can not be parsed deny every address.
+pub(crate) fn normalize_cidrs(cidrs: &[String]) -> Result<Vec<String>, Status>
+{
+    match parse_cidrs(cidrs) {
+        Ok(value) => Ok(value.iter().map(|net| net.to_string()).collect()),
+        Err(e) => Err(Status::invalid_argument(format!("{}: {}", CIDR_INVALID, e)))
+    }
+}
+
+/// Parent of every role of an api which has a parent, using role names
+pub(crate) async fn role_parents(auth_db: &Auth, api_id: Uuid) -> Result<Vec<ParentSchema>, sqlx::Error>
+{
+    let roles = auth_db.list_role_by_api(api_id).await?;
+    let parents = roles.iter()
+        .filter_map(|r| {
+            let parent = roles.iter().find(|p| Some(p.id) == r.parent_id)?;
+            Some(ParentSchema { role: r.name.clone(), parent: parent.name.clone() })
+        })
+        .collect();
+    Ok(parents)
+}
+
 #[tonic::async_trait]
 impl RoleService for RoleServer {
 
@@ -30,8 +57,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.read_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -43,8 +71,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_role_by_name(
+        let result = auth_db.read_role_by_name(
             Uuid::from_slice(&request.api_id).unwrap_or_default(),
             &request.name
         ).await;
@@ -59,8 +88,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_role_by_ids(
+        let result = auth_db.list_role_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -74,8 +104,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_role_by_api(Uuid::from_slice(&request.api_id).unwrap_or_default()).await;
+        let result = auth_db.list_role_by_api(Uuid::from_slice(&request.api_id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -87,8 +118,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_role_by_user(Uuid::from_slice(&request.user_id).unwrap_or_default()).await;
+        let result = auth_db.list_role_by_user(Uuid::from_slice(&request.user_id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -100,8 +132,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_role_by_name(&request.name).await;
+        let result = auth_db.list_role_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -113,8 +146,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_role_option(
+        let result = auth_db.list_role_option(
             request.api_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.user_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
@@ -130,8 +164,10 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleCreateResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.create_role(
+        let cidrs = normalize_cidrs(&request.ip_allow)?;
+        let result = auth_db.create_role(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.api_id).unwrap_or_default(),
             &request.name,
@@ -144,6 +180,10 @@ impl RoleService for RoleServer {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
         };
+        if !cidrs.is_empty() {
+            auth_db.update_role_ip_allow(id, &cidrs).await
+                .map_err(handle_error)?;
+        }
         Ok(Response::new(RoleCreateResponse { id: id.as_bytes().to_vec() }))
     }
 
@@ -151,8 +191,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.update_role(
+        let result = auth_db.update_role(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.multi,
@@ -171,8 +212,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.delete_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.delete_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -184,8 +226,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.add_role_access(
+        let result = auth_db.add_role_access(
             Uuid::from_slice(&request.id).unwrap_or_default(), 
             Uuid::from_slice(&request.procedure_id).unwrap_or_default()
         ).await;
@@ -200,8 +243,9 @@ impl RoleService for RoleServer {
         -> Result<Response<RoleChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.remove_role_access(
+        let result = auth_db.remove_role_access(
             Uuid::from_slice(&request.id).unwrap_or_default(), 
             Uuid::from_slice(&request.procedure_id).unwrap_or_default()
         ).await;
@@ -212,6 +256,126 @@ impl RoleService for RoleServer {
         Ok(Response::new(RoleChangeResponse { }))
     }
 
+    async fn list_role_scope(&self, request: Request<RoleId>)
+        -> Result<Response<ScopeListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.list_role_scope(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| ScopeSchema {
+                kind: ScopeKind::from(e.kind.as_str()).into(),
+                resource_id: e.resource_id.as_bytes().to_vec()
+            }).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ScopeListResponse { results }))
+    }
+
+    async fn add_role_scope(&self, request: Request<RoleScope>)
+        -> Result<Response<RoleChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.add_role_scope(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            ScopeKind::from(request.kind).name(),
+            Uuid::from_slice(&request.resource_id).unwrap_or_default()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(RoleChangeResponse { }))
+    }
+
+    async fn remove_role_scope(&self, request: Request<RoleScope>)
+        -> Result<Response<RoleChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.remove_role_scope(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            ScopeKind::from(request.kind).name(),
+            Uuid::from_slice(&request.resource_id).unwrap_or_default()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(RoleChangeResponse { }))
+    }
+
+    async fn set_role_ip_allow(&self, request: Request<RoleIpAllow>)
+        -> Result<Response<RoleChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let cidrs = normalize_cidrs(&request.cidrs)?;
+        let result = auth_db.update_role_ip_allow(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            &cidrs
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(RoleChangeResponse { }))
+    }
+
+    async fn set_role_parent(&self, request: Request<RoleParent>)
+        -> Result<Response<RoleChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let id = Uuid::from_slice(&request.id).unwrap_or_default();
+        let parent_id = request.parent_id.map(|p| Uuid::from_slice(&p).unwrap_or_default());
+        // parent must be another role of the same api and must not inherit from the role
+        if let Some(parent_id) = parent_id {
+            let role = auth_db.read_role(id).await.map_err(|e| handle_error(e))?;
+            let parent = auth_db.read_role(parent_id).await.map_err(|e| handle_error(e))?;
+            if parent.api_id != role.api_id {
+                return Err(Status::invalid_argument(ROLE_PARENT_INVALID));
+            }
+            let parents = role_parents(&auth_db, role.api_id).await.map_err(|e| handle_error(e))?;
+            if role_ancestors(&parent.name, &parents).contains(&role.name) {
+                return Err(Status::invalid_argument(ROLE_PARENT_INVALID));
+            }
+        }
+        let result = auth_db.update_role_parent(id, parent_id).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(RoleChangeResponse { }))
+    }
+
+    async fn list_role_procedure(&self, request: Request<RoleId>)
+        -> Result<Response<RoleProcedureResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let role = auth_db.read_role(Uuid::from_slice(&request.id).unwrap_or_default()).await
+            .map_err(|e| handle_error(e))?;
+        let parents = role_parents(&auth_db, role.api_id).await
+            .map_err(|e| handle_error(e))?;
+        let result = auth_db.list_procedure_by_api(role.api_id).await;
+        let procedures = match result {
+            Ok(value) => value.into_iter()
+                .filter(|p| effective_roles(&p.roles, &parents).contains(&role.name))
+                .map(|p| p.name)
+                .collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(RoleProcedureResponse { procedures }))
+    }
+
 }
 
 impl AuthValidator for RoleServer {
@@ -0,0 +1,223 @@
+use tonic::{Request, Response, Status};
+use chrono::{Utc, TimeZone};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_key::ApiKeySchema as KeySchema;
+use rmcs_auth_api::service_account::service_account_service_server::ServiceAccountService;
+use rmcs_auth_api::service_account::{
+    ServiceAccountSchema, ServiceAccountRequest, ApiKeySchema, ApiKeyId, ApiKeyCreate, ApiKeyRotate,
+    ServiceAccountId, ServiceAccountCreateResponse, ServiceAccountListResponse,
+    ApiKeyReadResponse, ApiKeyListResponse, ApiKeyCreateResponse, ApiKeyChangeResponse
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::api_key::{generate_api_key, hash_api_key};
+use crate::utility::revoke::revoked_tokens;
+use crate::utility::handle_error;
+use super::{SERVICE_ACCOUNT_REQUIRED, ROLE_NOT_ASSIGNED};
+
+/// Service accounts are users which can not login with password, machines such as gateways
+/// and scripts login using API keys of the account. Roles of a service account are assigned
+/// using user service.
+pub struct ServiceAccountServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl ServiceAccountServer {
+    pub fn new(auth_db: Auth) -> Self {
+        ServiceAccountServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ServiceAccountService for ServiceAccountServer {
+
+    async fn create_service_account(&self, request: Request<ServiceAccountSchema>)
+        -> Result<Response<ServiceAccountCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.create_service_account(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            &request.name,
+            &request.description
+        ).await;
+        let id = match result {
+            Ok(value) => value,
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ServiceAccountCreateResponse { id: id.as_bytes().to_vec() }))
+    }
+
+    async fn list_service_account(&self, request: Request<ServiceAccountRequest>)
+        -> Result<Response<ServiceAccountListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let result = auth_db.list_service_account().await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ServiceAccountListResponse { results }))
+    }
+
+    async fn read_api_key(&self, request: Request<ApiKeyId>)
+        -> Result<Response<ApiKeyReadResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.read_api_key(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = match result {
+            Ok(value) => Some(api_key_schema(value)),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ApiKeyReadResponse { result }))
+    }
+
+    async fn list_api_key(&self, request: Request<ServiceAccountId>)
+        -> Result<Response<ApiKeyListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.list_api_key_by_user(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| api_key_schema(e)).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ApiKeyListResponse { results }))
+    }
+
+    async fn create_api_key(&self, request: Request<ApiKeyCreate>)
+        -> Result<Response<ApiKeyCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let user_id = Uuid::from_slice(&request.user_id).unwrap_or_default();
+        check_account(&auth_db, user_id, &request.roles).await?;
+        // the key is only returned here, only its prefix and hash are stored
+        let (prefix, key) = generate_api_key();
+        let expire = if request.expire == 0 { None } else { Some(Utc.timestamp_nanos(request.expire * 1000)) };
+        let result = auth_db.create_api_key(
+            user_id,
+            &request.name,
+            &prefix,
+            &hash_api_key(&key),
+            &request.roles,
+            expire
+        ).await;
+        let id = match result {
+            Ok(value) => value,
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ApiKeyCreateResponse { id: id.as_bytes().to_vec(), prefix, key }))
+    }
+
+    async fn rotate_api_key(&self, request: Request<ApiKeyRotate>)
+        -> Result<Response<ApiKeyCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let id = Uuid::from_slice(&request.id).unwrap_or_default();
+        auth_db.read_api_key(id).await
+            .map_err(|e| handle_error(e))?;
+        // replace the key while keeping its id, name, and roles
+        let (prefix, key) = generate_api_key();
+        let expire = if request.expire == 0 { None } else { Some(Utc.timestamp_nanos(request.expire * 1000)) };
+        let result = auth_db.update_api_key_hash(id, &prefix, &hash_api_key(&key), expire).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        revoke_tokens(&auth_db, id).await?;
+        Ok(Response::new(ApiKeyCreateResponse { id: id.as_bytes().to_vec(), prefix, key }))
+    }
+
+    async fn revoke_api_key(&self, request: Request<ApiKeyId>)
+        -> Result<Response<ApiKeyChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let id = Uuid::from_slice(&request.id).unwrap_or_default();
+        auth_db.read_api_key(id).await
+            .map_err(|e| handle_error(e))?;
+        // tokens are listed before the key is deleted in case the key reference is cleared
+        revoke_tokens(&auth_db, id).await?;
+        let result = auth_db.delete_api_key(id).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ApiKeyChangeResponse { }))
+    }
+
+}
+
+/// Check the user is a service account and requested key roles are assigned to the account
+async fn check_account(auth_db: &Auth, user_id: Uuid, roles: &[String]) -> Result<(), Status>
+{
+    let user = auth_db.read_user(user_id).await
+        .map_err(|e| handle_error(e))?;
+    if !user.service {
+        return Err(Status::invalid_argument(SERVICE_ACCOUNT_REQUIRED));
+    }
+    if roles.iter().any(|r| !user.roles.iter().any(|e| &e.role == r)) {
+        return Err(Status::invalid_argument(ROLE_NOT_ASSIGNED));
+    }
+    Ok(())
+}
+
+/// Revoke tokens exchanged from a revoked or rotated key, tokens of other keys of the same
+/// service account keep working
+async fn revoke_tokens(auth_db: &Auth, key_id: Uuid) -> Result<(), Status>
+{
+    let tokens = auth_db.list_token_by_api_key(key_id).await
+        .map_err(|e| handle_error(e))?;
+    auth_db.delete_token_by_api_key(key_id).await
+        .map_err(|e| handle_error(e))?;
+    for token in tokens {
+        revoked_tokens().revoke(auth_db, token.access_id, token.expire).await
+            .map_err(handle_error)?;
+    }
+    Ok(())
+}
+
+fn api_key_schema(key: KeySchema) -> ApiKeySchema
+{
+    ApiKeySchema {
+        id: key.id.as_bytes().to_vec(),
+        user_id: key.user_id.as_bytes().to_vec(),
+        name: key.name,
+        prefix: key.prefix,
+        roles: key.roles,
+        expire: key.expire.map(|t| t.timestamp_micros()).unwrap_or_default(),
+        last_used: key.last_used.map(|t| t.timestamp_micros()).unwrap_or_default(),
+        created: key.created.timestamp_micros()
+    }
+}
+
+impl AuthValidator for ServiceAccountServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
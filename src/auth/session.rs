@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use tonic::{Request, Response, Status, Extensions};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_token::TokenSchema;
+use rmcs_auth_api::session::session_service_server::SessionService;
+use rmcs_auth_api::session::{
+    SessionSchema, SessionUserId, SessionRevokeRequest, SessionListResponse, SessionChangeResponse
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::token::family_id;
+use crate::utility::revoke::revoked_tokens;
+use crate::utility::handle_error;
+use super::SESSION_NOT_FOUND;
+
+pub struct SessionServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl SessionServer {
+    pub fn new(auth_db: Auth) -> Self {
+        SessionServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+
+    /// Group tokens of a user by auth token, a session is one login identified by token family id
+    async fn sessions(&self, user_id: Uuid) -> Result<BTreeMap<String, Vec<TokenSchema>>, Status>
+    {
+        let tokens = self.auth_db.list_token_by_user(user_id).await
+            .map_err(|e| handle_error(e))?;
+        let mut sessions: BTreeMap<String, Vec<TokenSchema>> = BTreeMap::new();
+        for token in tokens {
+            sessions.entry(family_id(&token.auth_token)).or_default().push(token);
+        }
+        Ok(sessions)
+    }
+
+    async fn revoke(&self, tokens: &[TokenSchema]) -> Result<(), Status>
+    {
+        if let Some(token) = tokens.first() {
+            self.auth_db.delete_auth_token(&token.auth_token).await
+                .map_err(|e| handle_error(e))?;
+        }
+        for token in tokens {
+            revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                .map_err(handle_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Session id of the auth token used to call the service
+fn current_session(extension: &Extensions) -> String
+{
+    extension.get::<String>()
+        .map(|token| family_id(token))
+        .unwrap_or_default()
+}
+
+#[tonic::async_trait]
+impl SessionService for SessionServer {
+
+    async fn list_session(&self, request: Request<SessionUserId>)
+        -> Result<Response<SessionListResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let current = current_session(request.extensions());
+        let results = self.sessions(user_id).await?
+            .into_iter()
+            .map(|(id, tokens)| {
+                let expire: DateTime<Utc> = tokens.iter().map(|t| t.expire).max().unwrap_or_default();
+                // login time is the creation time of the first token of the session, refresh keeps it
+                let created: DateTime<Utc> = tokens.iter().map(|t| t.created).min().unwrap_or_default();
+                let ip = tokens.first().map(|t| t.ip.clone()).unwrap_or_default();
+                SessionSchema {
+                    current: id == current,
+                    session_id: id,
+                    ip,
+                    created: created.timestamp_micros(),
+                    expire: expire.timestamp_micros()
+                }
+            })
+            .collect();
+        Ok(Response::new(SessionListResponse { results }))
+    }
+
+    async fn revoke_session(&self, request: Request<SessionRevokeRequest>)
+        -> Result<Response<SessionChangeResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let request = request.into_inner();
+        let sessions = self.sessions(user_id).await?;
+        let tokens = sessions.get(&request.session_id)
+            .ok_or(Status::not_found(SESSION_NOT_FOUND))?;
+        self.revoke(tokens).await?;
+        Ok(Response::new(SessionChangeResponse { }))
+    }
+
+    async fn revoke_other_session(&self, request: Request<SessionUserId>)
+        -> Result<Response<SessionChangeResponse>, Status>
+    {
+        let user_id = Uuid::from_slice(&request.get_ref().user_id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(user_id)).await?;
+        let current = current_session(request.extensions());
+        for (id, tokens) in self.sessions(user_id).await? {
+            if id != current {
+                self.revoke(&tokens).await?;
+            }
+        }
+        Ok(Response::new(SessionChangeResponse { }))
+    }
+
+}
+
+impl AuthValidator for SessionServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
@@ -0,0 +1,153 @@
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_api::tenant::tenant_service_server::TenantService;
+use rmcs_auth_api::tenant::{
+    TenantSchema, TenantId, TenantName, TenantUpdate, UserTenant, ApiTenant,
+    TenantReadResponse, TenantListResponse, TenantCreateResponse, TenantChangeResponse
+};
+use crate::utility::validator::{AuthValidator, ValidatorKind};
+use crate::utility::handle_error;
+
+/// Tenants own users and APIs, roles belong to the tenant of their API. User and API without
+/// tenant are shared by every tenant.
+pub struct TenantServer {
+    pub auth_db: Auth,
+    pub validator_flag: bool
+}
+
+impl TenantServer {
+    pub fn new(auth_db: Auth) -> Self {
+        TenantServer {
+            auth_db,
+            validator_flag: false
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl TenantService for TenantServer {
+
+    async fn read_tenant(&self, request: Request<TenantId>)
+        -> Result<Response<TenantReadResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.read_tenant(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = match result {
+            Ok(value) => Some(value.into()),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantReadResponse { result }))
+    }
+
+    async fn list_tenant_by_name(&self, request: Request<TenantName>)
+        -> Result<Response<TenantListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.list_tenant_by_name(&request.name).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantListResponse { results }))
+    }
+
+    async fn create_tenant(&self, request: Request<TenantSchema>)
+        -> Result<Response<TenantCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.create_tenant(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            &request.name,
+            &request.description
+        ).await;
+        let id = match result {
+            Ok(value) => value,
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantCreateResponse { id: id.as_bytes().to_vec() }))
+    }
+
+    async fn update_tenant(&self, request: Request<TenantUpdate>)
+        -> Result<Response<TenantChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.update_tenant(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            request.name.as_deref(),
+            request.description.as_deref()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantChangeResponse { }))
+    }
+
+    async fn delete_tenant(&self, request: Request<TenantId>)
+        -> Result<Response<TenantChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.delete_tenant(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantChangeResponse { }))
+    }
+
+    async fn set_user_tenant(&self, request: Request<UserTenant>)
+        -> Result<Response<TenantChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.update_user_tenant(
+            Uuid::from_slice(&request.user_id).unwrap_or_default(),
+            request.tenant_id.map(|id| Uuid::from_slice(&id).unwrap_or_default())
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantChangeResponse { }))
+    }
+
+    async fn set_api_tenant(&self, request: Request<ApiTenant>)
+        -> Result<Response<TenantChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let request = request.into_inner();
+        let result = self.auth_db.update_api_tenant(
+            Uuid::from_slice(&request.api_id).unwrap_or_default(),
+            request.tenant_id.map(|id| Uuid::from_slice(&id).unwrap_or_default())
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(TenantChangeResponse { }))
+    }
+
+}
+
+impl AuthValidator for TenantServer {
+
+    fn with_validator(mut self) -> Self {
+        self.validator_flag = true;
+        self
+    }
+
+    fn validator_flag(&self) -> bool {
+        self.validator_flag
+    }
+
+    fn auth_db(&self) ->  &Auth {
+        &self.auth_db
+    }
+
+}
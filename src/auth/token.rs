@@ -10,6 +10,7 @@ use rmcs_auth_api::token::{
 };
 use crate::utility::validator::{AuthValidator, ValidatorKind};
 use crate::utility::handle_error;
+use crate::utility::revoke::revoked_tokens;
 
 pub struct TokenServer {
     pub auth_db: Auth,
@@ -147,11 +148,15 @@ impl TokenService for TokenServer {
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
         let request = request.into_inner();
+        let token = self.auth_db.read_access_token(request.access_id).await
+            .map_err(|e| handle_error(e))?;
         let result = self.auth_db.delete_access_token(request.access_id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+            .map_err(handle_error)?;
         Ok(Response::new(TokenChangeResponse { }))
     }
 
@@ -160,11 +165,17 @@ impl TokenService for TokenServer {
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
         let request = request.into_inner();
+        let tokens = self.auth_db.list_auth_token(&request.auth_token).await
+            .map_err(|e| handle_error(e))?;
         let result = self.auth_db.delete_auth_token(&request.auth_token).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        for token in tokens {
+            revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                .map_err(handle_error)?;
+        }
         Ok(Response::new(TokenChangeResponse { }))
     }
 
@@ -173,11 +184,18 @@ impl TokenService for TokenServer {
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
         let request = request.into_inner();
-        let result = self.auth_db.delete_token_by_user(Uuid::from_slice(&request.user_id).unwrap_or_default()).await;
+        let user_id = Uuid::from_slice(&request.user_id).unwrap_or_default();
+        let tokens = self.auth_db.list_token_by_user(user_id).await
+            .map_err(|e| handle_error(e))?;
+        let result = self.auth_db.delete_token_by_user(user_id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        for token in tokens {
+            revoked_tokens().revoke(&self.auth_db, token.access_id, token.expire).await
+                .map_err(handle_error)?;
+        }
         Ok(Response::new(TokenChangeResponse { }))
     }
 
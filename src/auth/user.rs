@@ -1,13 +1,21 @@
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 use rmcs_auth_db::Auth;
+use rmcs_resource_db::DataValue;
 use rmcs_auth_api::user::user_service_server::UserService;
 use rmcs_auth_api::user::{
     UserSchema, UserId, UserIds, UserName, ApiId, RoleId, UserOption, UserUpdate, UserRole,
-    UserReadResponse, UserListResponse, UserCreateResponse, UserChangeResponse
+    UserScope, ScopeSchema, ScopeListResponse,
+    UserReadResponse, UserListResponse, UserCreateResponse, UserChangeResponse,
+    UserVerificationRequest, UserVerificationConfirm
 };
 use crate::utility::validator::{AuthValidator, ValidatorKind};
 use crate::utility::handle_error;
+use crate::utility::scope::ScopeKind;
+use crate::utility::password::{password_policy, hash_password, verify_password};
+use crate::utility::notifier::{notifier, Channel, Notification};
+use crate::utility::verification::{verifications, VerificationKind, EMAIL_VERIFIED, PHONE_VERIFIED};
+use super::{PASSWORD_MISMATCH, CURRENT_PASSWORD_EMPTY, HASH_ERR, VERIFY_TARGET_EMPTY, VERIFY_KIND_INVALID, VERIFY_TOKEN_INVALID, NOTIFY_ERR};
 
 pub struct UserServer {
     pub auth_db: Auth,
@@ -32,7 +40,8 @@ impl UserService for UserServer {
         let extension = request.extensions();
         let request = request.get_ref();
         self.validate(extension, ValidatorKind::User(Uuid::from_slice(&request.id).unwrap_or_default())).await?;
-        let result = self.auth_db.read_user(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let auth_db = self.tenant_db(extension).await?;
+        let result = auth_db.read_user(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -44,8 +53,9 @@ impl UserService for UserServer {
         -> Result<Response<UserReadResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.read_user_by_name(&request.name).await;
+        let result = auth_db.read_user_by_name(&request.name).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -57,8 +67,9 @@ impl UserService for UserServer {
         -> Result<Response<UserListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_user_by_ids(
+        let result = auth_db.list_user_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -72,8 +83,9 @@ impl UserService for UserServer {
         -> Result<Response<UserListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_user_by_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.list_user_by_api(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -85,8 +97,9 @@ impl UserService for UserServer {
         -> Result<Response<UserListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_user_by_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = auth_db.list_user_by_role(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -98,8 +111,9 @@ impl UserService for UserServer {
         -> Result<Response<UserListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_user_by_name(&request.name).await;
+        let result = auth_db.list_user_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -111,8 +125,9 @@ impl UserService for UserServer {
         -> Result<Response<UserListResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.list_user_option(
+        let result = auth_db.list_user_option(
             request.api_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.role_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
@@ -128,13 +143,19 @@ impl UserService for UserServer {
         -> Result<Response<UserCreateResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.create_user(
+        password_policy().check(&request.password)
+            .map_err(|e| Status::invalid_argument(e))?;
+        // password is hashed here and the hash is stored as is by the database layer
+        let hash = hash_password(request.password.as_bytes())
+            .map_err(|_| Status::internal(HASH_ERR))?;
+        let result = auth_db.create_user(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             &request.email,
             &request.phone,
-            &request.password
+            &hash
         ).await;
         let id = match result {
             Ok(value) => value,
@@ -148,18 +169,40 @@ impl UserService for UserServer {
     {
         let extension = request.extensions();
         let request = request.get_ref();
-        self.validate(extension, ValidatorKind::User(Uuid::from_slice(&request.id).unwrap_or_default())).await?;
-        let result = self.auth_db.update_user(
-            Uuid::from_slice(&request.id).unwrap_or_default(),
+        let id = Uuid::from_slice(&request.id).unwrap_or_default();
+        self.validate(extension, ValidatorKind::User(id)).await?;
+        let auth_db = self.tenant_db(extension).await?;
+        // password set by root is checked against policy and history as well, only a user changing
+        // own password must confirm current password
+        let (previous, hash) = match &request.password {
+            Some(password) => {
+                let self_change = self.validator_flag() && self.user_id(extension).await? == id;
+                let previous = check_password(&auth_db, id, password, request.current_password.as_deref(), self_change).await?;
+                let hash = hash_password(password.as_bytes())
+                    .map_err(|_| Status::internal(HASH_ERR))?;
+                (Some(previous), Some(hash))
+            },
+            None => (None, None)
+        };
+        // replaced password hash is kept in history in the same transaction as the update
+        let transaction = auth_db.begin().await
+            .map_err(handle_error)?;
+        let result = transaction.update_user(
+            id,
             request.name.as_deref(),
             request.email.as_deref(),
             request.phone.as_deref(),
-            request.password.as_deref()
+            hash.as_deref()
         ).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        if let Some(hash) = previous {
+            push_password_history(&transaction, id, &hash).await?;
+        }
+        transaction.commit().await
+            .map_err(handle_error)?;
         Ok(Response::new(UserChangeResponse { }))
     }
 
@@ -169,7 +212,8 @@ impl UserService for UserServer {
         let extension = request.extensions();
         let request = request.get_ref();
         self.validate(extension, ValidatorKind::User(Uuid::from_slice(&request.id).unwrap_or_default())).await?;
-        let result = self.auth_db.delete_user(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let auth_db = self.tenant_db(extension).await?;
+        let result = auth_db.delete_user(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -181,8 +225,9 @@ impl UserService for UserServer {
         -> Result<Response<UserChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.add_user_role(
+        let result = auth_db.add_user_role(
             Uuid::from_slice(&request.user_id).unwrap_or_default(),
             Uuid::from_slice(&request.role_id).unwrap_or_default()
         ).await;
@@ -197,8 +242,9 @@ impl UserService for UserServer {
         -> Result<Response<UserChangeResponse>, Status>
     {
         self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
         let request = request.into_inner();
-        let result = self.auth_db.remove_user_role(
+        let result = auth_db.remove_user_role(
             Uuid::from_slice(&request.user_id).unwrap_or_default(),
             Uuid::from_slice(&request.role_id).unwrap_or_default()
         ).await;
@@ -209,6 +255,165 @@ impl UserService for UserServer {
         Ok(Response::new(UserChangeResponse { }))
     }
 
+    async fn list_user_scope(&self, request: Request<UserId>)
+        -> Result<Response<ScopeListResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.list_user_scope(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| ScopeSchema {
+                kind: ScopeKind::from(e.kind.as_str()).into(),
+                resource_id: e.resource_id.as_bytes().to_vec()
+            }).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ScopeListResponse { results }))
+    }
+
+    async fn add_user_scope(&self, request: Request<UserScope>)
+        -> Result<Response<UserChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.add_user_scope(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            ScopeKind::from(request.kind).name(),
+            Uuid::from_slice(&request.resource_id).unwrap_or_default()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(UserChangeResponse { }))
+    }
+
+    async fn remove_user_scope(&self, request: Request<UserScope>)
+        -> Result<Response<UserChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), ValidatorKind::Root).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let request = request.into_inner();
+        let result = auth_db.remove_user_scope(
+            Uuid::from_slice(&request.id).unwrap_or_default(),
+            ScopeKind::from(request.kind).name(),
+            Uuid::from_slice(&request.resource_id).unwrap_or_default()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(UserChangeResponse { }))
+    }
+
+    async fn request_user_verification(&self, request: Request<UserVerificationRequest>)
+        -> Result<Response<UserChangeResponse>, Status>
+    {
+        let id = Uuid::from_slice(&request.get_ref().id).unwrap_or_default();
+        self.validate(request.extensions(), ValidatorKind::User(id)).await?;
+        let auth_db = self.tenant_db(request.extensions()).await?;
+        let kind = VerificationKind::from(request.get_ref().kind);
+        let user = auth_db.read_user(id).await
+            .map_err(handle_error)?;
+        let (channel, target, contact) = match kind {
+            VerificationKind::Email => (Channel::Email, user.email, "email"),
+            VerificationKind::Phone => (Channel::Sms, user.phone, "phone"),
+            VerificationKind::PasswordReset => return Err(Status::invalid_argument(VERIFY_KIND_INVALID))
+        };
+        if target.is_empty() {
+            return Err(Status::invalid_argument(VERIFY_TARGET_EMPTY));
+        }
+        let notifier = notifier(channel).ok_or(Status::unavailable(NOTIFY_ERR))?;
+        let token = verifications().create(&auth_db, kind, id, &target).await
+            .map_err(handle_error)?;
+        let notification = Notification {
+            destination: target,
+            subject: String::from("Verification code"),
+            body: format!("Use this code to verify your {} of user {}: {}", contact, user.name, token)
+        };
+        notifier.notify(&notification).await
+            .map_err(|e| Status::unavailable(format!("{}: {}", NOTIFY_ERR, e)))?;
+        Ok(Response::new(UserChangeResponse { }))
+    }
+
+    async fn confirm_user_verification(&self, request: Request<UserVerificationConfirm>)
+        -> Result<Response<UserChangeResponse>, Status>
+    {
+        // the token itself authorize the request since it is only sent to the user contact
+        let request = request.into_inner();
+        let kind = VerificationKind::from(request.kind);
+        if kind == VerificationKind::PasswordReset {
+            return Err(Status::invalid_argument(VERIFY_KIND_INVALID));
+        }
+        let verification = verifications().take(&self.auth_db, kind, &request.token).await
+            .ok_or(Status::invalid_argument(VERIFY_TOKEN_INVALID))?;
+        let user = self.auth_db.read_user(verification.user_id).await
+            .map_err(handle_error)?;
+        let (profile, target) = match kind {
+            VerificationKind::Phone => (PHONE_VERIFIED, user.phone),
+            _ => (EMAIL_VERIFIED, user.email)
+        };
+        // email or phone changed after token is sent
+        if target != verification.target {
+            return Err(Status::invalid_argument(VERIFY_TOKEN_INVALID));
+        }
+        set_user_profile(&self.auth_db, user.id, profile, DataValue::String(target)).await?;
+        Ok(Response::new(UserChangeResponse { }))
+    }
+
+}
+
+/// Update value of a user profile with the name, or create it if not exist
+pub(crate) async fn set_user_profile(auth_db: &Auth, user_id: Uuid, name: &str, value: DataValue)
+    -> Result<(), Status>
+{
+    let profiles = auth_db.list_user_profile_by_user(user_id).await
+        .map_err(|e| handle_error(e))?;
+    let result = match profiles.into_iter().filter(|p| p.name == name).next() {
+        Some(profile) => auth_db.update_user_profile(profile.id, None, Some(value)).await,
+        None => auth_db.create_user_profile(user_id, name, value).await.map(|_| ())
+    };
+    result.map_err(|e| handle_error(e))
+}
+
+/// Check a new password against password policy and previous passwords, and return the current
+/// password hash which is pushed to password history once the password is updated
+pub(crate) async fn check_password(auth_db: &Auth, id: Uuid, password: &str, current_password: Option<&str>, self_change: bool)
+    -> Result<String, Status>
+{
+    let policy = password_policy();
+    policy.check(password)
+        .map_err(Status::invalid_argument)?;
+    let user = auth_db.read_user(id).await
+        .map_err(handle_error)?;
+    if self_change {
+        let current = current_password.ok_or(Status::invalid_argument(CURRENT_PASSWORD_EMPTY))?;
+        verify_password(current.as_bytes(), &user.password)
+            .map_err(|_| Status::invalid_argument(PASSWORD_MISMATCH))?;
+    }
+    let history = auth_db.list_password_history(id).await
+        .map_err(handle_error)?;
+    let mut hashes = vec![user.password.as_str()];
+    hashes.extend(history.iter().map(|h| h.hash.as_str()));
+    policy.check_history(password, &hashes)
+        .map_err(Status::invalid_argument)?;
+    Ok(user.password)
+}
+
+/// Store a replaced password hash in password history and delete hashes no longer needed for reuse check
+pub(crate) async fn push_password_history(auth_db: &Auth, id: Uuid, hash: &str) -> Result<(), Status>
+{
+    let kept = password_policy().history_kept();
+    if kept == 0 {
+        return Ok(());
+    }
+    auth_db.create_password_history(id, hash).await
+        .map_err(handle_error)?;
+    auth_db.delete_password_history_old(id, kept).await
+        .map_err(handle_error)?;
+    Ok(())
 }
 
 impl AuthValidator for UserServer {
@@ -5,6 +5,12 @@ use rmcs_auth_api::role::role_service_server::RoleServiceServer;
 use rmcs_auth_api::user::user_service_server::UserServiceServer;
 use rmcs_auth_api::profile::profile_service_server::ProfileServiceServer;
 use rmcs_auth_api::token::token_service_server::TokenServiceServer;
+use rmcs_auth_api::service_account::service_account_service_server::ServiceAccountServiceServer;
+use rmcs_auth_api::tenant::tenant_service_server::TenantServiceServer;
+use rmcs_auth_api::session::session_service_server::SessionServiceServer;
+use rmcs_auth_api::lockout::lockout_service_server::LockoutServiceServer;
+use rmcs_auth_api::mfa::mfa_service_server::MfaServiceServer;
+use rmcs_auth_api::policy::policy_service_server::PolicyServiceServer;
 use rmcs_auth_api::auth::auth_service_server::AuthServiceServer;
 use rmcs_auth_api::descriptor;
 use rmcs_api_server::auth::api::ApiServer;
@@ -12,18 +18,33 @@ use rmcs_api_server::auth::role::RoleServer;
 use rmcs_api_server::auth::user::UserServer;
 use rmcs_api_server::auth::profile::ProfileServer;
 use rmcs_api_server::auth::token::TokenServer;
+use rmcs_api_server::auth::service_account::ServiceAccountServer;
+use rmcs_api_server::auth::tenant::TenantServer;
+use rmcs_api_server::auth::session::SessionServer;
+use rmcs_api_server::auth::lockout::LockoutServer;
+use rmcs_api_server::auth::mfa::MfaServer;
+use rmcs_api_server::auth::policy::PolicyServer;
 use rmcs_api_server::auth::auth::AuthServer;
 use rmcs_api_server::utility::interceptor::interceptor;
 use rmcs_api_server::utility::validator::AuthValidator;
 use rmcs_api_server::utility::config::{ROOT_DATA, RootData};
+use rmcs_api_server::utility::signing::signing_keys;
+use rmcs_api_server::utility::revoke::revoked_tokens;
+use rmcs_api_server::utility::notifier::init_notifier;
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use http::{header::HeaderName, Method};
 use tower_http::cors::{CorsLayer, Any};
 
+const DEF_ROTATE_INTERVAL: i64 = 86400;
+const DEF_KEY_OVERLAP: i64 = 86400;
+const KEY_SYNC_INTERVAL: u64 = 60;
+const REVOKE_SYNC_INTERVAL: u64 = 15;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
     let url = std::env::var("DATABASE_URL_AUTH").unwrap();
     let addr = std::env::var("BIND_ADDRESS_AUTH").unwrap().parse()?;
 
@@ -31,21 +52,72 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let root_ad = std::env::var("ROOT_ACCESS_DURATION");
     let root_rd = std::env::var("ROOT_REFRESH_DURATION");
     if let (Ok(password), Ok(access_duration), Ok(refresh_duration)) = (root_pw, root_ad, root_rd) {
-        ROOT_DATA.set(RootData::new(
+        let mut root = RootData::new(
             &password, 
             access_duration.parse()?, 
             refresh_duration.parse()?
-        )).unwrap();
+        );
+        if let Ok(secret) = std::env::var("ROOT_MFA_SECRET") {
+            root = root.with_mfa_secret(&secret);
+        }
+        ROOT_DATA.set(root).unwrap();
     }
+    init_notifier()?;
 
     let auth_db = Auth::new_with_url(&url).await;
     migrate(&auth_db.pool).await.unwrap();
 
+    // signing keys are stored in auth database so tokens stay verifiable after restart and every
+    // replica sign with the same key. Current key is retired after rotation interval and kept published
+    // for overlap duration which should be longer than the longest refresh duration so old access tokens
+    // can be refreshed
+    let rotate_interval = std::env::var("TOKEN_ROTATE_INTERVAL").ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEF_ROTATE_INTERVAL);
+    let key_overlap = std::env::var("TOKEN_KEY_OVERLAP").ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEF_KEY_OVERLAP);
+    signing_keys().load(&auth_db).await?;
+    let key_db = auth_db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(KEY_SYNC_INTERVAL));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let rotate = chrono::Duration::seconds(rotate_interval);
+            let overlap = chrono::Duration::seconds(key_overlap);
+            if let Err(e) = signing_keys().sync(&key_db, rotate, overlap).await {
+                tracing::error!("Failed to synchronize token signing keys: {}", e);
+            }
+        }
+    });
+
+    // revoked tokens are stored in auth database, tokens revoked by other replicas are loaded periodically
+    // so they are rejected and published to resource servers connected to this replica
+    revoked_tokens().load(&auth_db).await?;
+    let revoke_db = auth_db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(REVOKE_SYNC_INTERVAL));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = revoked_tokens().load(&revoke_db).await {
+                tracing::error!("Failed to synchronize revoked tokens: {}", e);
+            }
+        }
+    });
+
     let api_server = ApiServer::new(auth_db.clone()).with_validator();
     let role_server = RoleServer::new(auth_db.clone()).with_validator();
     let user_server = UserServer::new(auth_db.clone()).with_validator();
     let profile_server = ProfileServer::new(auth_db.clone()).with_validator();
     let token_server = TokenServer::new(auth_db.clone()).with_validator();
+    let service_account_server = ServiceAccountServer::new(auth_db.clone()).with_validator();
+    let tenant_server = TenantServer::new(auth_db.clone()).with_validator();
+    let session_server = SessionServer::new(auth_db.clone()).with_validator();
+    let lockout_server = LockoutServer::new(auth_db.clone()).with_validator();
+    let mfa_server = MfaServer::new(auth_db.clone()).with_validator();
+    let policy_server = PolicyServer::new(auth_db.clone()).with_validator();
     let auth_server = AuthServer::new(auth_db.clone());
 
     let api_server = ApiServiceServer::with_interceptor(api_server, interceptor);
@@ -53,6 +125,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let user_server = UserServiceServer::with_interceptor(user_server, interceptor);
     let profile_server = ProfileServiceServer::with_interceptor(profile_server, interceptor);
     let token_server = TokenServiceServer::with_interceptor(token_server, interceptor);
+    let service_account_server = ServiceAccountServiceServer::with_interceptor(service_account_server, interceptor);
+    let tenant_server = TenantServiceServer::with_interceptor(tenant_server, interceptor);
+    let session_server = SessionServiceServer::with_interceptor(session_server, interceptor);
+    let lockout_server = LockoutServiceServer::with_interceptor(lockout_server, interceptor);
+    let mfa_server = MfaServiceServer::with_interceptor(mfa_server, interceptor);
+    let policy_server = PolicyServiceServer::with_interceptor(policy_server, interceptor);
     let auth_server = AuthServiceServer::new(auth_server);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
@@ -61,6 +139,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .register_encoded_file_descriptor_set(descriptor::user::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::profile::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::token::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::service_account::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::tenant::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::session::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::lockout::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::mfa::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::policy::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::auth::DESCRIPTOR_SET)
         .build_v1();
 
@@ -78,6 +162,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_service(user_server)
         .add_service(profile_server)
         .add_service(token_server)
+        .add_service(service_account_server)
+        .add_service(tenant_server)
+        .add_service(session_server)
+        .add_service(lockout_server)
+        .add_service(mfa_server)
+        .add_service(policy_server)
         .add_service(auth_server)
         .add_service(reflection_service?)
         .serve(addr)
@@ -4,6 +4,12 @@ use rmcs_auth_api::role::role_service_server::RoleServiceServer;
 use rmcs_auth_api::user::user_service_server::UserServiceServer;
 use rmcs_auth_api::profile::profile_service_server::ProfileServiceServer;
 use rmcs_auth_api::token::token_service_server::TokenServiceServer;
+use rmcs_auth_api::service_account::service_account_service_server::ServiceAccountServiceServer;
+use rmcs_auth_api::tenant::tenant_service_server::TenantServiceServer;
+use rmcs_auth_api::session::session_service_server::SessionServiceServer;
+use rmcs_auth_api::lockout::lockout_service_server::LockoutServiceServer;
+use rmcs_auth_api::mfa::mfa_service_server::MfaServiceServer;
+use rmcs_auth_api::policy::policy_service_server::PolicyServiceServer;
 use rmcs_auth_api::auth::auth_service_server::AuthServiceServer;
 use rmcs_auth_api::descriptor as auth_descriptor;
 use rmcs_resource_db::Resource;
@@ -15,12 +21,21 @@ use rmcs_resource_api::data::data_service_server::DataServiceServer;
 use rmcs_resource_api::buffer::buffer_service_server::BufferServiceServer;
 use rmcs_resource_api::slice::slice_service_server::SliceServiceServer;
 use rmcs_resource_api::log::log_service_server::LogServiceServer;
+use rmcs_resource_api::command::command_service_server::CommandServiceServer;
+use rmcs_resource_api::provision::provision_service_server::ProvisionServiceServer;
+use rmcs_resource_api::catalog::catalog_service_server::CatalogServiceServer;
 use rmcs_resource_api::descriptor as resource_descriptor;
 use rmcs_api_server::auth::api::ApiServer;
 use rmcs_api_server::auth::role::RoleServer;
 use rmcs_api_server::auth::user::UserServer;
 use rmcs_api_server::auth::profile::ProfileServer;
 use rmcs_api_server::auth::token::TokenServer;
+use rmcs_api_server::auth::service_account::ServiceAccountServer;
+use rmcs_api_server::auth::tenant::TenantServer;
+use rmcs_api_server::auth::session::SessionServer;
+use rmcs_api_server::auth::lockout::LockoutServer;
+use rmcs_api_server::auth::mfa::MfaServer;
+use rmcs_api_server::auth::policy::PolicyServer;
 use rmcs_api_server::auth::auth::AuthServer;
 use rmcs_api_server::resource::model::ModelServer;
 use rmcs_api_server::resource::device::DeviceServer;
@@ -30,20 +45,36 @@ use rmcs_api_server::resource::data::DataServer;
 use rmcs_api_server::resource::buffer::BufferServer;
 use rmcs_api_server::resource::slice::SliceServer;
 use rmcs_api_server::resource::log::LogServer;
+use rmcs_api_server::resource::command::CommandServer;
+use rmcs_api_server::resource::provision::ProvisionServer;
+use rmcs_api_server::resource::catalog::CatalogServer;
+use rmcs_api_server::utility::signing::signing_keys;
+use rmcs_api_server::utility::revoke::revoked_tokens;
+use rmcs_api_server::utility::notifier::init_notifier;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
     let url_auth = std::env::var("DATABASE_AUTH_URL").unwrap();
     let url_resource = std::env::var("DATABASE_RESOURCE_URL").unwrap();
     let addr = std::env::var("ADDRESS").unwrap().parse()?;
+    init_notifier()?;
 
     let auth_db = Auth::new_with_url(&url_auth).await;
+    signing_keys().load(&auth_db).await?;
+    revoked_tokens().load(&auth_db).await?;
     let api_server = ApiServer::new(auth_db.clone());
     let role_server = RoleServer::new(auth_db.clone());
     let user_server = UserServer::new(auth_db.clone());
     let profile_server = ProfileServer::new(auth_db.clone());
     let token_server = TokenServer::new(auth_db.clone());
+    let service_account_server = ServiceAccountServer::new(auth_db.clone());
+    let tenant_server = TenantServer::new(auth_db.clone());
+    let session_server = SessionServer::new(auth_db.clone());
+    let lockout_server = LockoutServer::new(auth_db.clone());
+    let mfa_server = MfaServer::new(auth_db.clone());
+    let policy_server = PolicyServer::new(auth_db.clone());
     let auth_server = AuthServer::new(auth_db.clone());
 
     let resource_db = Resource::new_with_url(&url_resource).await;
@@ -55,6 +86,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let buffer_server = BufferServer::new(resource_db.clone());
     let slice_server = SliceServer::new(resource_db.clone());
     let log_server = LogServer::new(resource_db.clone());
+    let command_server = CommandServer::new(resource_db.clone());
+    let provision_server = ProvisionServer::new(resource_db.clone());
+    let catalog_server = CatalogServer::new(resource_db.clone());
 
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(auth_descriptor::api::DESCRIPTOR_SET)
@@ -62,6 +96,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .register_encoded_file_descriptor_set(auth_descriptor::user::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(auth_descriptor::profile::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(auth_descriptor::token::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::service_account::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::tenant::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::session::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::lockout::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::mfa::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(auth_descriptor::policy::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(auth_descriptor::auth::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(resource_descriptor::model::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(resource_descriptor::device::DESCRIPTOR_SET)
@@ -71,6 +111,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .register_encoded_file_descriptor_set(resource_descriptor::buffer::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(resource_descriptor::slice::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(resource_descriptor::log::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(resource_descriptor::command::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(resource_descriptor::provision::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(resource_descriptor::catalog::DESCRIPTOR_SET)
         .build_v1();
 
     tonic::transport::Server::builder()
@@ -79,6 +122,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_service(UserServiceServer::new(user_server))
         .add_service(ProfileServiceServer::new(profile_server))
         .add_service(TokenServiceServer::new(token_server))
+        .add_service(ServiceAccountServiceServer::new(service_account_server))
+        .add_service(TenantServiceServer::new(tenant_server))
+        .add_service(SessionServiceServer::new(session_server))
+        .add_service(LockoutServiceServer::new(lockout_server))
+        .add_service(MfaServiceServer::new(mfa_server))
+        .add_service(PolicyServiceServer::new(policy_server))
         .add_service(AuthServiceServer::new(auth_server))
         .add_service(ModelServiceServer::new(model_server))
         .add_service(DeviceServiceServer::new(device_server))
@@ -88,6 +137,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_service(BufferServiceServer::new(buffer_server))
         .add_service(SliceServiceServer::new(slice_server))
         .add_service(LogServiceServer::new(log_server))
+        .add_service(CommandServiceServer::new(command_server))
+        .add_service(ProvisionServiceServer::new(provision_server))
+        .add_service(CatalogServiceServer::new(catalog_server))
         .add_service(reflection_service?)
         .serve(addr)
         .await?;
@@ -11,25 +11,30 @@ use rmcs_resource_api::buffer::{
     BufferReadResponse, BufferListResponse, BufferCreateResponse, BufferCreateMultipleResponse, BufferChangeResponse,
     BufferSetReadResponse, BufferSetListResponse, TimestampReadResponse, TimestampListResponse, BufferCountResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_BUFFER, CREATE_BUFFER, UPDATE_BUFFER, DELETE_BUFFER
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_BUFFER, CREATE_BUFFER, UPDATE_BUFFER, DELETE_BUFFER
+];
+
 #[derive(Debug)]
 pub struct BufferServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl BufferServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -41,10 +46,15 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer(request.id).await;
+        let result = resource_db.read_buffer(request.id).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.device_id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(BufferReadResponse { result }))
@@ -54,8 +64,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.read_buffer_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -72,10 +85,15 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_ids(&request.ids).await;
+        let result = resource_db.list_buffer_by_ids(&request.ids).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.device_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(BufferListResponse { results }))
@@ -85,8 +103,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -103,8 +124,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -121,8 +145,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -140,8 +167,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_number_before(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_by_number_before(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -159,8 +189,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_by_number_after(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_by_number_after(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -178,8 +211,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_first(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.read_buffer_first(
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.tag.map(|t| t as i16)
@@ -195,8 +231,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_last(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.read_buffer_last(
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.tag.map(|t| t as i16)
@@ -212,8 +251,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_first(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_first(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -230,8 +272,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_first_offset(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_first_offset(
             request.number as usize,
             request.offset as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -249,8 +294,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_last(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_last(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -267,8 +315,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_last_offset(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_last_offset(
             request.number as usize,
             request.offset as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -286,8 +337,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_by_time(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_by_time(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -304,8 +358,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -322,8 +379,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -341,8 +401,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_by_number_before(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_by_number_before(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -360,8 +423,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_by_number_after(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_by_number_after(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -379,8 +445,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_group_first(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_buffer_group_first(
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             request.tag.map(|t| t as i16)
@@ -396,8 +465,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_group_last(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_buffer_group_last(
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             request.tag.map(|t| t as i16)
@@ -413,8 +485,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_first(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_first(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -431,8 +506,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_first_offset(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_first_offset(
             request.number as usize,
             request.offset as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -450,8 +528,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_last(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_last(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -468,8 +549,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_last_offset(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_last_offset(
             request.number as usize,
             request.offset as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -487,8 +571,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferSetReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_set(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.read_buffer_set(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.tag.map(|t| t as i16)
@@ -504,8 +591,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_set_by_time(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_buffer_set_by_time(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.tag.map(|t| t as i16)
@@ -521,8 +611,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_set_by_latest(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_buffer_set_by_latest(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
             request.tag.map(|t| t as i16)
@@ -538,8 +631,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_set_by_range(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_buffer_set_by_range(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000),
@@ -556,8 +652,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_buffer(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.create_buffer(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -578,7 +677,10 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCreateMultipleResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
+        scope.check_devices(&request.schemas.iter().map(|r| r.device_id.clone()).collect::<Vec<Vec<u8>>>())?;
         let (device_ids, model_ids, timestamps, data_vec, tags): (Vec<Uuid>, Vec<Uuid>, Vec<DateTime<Utc>>, Vec<Vec<DataValue>>, Vec<i16>) 
             = request.schemas.into_iter().map(|r| {(
                 Uuid::from_slice(&r.device_id).unwrap_or_default(),
@@ -591,7 +693,7 @@ impl BufferService for BufferServer {
                 r.tag as i16
             )}).collect();
         let data_multiple: Vec<&[DataValue]> = data_vec.iter().map(|d| d.as_slice()).collect();
-        let result = self.resource_db.create_buffer_multiple(
+        let result = resource_db.create_buffer_multiple(
             &device_ids,
             &model_ids,
             &timestamps,
@@ -609,8 +711,14 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_buffer(
+        if scope.is_restricted() {
+            let value = resource_db.read_buffer(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.update_buffer(
             request.id,
             request.data_bytes.map(|s| {
                 ArrayDataValue::from_bytes(
@@ -631,8 +739,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_buffer_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.update_buffer_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -655,8 +766,14 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_buffer(request.id).await;
+        if scope.is_restricted() {
+            let value = resource_db.read_buffer(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.delete_buffer(request.id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -668,8 +785,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_buffer_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.delete_buffer_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -686,8 +806,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_timestamp(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.read_buffer_timestamp(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -704,8 +827,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_timestamp_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_timestamp_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -722,8 +848,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_timestamp_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_buffer_timestamp_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -741,8 +870,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_timestamp_first(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_timestamp_first(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -759,8 +891,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_timestamp_last(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_buffer_timestamp_last(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -777,8 +912,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_buffer_group_timestamp(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_buffer_group_timestamp(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -795,8 +933,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_timestamp_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_timestamp_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -813,8 +954,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_timestamp_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_timestamp_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -832,8 +976,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_timestamp_first(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_timestamp_first(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -850,8 +997,11 @@ impl BufferService for BufferServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_buffer_group_timestamp_last(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_buffer_group_timestamp_last(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -868,8 +1018,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_buffer(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag.map(|t| t as i16)
@@ -885,8 +1038,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_buffer_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -903,8 +1059,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_buffer_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -922,8 +1081,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer_group(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_buffer_group(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             request.tag.map(|t| t as i16)
@@ -939,8 +1101,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer_group_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_buffer_group_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -957,8 +1122,11 @@ impl BufferService for BufferServer {
         -> Result<Response<BufferCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_BUFFER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_buffer_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_buffer_group_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -976,21 +1144,25 @@ impl BufferService for BufferServer {
 
 impl AccessValidator for BufferServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_BUFFER, CREATE_BUFFER, UPDATE_BUFFER, DELETE_BUFFER
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for BufferServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
@@ -0,0 +1,699 @@
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+use rmcs_resource_db::{Resource, DataType, DataValue, ConfigKind};
+use rmcs_resource_api::device::ConfigAction;
+use rmcs_resource_api::catalog::catalog_service_server::CatalogService;
+use rmcs_resource_api::catalog::{
+    CatalogExportRequest, CatalogExportResponse, CatalogImportRequest, CatalogImportResponse,
+    CatalogChange, CatalogAction
+};
+use crate::utility::catalog::{
+    Catalog, CatalogFormat, CatalogConfig, CatalogDataType, CatalogModelConfig, CatalogTag, CatalogModel, CatalogType,
+    CatalogGateway, CatalogDevice, CatalogGroup, CatalogSet, CatalogSetMember, CatalogTemplate,
+    CatalogTemplateMember, CATALOG_VERSION
+};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
+use super::history;
+use super::config_change::{ConfigEntry, ConfigPlan, plan_config, apply_config, notify_config};
+use super::{
+    READ_MODEL, CREATE_MODEL, UPDATE_MODEL, READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG,
+    READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG,
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, CHANGE_TYPE_MODEL,
+    READ_GROUP, CREATE_GROUP, UPDATE_GROUP, CHANGE_GROUP_MEMBER,
+    READ_SET, CREATE_SET, UPDATE_SET, CHANGE_SET_MEMBER,
+    CATALOG_INVALID
+};
+use crate::utility::handle_error;
+
+const KIND_MODEL: &str = "model";
+const KIND_MODEL_CONFIG: &str = "model_config";
+const KIND_TAG: &str = "tag";
+const KIND_TYPE: &str = "type";
+const KIND_TYPE_MODEL: &str = "type_model";
+const KIND_GATEWAY: &str = "gateway";
+const KIND_GATEWAY_CONFIG: &str = "gateway_config";
+const KIND_DEVICE: &str = "device";
+const KIND_DEVICE_CONFIG: &str = "device_config";
+const KIND_GROUP_MODEL: &str = "group_model";
+const KIND_GROUP_DEVICE: &str = "group_device";
+const KIND_GROUP_GATEWAY: &str = "group_gateway";
+const KIND_GROUP_MEMBER: &str = "group_member";
+const KIND_SET_TEMPLATE: &str = "set_template";
+const KIND_SET: &str = "set";
+const KIND_SET_MEMBER: &str = "set_member";
+
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_MODEL, CREATE_MODEL, UPDATE_MODEL, READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG,
+    READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG,
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, CHANGE_TYPE_MODEL,
+    READ_GROUP, CREATE_GROUP, UPDATE_GROUP, CHANGE_GROUP_MEMBER,
+    READ_SET, CREATE_SET, UPDATE_SET, CHANGE_SET_MEMBER
+];
+
+#[derive(Debug)]
+pub struct CatalogServer {
+    resource_db: Resource,
+    accesses: AccessTable
+}
+
+impl CatalogServer {
+    pub fn new(resource_db: Resource) -> Self {
+        Self {
+            resource_db,
+            accesses: AccessTable::default()
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CatalogService for CatalogServer {
+
+    async fn export_catalog(&self, request: Request<CatalogExportRequest>)
+        -> Result<Response<CatalogExportResponse>, Status>
+    {
+        for procedure in [READ_MODEL, READ_MODEL_CONFIG, READ_TYPE, READ_DEVICE, READ_DEVICE_CONFIG, READ_GROUP, READ_SET] {
+            self.validate(request.extensions(), procedure)?;
+        }
+        // bulk operations touch resources outside any scope so scoped token can not use them
+        self.device_scope(request.extensions()).await?.check_unrestricted()?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        // every entry is read from the same snapshot in a read only repeatable read transaction, so
+        // a catalog exported while resources change never refers to missing entries
+        let transaction = resource_db.begin_read_only().await
+            .map_err(handle_error)?;
+        let catalog = export(&transaction).await.map_err(handle_error)?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        let content = catalog.encode(CatalogFormat::from(request.format))
+            .map_err(Status::internal)?;
+        Ok(Response::new(CatalogExportResponse { content }))
+    }
+
+    async fn import_catalog(&self, request: Request<CatalogImportRequest>)
+        -> Result<Response<CatalogImportResponse>, Status>
+    {
+        let procedures: &[&str] = if request.get_ref().dry_run {
+            &[READ_MODEL, READ_MODEL_CONFIG, READ_TYPE, READ_DEVICE, READ_DEVICE_CONFIG, READ_GROUP, READ_SET]
+        } else {
+            &[READ_MODEL, CREATE_MODEL, UPDATE_MODEL, READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG,
+            READ_TYPE, CREATE_TYPE, UPDATE_TYPE, CHANGE_TYPE_MODEL,
+            READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG,
+            READ_GROUP, CREATE_GROUP, UPDATE_GROUP, CHANGE_GROUP_MEMBER,
+            READ_SET, CREATE_SET, UPDATE_SET, CHANGE_SET_MEMBER]
+        };
+        for procedure in procedures {
+            self.validate(request.extensions(), procedure)?;
+        }
+        // bulk operations touch resources outside any scope so scoped token can not use them
+        self.device_scope(request.extensions()).await?.check_unrestricted()?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let catalog = Catalog::decode(&request.content, CatalogFormat::from(request.format))
+            .map_err(|e| Status::invalid_argument(format!("{}: {}", CATALOG_INVALID, e)))?;
+        // the whole catalog is imported in a single transaction so a failed import leaves the database
+        // untouched, dropping the transaction without commit rolls it back
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let mut import = Import::new(&transaction, request.dry_run, &subject);
+        import.apply(catalog).await?;
+        let Import { dry_run, changes, configs, .. } = import;
+        if dry_run {
+            return Ok(Response::new(CatalogImportResponse { changes }));
+        }
+        transaction.commit().await
+            .map_err(handle_error)?;
+        // gateways streaming config changes reload imported configs once they are committed
+        for (kind, action, id) in configs {
+            notify_config(&resource_db, kind, action, id).await;
+        }
+        Ok(Response::new(CatalogImportResponse { changes }))
+    }
+
+}
+
+async fn export(resource_db: &Resource) -> Result<Catalog, sqlx::Error>
+{
+    let config = |name: String, value: DataValue, category: String| CatalogConfig {
+        name,
+        category,
+        value: value.into()
+    };
+    let mut models = Vec::new();
+    for model in resource_db.list_model_option(None, None, None).await? {
+        let tags = resource_db.list_tag_by_model(model.id).await?
+            .into_iter()
+            .map(|t| CatalogTag { tag: t.tag, name: t.name, members: t.members })
+            .collect();
+        let configs = resource_db.list_model_config_by_model(model.id).await?
+            .into_iter()
+            .map(|c| CatalogModelConfig { index: c.index, config: config(c.name, c.value, c.category) })
+            .collect();
+        models.push(CatalogModel {
+            id: model.id,
+            category: model.category,
+            name: model.name,
+            description: model.description,
+            data_type: model.data_type.into_iter().map(CatalogDataType::from).collect(),
+            tags,
+            configs
+        });
+    }
+    let types = resource_db.list_type_option(None).await?
+        .into_iter()
+        .map(|t| CatalogType { id: t.id, name: t.name, description: t.description, models: t.models })
+        .collect();
+    let mut gateways = Vec::new();
+    for gateway in resource_db.list_gateway_option(None, None).await? {
+        let configs = resource_db.list_gateway_config_by_gateway(gateway.id).await?
+            .into_iter()
+            .map(|c| config(c.name, c.value, c.category))
+            .collect();
+        gateways.push(CatalogGateway {
+            id: gateway.id,
+            type_id: gateway.type_id,
+            serial_number: gateway.serial_number,
+            name: gateway.name,
+            description: gateway.description,
+            configs
+        });
+    }
+    let mut devices = Vec::new();
+    for device in resource_db.list_device_option(None, None, None).await? {
+        let configs = resource_db.list_device_config_by_device(device.id).await?
+            .into_iter()
+            .map(|c| config(c.name, c.value, c.category))
+            .collect();
+        devices.push(CatalogDevice {
+            id: device.id,
+            gateway_id: device.gateway_id,
+            type_id: device.type_id,
+            serial_number: device.serial_number,
+            name: device.name,
+            description: device.description,
+            configs
+        });
+    }
+    let group_models = resource_db.list_group_model_option(None, None).await?
+        .into_iter()
+        .map(|g| CatalogGroup { id: g.id, name: g.name, category: g.category, description: g.description, members: g.models })
+        .collect();
+    let group_devices = resource_db.list_group_device_option(None, None).await?
+        .into_iter()
+        .map(|g| CatalogGroup { id: g.id, name: g.name, category: g.category, description: g.description, members: g.devices })
+        .collect();
+    let group_gateways = resource_db.list_group_gateway_option(None, None).await?
+        .into_iter()
+        .map(|g| CatalogGroup { id: g.id, name: g.name, category: g.category, description: g.description, members: g.gateways })
+        .collect();
+    let set_templates = resource_db.list_set_template_option(None).await?
+        .into_iter()
+        .map(|t| CatalogTemplate {
+            id: t.id,
+            name: t.name,
+            description: t.description,
+            members: t.members.into_iter()
+                .map(|m| CatalogTemplateMember { type_id: m.type_id, model_id: m.model_id, data_index: m.data_index })
+                .collect()
+        })
+        .collect();
+    let sets = resource_db.list_set_option(None, None).await?
+        .into_iter()
+        .map(|s| CatalogSet {
+            id: s.id,
+            template_id: s.template_id,
+            name: s.name,
+            description: s.description,
+            members: s.members.into_iter()
+                .map(|m| CatalogSetMember { device_id: m.device_id, model_id: m.model_id, data_index: m.data_index })
+                .collect()
+        })
+        .collect();
+    Ok(Catalog {
+        version: CATALOG_VERSION,
+        models,
+        types,
+        gateways,
+        devices,
+        group_models,
+        group_devices,
+        group_gateways,
+        set_templates,
+        sets
+    })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GroupKind {
+    Model,
+    Device,
+    Gateway
+}
+
+/// Import a catalog by creating missing and updating changed entries identified by id.
+/// Entries not listed in the catalog and existing members are never removed, so running
+/// the same import again only reports unchanged entries.
+struct Import<'a> {
+    resource_db: &'a Resource,
+    dry_run: bool,
+    subject: &'a str,
+    changes: Vec<CatalogChange>,
+    configs: Vec<(ConfigKind, ConfigAction, i32)>
+}
+
+impl<'a> Import<'a> {
+
+    fn new(resource_db: &'a Resource, dry_run: bool, subject: &'a str) -> Self {
+        Self {
+            resource_db,
+            dry_run,
+            subject,
+            changes: Vec::new(),
+            configs: Vec::new()
+        }
+    }
+
+    fn change(&mut self, kind: &str, action: CatalogAction, id: Uuid, name: &str) {
+        self.changes.push(CatalogChange {
+            kind: kind.to_owned(),
+            action: action as i32,
+            id: id.as_bytes().to_vec(),
+            name: name.to_owned()
+        });
+    }
+
+    async fn apply(&mut self, catalog: Catalog) -> Result<(), Status>
+    {
+        // apply in dependency order so every referenced entry is already exist
+        for model in catalog.models {
+            self.apply_model(model).await?;
+        }
+        for schema in catalog.types {
+            self.apply_type(schema).await?;
+        }
+        for gateway in catalog.gateways {
+            self.apply_gateway(gateway).await?;
+        }
+        for device in catalog.devices {
+            self.apply_device(device).await?;
+        }
+        for group in catalog.group_models {
+            self.apply_group(GroupKind::Model, group).await?;
+        }
+        for group in catalog.group_devices {
+            self.apply_group(GroupKind::Device, group).await?;
+        }
+        for group in catalog.group_gateways {
+            self.apply_group(GroupKind::Gateway, group).await?;
+        }
+        for template in catalog.set_templates {
+            self.apply_template(template).await?;
+        }
+        for set in catalog.sets {
+            self.apply_set(set).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_model(&mut self, model: CatalogModel) -> Result<(), Status>
+    {
+        let data_type: Vec<DataType> = model.data_type.iter().map(|&t| DataType::from(t)).collect();
+        match self.resource_db.read_model(model.id).await {
+            Ok(current) => {
+                let current_type: Vec<CatalogDataType> = current.data_type.into_iter().map(CatalogDataType::from).collect();
+                if current_type == model.data_type && current.category == model.category
+                    && current.name == model.name && current.description == model.description
+                {
+                    self.change(KIND_MODEL, CatalogAction::Unchanged, model.id, &model.name);
+                } else {
+                    self.change(KIND_MODEL, CatalogAction::Update, model.id, &model.name);
+                    if !self.dry_run {
+                        self.resource_db.update_model(
+                            model.id, Some(&data_type), Some(&model.category), Some(&model.name), Some(&model.description)
+                        ).await.map_err(handle_error)?;
+                    }
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_MODEL, CatalogAction::Create, model.id, &model.name);
+                if !self.dry_run {
+                    self.resource_db.create_model(
+                        model.id, &data_type, &model.category, &model.name, Some(&model.description)
+                    ).await.map_err(handle_error)?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        // tags and configs of a newly created model are all new
+        let tags = match self.resource_db.list_tag_by_model(model.id).await {
+            Ok(value) => value,
+            Err(sqlx::Error::RowNotFound) => Vec::new(),
+            Err(e) => return Err(handle_error(e))
+        };
+        for tag in model.tags {
+            match tags.iter().find(|t| t.tag == tag.tag) {
+                Some(current) if current.name == tag.name && current.members == tag.members => {
+                    self.change(KIND_TAG, CatalogAction::Unchanged, model.id, &tag.name);
+                },
+                Some(_) => {
+                    self.change(KIND_TAG, CatalogAction::Update, model.id, &tag.name);
+                    if !self.dry_run {
+                        self.resource_db.update_tag(model.id, tag.tag, Some(&tag.name), Some(&tag.members)).await
+                            .map_err(handle_error)?;
+                    }
+                },
+                None => {
+                    self.change(KIND_TAG, CatalogAction::Create, model.id, &tag.name);
+                    if !self.dry_run {
+                        self.resource_db.create_tag(model.id, tag.tag, &tag.name, &tag.members).await
+                            .map_err(handle_error)?;
+                    }
+                }
+            }
+        }
+        let configs = match self.resource_db.list_model_config_by_model(model.id).await {
+            Ok(value) => value,
+            Err(sqlx::Error::RowNotFound) => Vec::new(),
+            Err(e) => return Err(handle_error(e))
+        };
+        for config in model.configs {
+            let value = DataValue::from(config.config.value);
+            // index of existing model config can not be changed, so configs are matched and
+            // compared by name, value and category only
+            match configs.iter().find(|c| c.name == config.config.name) {
+                Some(current) if current.value == value && current.category == config.config.category => {
+                    self.change(KIND_MODEL_CONFIG, CatalogAction::Unchanged, model.id, &config.config.name);
+                },
+                Some(current) => {
+                    self.change(KIND_MODEL_CONFIG, CatalogAction::Update, model.id, &config.config.name);
+                    if !self.dry_run {
+                        history::record_config_baseline(self.resource_db, ConfigKind::Model, current.id).await?;
+                        self.resource_db.update_model_config(current.id, None, Some(value), Some(&config.config.category)).await
+                            .map_err(handle_error)?;
+                        history::record_config(self.resource_db, ConfigKind::Model, current.id, self.subject).await?;
+                    }
+                },
+                None => {
+                    self.change(KIND_MODEL_CONFIG, CatalogAction::Create, model.id, &config.config.name);
+                    if !self.dry_run {
+                        let id = self.resource_db.create_model_config(
+                            model.id, config.index, &config.config.name, value, &config.config.category
+                        ).await.map_err(handle_error)?;
+                        history::record_config(self.resource_db, ConfigKind::Model, id, self.subject).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_type(&mut self, schema: CatalogType) -> Result<(), Status>
+    {
+        let models = match self.resource_db.read_type(schema.id).await {
+            Ok(current) => {
+                if current.name == schema.name && current.description == schema.description {
+                    self.change(KIND_TYPE, CatalogAction::Unchanged, schema.id, &schema.name);
+                } else {
+                    self.change(KIND_TYPE, CatalogAction::Update, schema.id, &schema.name);
+                    if !self.dry_run {
+                        self.resource_db.update_type(schema.id, Some(&schema.name), Some(&schema.description)).await
+                            .map_err(handle_error)?;
+                    }
+                }
+                current.models
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_TYPE, CatalogAction::Create, schema.id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.create_type(schema.id, &schema.name, Some(&schema.description)).await
+                        .map_err(handle_error)?;
+                }
+                Vec::new()
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+        for model_id in schema.models.into_iter().filter(|id| !models.contains(id)) {
+            self.change(KIND_TYPE_MODEL, CatalogAction::Create, schema.id, &model_id.to_string());
+            if !self.dry_run {
+                self.resource_db.add_type_model(schema.id, model_id).await
+                    .map_err(handle_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_gateway(&mut self, gateway: CatalogGateway) -> Result<(), Status>
+    {
+        match self.resource_db.read_gateway(gateway.id).await {
+            Ok(current) => {
+                if current.type_id == gateway.type_id && current.serial_number == gateway.serial_number
+                    && current.name == gateway.name && current.description == gateway.description
+                {
+                    self.change(KIND_GATEWAY, CatalogAction::Unchanged, gateway.id, &gateway.name);
+                } else {
+                    self.change(KIND_GATEWAY, CatalogAction::Update, gateway.id, &gateway.name);
+                    if !self.dry_run {
+                        self.resource_db.update_gateway(
+                            gateway.id, Some(gateway.type_id), Some(&gateway.serial_number), Some(&gateway.name), Some(&gateway.description)
+                        ).await.map_err(handle_error)?;
+                    }
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_GATEWAY, CatalogAction::Create, gateway.id, &gateway.name);
+                if !self.dry_run {
+                    self.resource_db.create_gateway(
+                        gateway.id, gateway.type_id, &gateway.serial_number, &gateway.name, Some(&gateway.description)
+                    ).await.map_err(handle_error)?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        for config in gateway.configs {
+            self.apply_config(ConfigKind::Gateway, gateway.id, config).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_device(&mut self, device: CatalogDevice) -> Result<(), Status>
+    {
+        match self.resource_db.read_device(device.id).await {
+            Ok(current) => {
+                if current.gateway_id == device.gateway_id && current.type_id == device.type_id
+                    && current.serial_number == device.serial_number
+                    && current.name == device.name && current.description == device.description
+                {
+                    self.change(KIND_DEVICE, CatalogAction::Unchanged, device.id, &device.name);
+                } else {
+                    self.change(KIND_DEVICE, CatalogAction::Update, device.id, &device.name);
+                    if !self.dry_run {
+                        self.resource_db.update_device(
+                            device.id, Some(device.gateway_id), Some(device.type_id), Some(&device.serial_number),
+                            Some(&device.name), Some(&device.description)
+                        ).await.map_err(handle_error)?;
+                    }
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_DEVICE, CatalogAction::Create, device.id, &device.name);
+                if !self.dry_run {
+                    self.resource_db.create_device(
+                        device.id, device.gateway_id, device.type_id, &device.serial_number, &device.name, Some(&device.description)
+                    ).await.map_err(handle_error)?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        for config in device.configs {
+            self.apply_config(ConfigKind::Device, device.id, config).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_config(&mut self, kind: ConfigKind, owner_id: Uuid, config: CatalogConfig) -> Result<(), Status>
+    {
+        let entry = ConfigEntry {
+            kind,
+            owner_id,
+            name: config.name,
+            value: config.value.into(),
+            category: config.category
+        };
+        let label = if kind == ConfigKind::Gateway { KIND_GATEWAY_CONFIG } else { KIND_DEVICE_CONFIG };
+        let plan = plan_config(self.resource_db, &entry).await?;
+        let action = match plan {
+            ConfigPlan::Unchanged => CatalogAction::Unchanged,
+            ConfigPlan::Update(_) => CatalogAction::Update,
+            ConfigPlan::Create => CatalogAction::Create
+        };
+        self.change(label, action, owner_id, &entry.name);
+        if !self.dry_run {
+            if let Some(config) = apply_config(self.resource_db, entry, plan, self.subject).await? {
+                self.configs.push(config);
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_group(&mut self, kind: GroupKind, group: CatalogGroup) -> Result<(), Status>
+    {
+        let label = match kind {
+            GroupKind::Model => KIND_GROUP_MODEL,
+            GroupKind::Device => KIND_GROUP_DEVICE,
+            GroupKind::Gateway => KIND_GROUP_GATEWAY
+        };
+        let result = match kind {
+            GroupKind::Model => self.resource_db.read_group_model(group.id).await
+                .map(|g| (g.name, g.category, g.description, g.models)),
+            GroupKind::Device => self.resource_db.read_group_device(group.id).await
+                .map(|g| (g.name, g.category, g.description, g.devices)),
+            GroupKind::Gateway => self.resource_db.read_group_gateway(group.id).await
+                .map(|g| (g.name, g.category, g.description, g.gateways))
+        };
+        let members = match result {
+            Ok((name, category, description, members)) => {
+                if name == group.name && category == group.category && description == group.description {
+                    self.change(label, CatalogAction::Unchanged, group.id, &group.name);
+                } else {
+                    self.change(label, CatalogAction::Update, group.id, &group.name);
+                    if !self.dry_run {
+                        let (name, category, description) = (Some(group.name.as_str()), Some(group.category.as_str()), Some(group.description.as_str()));
+                        let result = match kind {
+                            GroupKind::Model => self.resource_db.update_group_model(group.id, name, category, description).await,
+                            GroupKind::Device => self.resource_db.update_group_device(group.id, name, category, description).await,
+                            GroupKind::Gateway => self.resource_db.update_group_gateway(group.id, name, category, description).await
+                        };
+                        result.map_err(handle_error)?;
+                    }
+                }
+                members
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(label, CatalogAction::Create, group.id, &group.name);
+                if !self.dry_run {
+                    let description = Some(group.description.as_str());
+                    let result = match kind {
+                        GroupKind::Model => self.resource_db.create_group_model(group.id, &group.name, &group.category, description).await,
+                        GroupKind::Device => self.resource_db.create_group_device(group.id, &group.name, &group.category, description).await,
+                        GroupKind::Gateway => self.resource_db.create_group_gateway(group.id, &group.name, &group.category, description).await
+                    };
+                    result.map_err(handle_error)?;
+                }
+                Vec::new()
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+        for member_id in group.members.into_iter().filter(|id| !members.contains(id)) {
+            self.change(KIND_GROUP_MEMBER, CatalogAction::Create, group.id, &member_id.to_string());
+            if !self.dry_run {
+                let result = match kind {
+                    GroupKind::Model => self.resource_db.add_group_model_member(group.id, member_id).await,
+                    GroupKind::Device => self.resource_db.add_group_device_member(group.id, member_id).await,
+                    GroupKind::Gateway => self.resource_db.add_group_gateway_member(group.id, member_id).await
+                };
+                result.map_err(handle_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_template(&mut self, template: CatalogTemplate) -> Result<(), Status>
+    {
+        let members = match self.resource_db.read_set_template(template.id).await {
+            Ok(current) => {
+                if current.name == template.name && current.description == template.description {
+                    self.change(KIND_SET_TEMPLATE, CatalogAction::Unchanged, template.id, &template.name);
+                } else {
+                    self.change(KIND_SET_TEMPLATE, CatalogAction::Update, template.id, &template.name);
+                    if !self.dry_run {
+                        self.resource_db.update_set_template(template.id, Some(&template.name), Some(&template.description)).await
+                            .map_err(handle_error)?;
+                    }
+                }
+                current.members.into_iter().map(|m| (m.type_id, m.model_id)).collect()
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_SET_TEMPLATE, CatalogAction::Create, template.id, &template.name);
+                if !self.dry_run {
+                    self.resource_db.create_set_template(template.id, &template.name, Some(&template.description)).await
+                        .map_err(handle_error)?;
+                }
+                Vec::new()
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+        for member in template.members.into_iter().filter(|m| !members.contains(&(m.type_id, m.model_id))) {
+            self.change(KIND_SET_MEMBER, CatalogAction::Create, template.id, &member.model_id.to_string());
+            if !self.dry_run {
+                self.resource_db.add_set_template_member(template.id, member.type_id, member.model_id, &member.data_index).await
+                    .map_err(handle_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_set(&mut self, set: CatalogSet) -> Result<(), Status>
+    {
+        let members = match self.resource_db.read_set(set.id).await {
+            Ok(current) => {
+                if current.template_id == set.template_id && current.name == set.name && current.description == set.description {
+                    self.change(KIND_SET, CatalogAction::Unchanged, set.id, &set.name);
+                } else {
+                    self.change(KIND_SET, CatalogAction::Update, set.id, &set.name);
+                    if !self.dry_run {
+                        self.resource_db.update_set(set.id, Some(set.template_id), Some(&set.name), Some(&set.description)).await
+                            .map_err(handle_error)?;
+                    }
+                }
+                current.members.into_iter().map(|m| (m.device_id, m.model_id)).collect()
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_SET, CatalogAction::Create, set.id, &set.name);
+                if !self.dry_run {
+                    self.resource_db.create_set(set.id, set.template_id, &set.name, Some(&set.description)).await
+                        .map_err(handle_error)?;
+                }
+                Vec::new()
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+        for member in set.members.into_iter().filter(|m| !members.contains(&(m.device_id, m.model_id))) {
+            self.change(KIND_SET_MEMBER, CatalogAction::Create, set.id, &member.model_id.to_string());
+            if !self.dry_run {
+                self.resource_db.add_set_member(set.id, member.device_id, member.model_id, &member.data_index).await
+                    .map_err(handle_error)?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+impl AccessValidator for CatalogServer {
+
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
+        self
+    }
+
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
+    }
+
+    fn accesses(&self) -> Vec<AccessSchema> {
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for CatalogServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
+    }
+
+}
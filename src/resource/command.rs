@@ -0,0 +1,308 @@
+use tonic::{Request, Response, Status};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+use rmcs_resource_db::{Resource, DataType, DataValue, CommandStatus};
+use rmcs_resource_api::command::command_service_server::CommandService;
+use rmcs_resource_api::command::{
+    CommandSchema, CommandId, CommandDevice, CommandGateway, CommandAck, GatewayId,
+    CommandReadResponse, CommandListResponse, CommandCreateResponse, CommandChangeResponse
+};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
+use super::{
+    READ_COMMAND, CREATE_COMMAND, UPDATE_COMMAND, DELETE_COMMAND,
+    COMMAND_STATUS_INVALID
+};
+use crate::utility::handle_error;
+
+const COMMAND_CHANNEL_SIZE: usize = 256;
+
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_COMMAND, CREATE_COMMAND, UPDATE_COMMAND, DELETE_COMMAND
+];
+
+#[derive(Debug)]
+pub struct CommandServer {
+    resource_db: Resource,
+    accesses: AccessTable,
+    sender: broadcast::Sender<CommandSchema>
+}
+
+impl CommandServer {
+    pub fn new(resource_db: Resource) -> Self {
+        let (sender, _) = broadcast::channel(COMMAND_CHANNEL_SIZE);
+        Self {
+            resource_db,
+            accesses: AccessTable::default(),
+            sender
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CommandService for CommandServer {
+
+    type StreamCommandStream = ReceiverStream<Result<CommandSchema, Status>>;
+
+    async fn read_command(&self, request: Request<CommandId>)
+        -> Result<Response<CommandReadResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let result = resource_db.read_command(request.id).await;
+        let result = match result {
+            Ok(value) => {
+                scope.check_device_id(value.device_id)?;
+                Some(value.into())
+            },
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(CommandReadResponse { result }))
+    }
+
+    async fn list_command_by_device(&self, request: Request<CommandDevice>)
+        -> Result<Response<CommandListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_command_by_device(
+            Uuid::from_slice(&request.device_id).unwrap_or_default(),
+            request.status.map(|s| CommandStatus::from(s))
+        ).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(CommandListResponse { results }))
+    }
+
+    async fn list_command_by_gateway(&self, request: Request<CommandGateway>)
+        -> Result<Response<CommandListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.gateway_id)?;
+        let result = resource_db.list_command_by_gateway(
+            Uuid::from_slice(&request.gateway_id).unwrap_or_default(),
+            request.status.map(|s| CommandStatus::from(s))
+        ).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(CommandListResponse { results }))
+    }
+
+    async fn fetch_command(&self, request: Request<GatewayId>)
+        -> Result<Response<CommandListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.id)?;
+        let gateway_id = Uuid::from_slice(&request.id).unwrap_or_default();
+        let results = fetch_pending(&resource_db, gateway_id).await?;
+        Ok(Response::new(CommandListResponse { results }))
+    }
+
+    async fn stream_command(&self, request: Request<GatewayId>)
+        -> Result<Response<Self::StreamCommandStream>, Status>
+    {
+        self.validate(request.extensions(), READ_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.id)?;
+        let gateway_id = Uuid::from_slice(&request.id).unwrap_or_default();
+        // gateway must belong to tenant of the token, gateway ids are unique across tenants so
+        // filtering notified commands by gateway only forwards commands of the same tenant
+        resource_db.read_device(gateway_id).await
+            .map_err(|e| handle_error(e))?;
+        // subscribe before reading pending commands so commands created in between are not missed
+        let mut receiver = self.sender.subscribe();
+        let mut pending = list_pending(&resource_db, gateway_id).await?;
+        let (tx, rx) = mpsc::channel(COMMAND_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            loop {
+                // a command listed again after lagging is skipped since it is already claimed
+                for command in pending.drain(..) {
+                    if !send_command(&tx, &resource_db, command).await {
+                        return;
+                    }
+                }
+                // wait for newly created command, and stop as soon as the gateway disconnect so
+                // no command is taken by an orphaned stream
+                let received = tokio::select! {
+                    _ = tx.closed() => return,
+                    received = receiver.recv() => received
+                };
+                match received {
+                    Ok(command) if command.gateway_id == gateway_id.as_bytes().to_vec() => pending.push(command),
+                    Ok(_) => (),
+                    // notifications are dropped, read pending commands again from database
+                    Err(broadcast::error::RecvError::Lagged(_)) => match list_pending(&resource_db, gateway_id).await {
+                        Ok(commands) => pending = commands,
+                        Err(e) => {
+                            tx.send(Err(e)).await.ok();
+                            return;
+                        }
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn create_command(&self, request: Request<CommandSchema>)
+        -> Result<Response<CommandCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), CREATE_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.create_command(
+            Uuid::from_slice(&request.device_id).unwrap_or_default(),
+            &request.name,
+            DataValue::from_bytes(
+                &request.payload_bytes,
+                DataType::from(request.payload_type)
+            )
+        ).await;
+        let id = match result {
+            Ok(value) => value,
+            Err(e) => return Err(handle_error(e))
+        };
+        // notify gateway streams, the command stays pending in database when no gateway is listening
+        if let Ok(command) = resource_db.read_command(id).await {
+            self.sender.send(command.into()).ok();
+        }
+        Ok(Response::new(CommandCreateResponse { id }))
+    }
+
+    async fn ack_command(&self, request: Request<CommandAck>)
+        -> Result<Response<CommandChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_command(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let status = CommandStatus::from(request.status);
+        if status != CommandStatus::Succeeded && status != CommandStatus::Failed {
+            return Err(Status::invalid_argument(COMMAND_STATUS_INVALID));
+        }
+        let result = resource_db.update_command_status(
+            request.id,
+            status,
+            request.result_bytes.map(|s| {
+                DataValue::from_bytes(
+                    &s,
+                    DataType::from(request.result_type.unwrap_or_default())
+                )
+            })
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(CommandChangeResponse { }))
+    }
+
+    async fn delete_command(&self, request: Request<CommandId>)
+        -> Result<Response<CommandChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), DELETE_COMMAND)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_command(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.delete_command(request.id).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(CommandChangeResponse { }))
+    }
+
+}
+
+async fn fetch_pending(resource_db: &Resource, gateway_id: Uuid)
+    -> Result<Vec<CommandSchema>, Status>
+{
+    // mark pending commands of a gateway as delivered and return them in one update, so concurrent
+    // fetches never deliver the same command twice
+    let commands = resource_db.deliver_command_by_gateway(gateway_id).await
+        .map_err(|e| handle_error(e))?;
+    Ok(commands.into_iter().map(|c| c.into()).collect())
+}
+
+async fn list_pending(resource_db: &Resource, gateway_id: Uuid)
+    -> Result<Vec<CommandSchema>, Status>
+{
+    let commands = resource_db.list_command_by_gateway(gateway_id, Some(CommandStatus::Pending)).await
+        .map_err(|e| handle_error(e))?;
+    Ok(commands.into_iter().map(|c| c.into()).collect())
+}
+
+/// Claim a command by changing its status from pending to delivered and send it to gateway stream
+/// only if this stream won the claim, so a command is never delivered by two streams or by a
+/// stream and a fetch. Stream capacity is reserved first so a claimed command is not dropped
+/// because the stream is full. Return false when the stream is closed.
+async fn send_command(tx: &mpsc::Sender<Result<CommandSchema, Status>>, resource_db: &Resource, command: CommandSchema)
+    -> bool
+{
+    let permit = match tx.reserve().await {
+        Ok(permit) => permit,
+        Err(_) => return false
+    };
+    match resource_db.deliver_command(command.id).await {
+        Ok(true) => permit.send(Ok(CommandSchema { status: CommandStatus::Delivered.into(), ..command })),
+        Ok(false) => (),
+        Err(e) => permit.send(Err(handle_error(e)))
+    }
+    true
+}
+
+impl AccessValidator for CommandServer {
+
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
+        self
+    }
+
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
+    }
+
+    fn accesses(&self) -> Vec<AccessSchema> {
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for CommandServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
+    }
+
+}
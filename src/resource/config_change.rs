@@ -0,0 +1,118 @@
+use tokio::sync::broadcast;
+use tonic::Status;
+use chrono::Utc;
+use uuid::Uuid;
+use rmcs_resource_db::{Resource, ConfigKind, DataValue};
+use rmcs_resource_api::device::{ConfigChange, ConfigAction};
+use crate::utility::config::CONFIG_CHANGES;
+use crate::utility::handle_error;
+use super::history;
+
+const CONFIG_CHANNEL_SIZE: usize = 256;
+
+/// Sender of config changes shared by every service of this server, so configs changed by
+/// provision and catalog import reach gateways streaming from device service
+pub(crate) fn config_changes() -> &'static broadcast::Sender<ConfigChange> {
+    CONFIG_CHANGES.get_or_init(|| broadcast::channel(CONFIG_CHANNEL_SIZE).0)
+}
+
+pub(crate) async fn config_change(resource_db: &Resource, kind: ConfigKind, action: ConfigAction, id: i32) -> Option<ConfigChange>
+{
+    // get device and gateway owning the config to route the notification, model and type configs
+    // are shared by many devices and are read through resolved device configs
+    let (device_id, gateway_id) = match kind {
+        ConfigKind::Gateway => {
+            let config = resource_db.read_gateway_config(id).await.ok()?;
+            (config.gateway_id, config.gateway_id)
+        },
+        ConfigKind::Model | ConfigKind::Type => return None,
+        ConfigKind::Device => {
+            let config = resource_db.read_device_config(id).await.ok()?;
+            let device = resource_db.read_device(config.device_id).await.ok()?;
+            (device.id, device.gateway_id)
+        }
+    };
+    Some(ConfigChange {
+        kind: kind.into(),
+        action: action as i32,
+        config_id: id,
+        device_id: device_id.as_bytes().to_vec(),
+        gateway_id: gateway_id.as_bytes().to_vec(),
+        timestamp: Utc::now().timestamp_micros()
+    })
+}
+
+pub(crate) async fn notify_config(resource_db: &Resource, kind: ConfigKind, action: ConfigAction, id: i32)
+{
+    if let Some(change) = config_change(resource_db, kind, action, id).await {
+        config_changes().send(change).ok();
+    }
+}
+
+/// Wanted state of a gateway or device config, identified by owner and config name
+pub(crate) struct ConfigEntry {
+    pub kind: ConfigKind,
+    pub owner_id: Uuid,
+    pub name: String,
+    pub value: DataValue,
+    pub category: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConfigPlan {
+    Unchanged,
+    Update(i32),
+    Create
+}
+
+pub(crate) async fn plan_config(resource_db: &Resource, entry: &ConfigEntry) -> Result<ConfigPlan, Status>
+{
+    let result = match entry.kind {
+        ConfigKind::Gateway => resource_db.list_gateway_config_by_gateway(entry.owner_id).await
+            .map(|v| v.into_iter().map(|c| (c.id, c.name, c.value, c.category)).collect()),
+        _ => resource_db.list_device_config_by_device(entry.owner_id).await
+            .map(|v| v.into_iter().map(|c| (c.id, c.name, c.value, c.category)).collect())
+    };
+    let configs: Vec<(i32, String, DataValue, String)> = match result {
+        Ok(value) => value,
+        Err(sqlx::Error::RowNotFound) => Vec::new(),
+        Err(e) => return Err(handle_error(e))
+    };
+    let plan = match configs.into_iter().find(|c| c.1 == entry.name) {
+        Some((_, _, value, category)) if value == entry.value && category == entry.category => ConfigPlan::Unchanged,
+        Some((id, _, _, _)) => ConfigPlan::Update(id),
+        None => ConfigPlan::Create
+    };
+    Ok(plan)
+}
+
+/// Write a planned gateway or device config change and store it as a new history version,
+/// returned change should be notified after the change is committed
+pub(crate) async fn apply_config(resource_db: &Resource, entry: ConfigEntry, plan: ConfigPlan, subject: &str)
+    -> Result<Option<(ConfigKind, ConfigAction, i32)>, Status>
+{
+    let kind = entry.kind;
+    let (action, id) = match plan {
+        ConfigPlan::Unchanged => return Ok(None),
+        ConfigPlan::Update(id) => {
+            history::record_config_baseline(resource_db, kind, id).await?;
+            let result = if kind == ConfigKind::Gateway {
+                resource_db.update_gateway_config(id, None, Some(entry.value), Some(&entry.category)).await
+            } else {
+                resource_db.update_device_config(id, None, Some(entry.value), Some(&entry.category)).await
+            };
+            result.map_err(handle_error)?;
+            (ConfigAction::Update, id)
+        },
+        ConfigPlan::Create => {
+            let result = if kind == ConfigKind::Gateway {
+                resource_db.create_gateway_config(entry.owner_id, &entry.name, entry.value, &entry.category).await
+            } else {
+                resource_db.create_device_config(entry.owner_id, &entry.name, entry.value, &entry.category).await
+            };
+            (ConfigAction::Create, result.map_err(handle_error)?)
+        }
+    };
+    history::record_config(resource_db, kind, id, subject).await?;
+    Ok(Some((kind, action, id)))
+}
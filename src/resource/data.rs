@@ -9,25 +9,30 @@ use rmcs_resource_api::data::{
     DataReadResponse, DataListResponse, DataChangeResponse, DataSetReadResponse, DataSetListResponse,
     TimestampReadResponse, TimestampListResponse, DataCountResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_DATA, CREATE_DATA, DELETE_DATA
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_DATA, CREATE_DATA, DELETE_DATA
+];
+
 #[derive(Debug)]
 pub struct DataServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl DataServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -39,8 +44,11 @@ impl DataService for DataServer {
         -> Result<Response<DataReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_data(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.read_data(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -57,8 +65,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -75,8 +86,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -93,8 +107,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -112,8 +129,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_by_number_before(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_by_number_before(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -131,8 +151,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_by_number_after(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_by_number_after(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -150,8 +173,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_by_time(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_by_time(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -168,8 +194,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -186,8 +215,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -205,8 +237,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_by_number_before(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_by_number_before(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -224,8 +259,11 @@ impl DataService for DataServer {
         -> Result<Response<DataListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_by_number_after(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_by_number_after(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -243,8 +281,11 @@ impl DataService for DataServer {
         -> Result<Response<DataSetReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_data_set(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.read_data_set(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.tag.map(|t| t as i16)
@@ -260,8 +301,11 @@ impl DataService for DataServer {
         -> Result<Response<DataSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_set_by_time(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_data_set_by_time(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.tag.map(|t| t as i16)
@@ -277,8 +321,11 @@ impl DataService for DataServer {
         -> Result<Response<DataSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_set_by_latest(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_data_set_by_latest(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
             request.tag.map(|t| t as i16)
@@ -294,8 +341,11 @@ impl DataService for DataServer {
         -> Result<Response<DataSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_set_by_range(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_data_set_by_range(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000),
@@ -312,8 +362,11 @@ impl DataService for DataServer {
         -> Result<Response<DataChangeResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_data(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.create_data(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -334,7 +387,10 @@ impl DataService for DataServer {
         -> Result<Response<DataChangeResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
+        scope.check_devices(&request.schemas.iter().map(|r| r.device_id.clone()).collect::<Vec<Vec<u8>>>())?;
         let (device_ids, model_ids, timestamps, data_vec, tags): (Vec<Uuid>, Vec<Uuid>, Vec<DateTime<Utc>>, Vec<Vec<DataValue>>, Vec<i16>) 
             = request.schemas.into_iter().map(|r| {(
                 Uuid::from_slice(&r.device_id).unwrap_or_default(),
@@ -347,7 +403,7 @@ impl DataService for DataServer {
                 r.tag as i16
             )}).collect();
         let data_multiple: Vec<&[DataValue]> = data_vec.iter().map(|d| d.as_slice()).collect();
-        let result = self.resource_db.create_data_multiple(
+        let result = resource_db.create_data_multiple(
             &device_ids,
             &model_ids,
             &timestamps,
@@ -365,8 +421,11 @@ impl DataService for DataServer {
         -> Result<Response<DataChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_data(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.delete_data(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -383,8 +442,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_data_timestamp(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.read_data_timestamp(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -401,8 +463,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_timestamp_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_timestamp_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -419,8 +484,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_timestamp_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_data_timestamp_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -438,8 +506,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_data_group_timestamp(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_data_group_timestamp(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000),
@@ -456,8 +527,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_timestamp_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_timestamp_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -474,8 +548,11 @@ impl DataService for DataServer {
         -> Result<Response<TimestampListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_data_group_timestamp_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_data_group_timestamp_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -493,8 +570,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_data(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag.map(|t| t as i16)
@@ -510,8 +590,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data_by_latest(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_data_by_latest(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -528,8 +611,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.count_data_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -547,8 +633,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data_group(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_data_group(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             request.tag.map(|t| t as i16)
@@ -564,8 +653,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data_group_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_data_group_by_latest(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.latest * 1000),
@@ -582,8 +674,11 @@ impl DataService for DataServer {
         -> Result<Response<DataCountResponse>, Status>
     {
         self.validate(request.extensions(), READ_DATA)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.count_data_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.count_data_group_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -601,21 +696,25 @@ impl DataService for DataServer {
 
 impl AccessValidator for DataServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_DATA, CREATE_DATA, DELETE_DATA
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for DataServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
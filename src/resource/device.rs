@@ -1,43 +1,68 @@
 use tonic::{Request, Response, Status};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use uuid::Uuid;
-use rmcs_resource_db::{Resource, DataType, DataValue};
+use rmcs_resource_db::{Resource, DataType, DataValue, ConfigKind};
 use rmcs_resource_api::device::device_service_server::DeviceService;
 use rmcs_resource_api::device::{
-    ConfigChangeResponse, ConfigCreateResponse, ConfigId, ConfigListResponse, ConfigReadResponse, ConfigSchema, ConfigUpdate, DeviceChangeResponse, DeviceCreateResponse, DeviceId, DeviceIds, DeviceListResponse, DeviceName, DeviceOption, DeviceReadResponse, DeviceSchema, DeviceUpdate, GatewayChangeResponse, GatewayCreateResponse, GatewayId, GatewayIds, GatewayListResponse, GatewayName, GatewayOption, GatewayReadResponse, GatewaySchema, GatewayUpdate, SerialNumber, TypeChangeResponse, TypeCreateResponse, TypeId, TypeIds, TypeListResponse, TypeModel, TypeName, TypeOption, TypeReadResponse, TypeSchema, TypeUpdate
+    ConfigChangeResponse, ConfigCreateResponse, ConfigId, ConfigListResponse, ConfigReadResponse, ConfigSchema, ConfigUpdate, ConfigVersion, ConfigVersionPair, ConfigHistoryListResponse, ConfigDiffResponse, ConfigResolved, ConfigResolveResponse, ConfigChange, ConfigAction, DeviceChangeResponse, DeviceCreateResponse, DeviceId, DeviceIds, DeviceListResponse, DeviceName, DeviceOption, DeviceReadResponse, DeviceSchema, DeviceUpdate, GatewayChangeResponse, GatewayCreateResponse, GatewayId, GatewayIds, GatewayListResponse, GatewayName, GatewayOption, GatewayReadResponse, GatewaySchema, GatewayUpdate, SerialNumber, TypeChangeResponse, TypeCreateResponse, TypeId, TypeIds, TypeListResponse, TypeModel, TypeName, TypeOption, TypeReadResponse, TypeSchema, TypeUpdate
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
+use super::history;
+use super::config_change::{config_changes, config_change, notify_config};
 use super::{
     READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, DELETE_DEVICE,
     READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG, DELETE_DEVICE_CONFIG,
-    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, DELETE_TYPE, CHANGE_TYPE_MODEL
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, DELETE_TYPE, CHANGE_TYPE_MODEL,
+    CONFIG_STREAM_LAGGED
 };
 use crate::utility::handle_error;
 
+const CONFIG_CHANNEL_SIZE: usize = 256;
+
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, DELETE_DEVICE,
+    READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG, DELETE_DEVICE_CONFIG,
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, DELETE_TYPE, CHANGE_TYPE_MODEL
+];
+
 #[derive(Debug)]
 pub struct DeviceServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl DeviceServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
+
+    async fn notify_config(&self, kind: ConfigKind, action: ConfigAction, id: i32)
+    {
+        notify_config(&self.resource_db, kind, action, id).await;
+    }
 }
 
 #[tonic::async_trait]
 impl DeviceService for DeviceServer {
+
+    type StreamConfigChangeStream = ReceiverStream<Result<ConfigChange, Status>>;
+
     async fn read_device(&self, request: Request<DeviceId>)
         -> Result<Response<DeviceReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.read_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -49,10 +74,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_device_by_sn(&request.serial_number).await;
+        let result = resource_db.read_device_by_sn(&request.serial_number).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceReadResponse { result }))
@@ -62,12 +92,17 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_by_ids(
+        let result = resource_db.list_device_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceListResponse { results }))
@@ -77,10 +112,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_by_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_device_by_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceListResponse { results }))
@@ -90,10 +130,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_by_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_device_by_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceListResponse { results }))
@@ -103,10 +148,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_by_name(&request.name).await;
+        let result = resource_db.list_device_by_name(&request.name).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceListResponse { results }))
@@ -116,14 +166,19 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_option(
+        let result = resource_db.list_device_option(
             request.gateway_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.type_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(DeviceListResponse { results }))
@@ -133,8 +188,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_device(
+        scope.check_device(&request.gateway_id)?;
+        let result = resource_db.create_device(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.gateway_id).unwrap_or_default(),
             Uuid::from_slice(&request.device_type.unwrap_or_default().id).unwrap_or_default(),
@@ -153,8 +211,12 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_device(
+        scope.check_device(&request.id)?;
+        request.gateway_id.as_deref().map(|id| scope.check_device(id)).unwrap_or(Ok(()))?;
+        let result = resource_db.update_device(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.gateway_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.type_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -173,8 +235,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<DeviceChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.delete_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -186,8 +251,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.read_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -199,10 +267,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_gateway_by_sn(&request.serial_number).await;
+        let result = resource_db.read_gateway_by_sn(&request.serial_number).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GatewayReadResponse { result }))
@@ -212,12 +285,17 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_gateway_by_ids(
+        let result = resource_db.list_gateway_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GatewayListResponse { results }))
@@ -227,10 +305,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_gateway_by_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_gateway_by_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GatewayListResponse { results }))
@@ -240,10 +323,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_gateway_by_name(&request.name).await;
+        let result = resource_db.list_gateway_by_name(&request.name).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GatewayListResponse { results }))
@@ -253,13 +341,18 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_gateway_option(
+        let result = resource_db.list_gateway_option(
             request.type_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GatewayListResponse { results }))
@@ -269,8 +362,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_gateway(
+        scope.check_unrestricted()?;
+        let result = resource_db.create_gateway(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.gateway_type.unwrap_or_default().id).unwrap_or_default(),
             &request.serial_number,
@@ -288,8 +384,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<GatewayChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_gateway(
+        scope.check_device(&request.id)?;
+        let result = resource_db.update_gateway(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.type_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.serial_number.as_deref(),
@@ -307,8 +406,11 @@ impl DeviceService for DeviceServer {
     -> Result<Response<GatewayChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_DEVICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.delete_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -320,10 +422,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_device_config(request.id).await;
+        let result = resource_db.read_device_config(request.id).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.device_id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(ConfigReadResponse { result }))
@@ -333,8 +440,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_device_config_by_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.list_device_config_by_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -346,8 +456,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_device_config(
+        scope.check_device(&request.device_id)?;
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let result = transaction.create_device_config(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             &request.name,
             DataValue::from_bytes(
@@ -360,6 +477,10 @@ impl DeviceService for DeviceServer {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Device, id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Device, ConfigAction::Create, id).await;
         Ok(Response::new(ConfigCreateResponse { id }))
     }
 
@@ -367,8 +488,19 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_device_config(
+        if scope.is_restricted() {
+            let value = resource_db.read_device_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_baseline(&transaction, ConfigKind::Device, request.id).await?;
+        let result = transaction.update_device_config(
             request.id,
             request.name.as_deref(),
             request.config_bytes.map(|s| {
@@ -383,6 +515,10 @@ impl DeviceService for DeviceServer {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Device, request.id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Device, ConfigAction::Update, request.id).await;
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
@@ -390,23 +526,166 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_device_config(request.id).await;
+        if scope.is_restricted() {
+            let value = resource_db.read_device_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let change = config_change(&self.resource_db, ConfigKind::Device, ConfigAction::Delete, request.id).await;
+        // deletion is stored as the last history version of the config
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_delete(&transaction, ConfigKind::Device, request.id, &subject).await?;
+        let result = transaction.delete_device_config(request.id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        transaction.commit().await
+            .map_err(handle_error)?;
+        if let Some(change) = change {
+            config_changes().send(change).ok();
+        }
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
+    async fn list_device_config_history(&self, request: Request<ConfigId>)
+        -> Result<Response<ConfigHistoryListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_device_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.list_config_history(ConfigKind::Device, request.id).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ConfigHistoryListResponse { results }))
+    }
+
+    async fn diff_device_config(&self, request: Request<ConfigVersionPair>)
+        -> Result<Response<ConfigDiffResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_device_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let diff = history::diff_config(
+            &resource_db,
+            ConfigKind::Device,
+            request.id,
+            request.version_1,
+            request.version_2
+        ).await?;
+        Ok(Response::new(ConfigDiffResponse {
+            version_1: Some(diff.version_1.into()),
+            version_2: Some(diff.version_2.into()),
+            fields: diff.fields
+        }))
+    }
+
+    async fn rollback_device_config(&self, request: Request<ConfigVersion>)
+        -> Result<Response<ConfigChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_device_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::rollback_config(&transaction, ConfigKind::Device, request.id, request.version, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Device, ConfigAction::Update, request.id).await;
+        Ok(Response::new(ConfigChangeResponse { }))
+    }
+
+    async fn resolve_device_config(&self, request: Request<DeviceId>)
+        -> Result<Response<ConfigResolveResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.id)?;
+        let results = resolve_config(
+            &resource_db,
+            Uuid::from_slice(&request.id).unwrap_or_default()
+        ).await?;
+        Ok(Response::new(ConfigResolveResponse { results }))
+    }
+
+    async fn stream_config_change(&self, request: Request<GatewayId>)
+        -> Result<Response<Self::StreamConfigChangeStream>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        scope.check_device(&request.id)?;
+        // gateway must belong to tenant of the token, gateway ids are unique across tenants so
+        // filtering changes by gateway only forwards changes of the same tenant
+        resource_db.read_device(Uuid::from_slice(&request.id).unwrap_or_default()).await
+            .map_err(|e| handle_error(e))?;
+        let gateway_id = request.id;
+        let mut receiver = config_changes().subscribe();
+        let (tx, rx) = mpsc::channel(CONFIG_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            loop {
+                let result = tokio::select! {
+                    result = receiver.recv() => result,
+                    _ = tx.closed() => break
+                };
+                // forward changes of the gateway and its devices, end the stream when notifications are lost
+                // so the gateway can reconnect and reload all configs
+                let (item, lagged) = match result {
+                    Ok(change) => {
+                        if change.gateway_id != gateway_id {
+                            continue;
+                        }
+                        (Ok(change), false)
+                    },
+                    Err(broadcast::error::RecvError::Lagged(_)) => (Err(Status::data_loss(CONFIG_STREAM_LAGGED)), true),
+                    Err(broadcast::error::RecvError::Closed) => break
+                };
+                if tx.send(item).await.is_err() || lagged {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
     async fn read_gateway_config(&self, request: Request<ConfigId>)
         -> Result<Response<ConfigReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_gateway_config(request.id).await;
+        let result = resource_db.read_gateway_config(request.id).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.gateway_id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(ConfigReadResponse { result }))
@@ -416,8 +695,11 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_gateway_config_by_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_device(&request.id)?;
+        let result = resource_db.list_gateway_config_by_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -429,8 +711,15 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_gateway_config(
+        scope.check_device(&request.device_id)?;
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let result = transaction.create_gateway_config(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             &request.name,
             DataValue::from_bytes(
@@ -443,6 +732,10 @@ impl DeviceService for DeviceServer {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Gateway, id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Gateway, ConfigAction::Create, id).await;
         Ok(Response::new(ConfigCreateResponse { id }))
     }
 
@@ -450,8 +743,19 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_gateway_config(
+        if scope.is_restricted() {
+            let value = resource_db.read_gateway_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.gateway_id)?;
+        }
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_baseline(&transaction, ConfigKind::Gateway, request.id).await?;
+        let result = transaction.update_gateway_config(
             request.id,
             request.name.as_deref(),
             request.config_bytes.map(|s| {
@@ -466,6 +770,10 @@ impl DeviceService for DeviceServer {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Gateway, request.id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Gateway, ConfigAction::Update, request.id).await;
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
@@ -473,12 +781,94 @@ impl DeviceService for DeviceServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_gateway_config(request.id).await;
+        if scope.is_restricted() {
+            let value = resource_db.read_gateway_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.gateway_id)?;
+        }
+        let change = config_change(&self.resource_db, ConfigKind::Gateway, ConfigAction::Delete, request.id).await;
+        // deletion is stored as the last history version of the config
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_delete(&transaction, ConfigKind::Gateway, request.id, &subject).await?;
+        let result = transaction.delete_gateway_config(request.id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        transaction.commit().await
+            .map_err(handle_error)?;
+        if let Some(change) = change {
+            config_changes().send(change).ok();
+        }
+        Ok(Response::new(ConfigChangeResponse { }))
+    }
+
+    async fn list_gateway_config_history(&self, request: Request<ConfigId>)
+        -> Result<Response<ConfigHistoryListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_gateway_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.gateway_id)?;
+        }
+        let result = resource_db.list_config_history(ConfigKind::Gateway, request.id).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ConfigHistoryListResponse { results }))
+    }
+
+    async fn diff_gateway_config(&self, request: Request<ConfigVersionPair>)
+        -> Result<Response<ConfigDiffResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_gateway_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.gateway_id)?;
+        }
+        let diff = history::diff_config(
+            &resource_db,
+            ConfigKind::Gateway,
+            request.id,
+            request.version_1,
+            request.version_2
+        ).await?;
+        Ok(Response::new(ConfigDiffResponse {
+            version_1: Some(diff.version_1.into()),
+            version_2: Some(diff.version_2.into()),
+            fields: diff.fields
+        }))
+    }
+
+    async fn rollback_gateway_config(&self, request: Request<ConfigVersion>)
+        -> Result<Response<ConfigChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_DEVICE_CONFIG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        if scope.is_restricted() {
+            let value = resource_db.read_gateway_config(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.gateway_id)?;
+        }
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::rollback_config(&transaction, ConfigKind::Gateway, request.id, request.version, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        self.notify_config(ConfigKind::Gateway, ConfigAction::Update, request.id).await;
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
@@ -486,8 +876,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.read_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -499,8 +890,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeListResponse>, Status>
     {
         self.validate(request.extensions(), READ_DEVICE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_type_by_ids(
+        let result = resource_db.list_type_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -514,8 +906,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeListResponse>, Status>
     {
         self.validate(request.extensions(), READ_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_type_by_name(&request.name).await;
+        let result = resource_db.list_type_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -527,8 +920,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeListResponse>, Status>
     {
         self.validate(request.extensions(), READ_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_type_option(request.name.as_deref()).await;
+        let result = resource_db.list_type_option(request.name.as_deref()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -540,8 +934,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_type(
+        let result = resource_db.create_type(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             Some(&request.description)
@@ -557,8 +952,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_type(
+        let result = resource_db.update_type(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.description.as_deref()
@@ -574,8 +970,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.delete_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -587,8 +984,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_TYPE_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_type_model(
+        let result = resource_db.add_type_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default()
         ).await;
@@ -603,8 +1001,9 @@ impl DeviceService for DeviceServer {
         -> Result<Response<TypeChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_TYPE_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_type_model(
+        let result = resource_db.remove_type_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default()
         ).await;
@@ -614,27 +1013,197 @@ impl DeviceService for DeviceServer {
         };
         Ok(Response::new(TypeChangeResponse { }))
     }
+
+    async fn read_type_config(&self, request: Request<ConfigId>)
+        -> Result<Response<ConfigReadResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let result = resource_db.read_type_config(request.id).await;
+        let result = match result {
+            Ok(value) => Some(value.into()),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ConfigReadResponse { result }))
+    }
+
+    async fn list_type_config(&self, request: Request<TypeId>)
+        -> Result<Response<ConfigListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_TYPE)?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let result = resource_db.list_type_config_by_type(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ConfigListResponse { results }))
+    }
+
+    async fn create_type_config(&self, request: Request<ConfigSchema>)
+        -> Result<Response<ConfigCreateResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_TYPE)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let result = transaction.create_type_config(
+            Uuid::from_slice(&request.device_id).unwrap_or_default(),
+            &request.name,
+            DataValue::from_bytes(
+                &request.config_bytes,
+                DataType::from(request.config_type)
+            ),
+            &request.category
+        ).await;
+        let id = match result {
+            Ok(value) => value,
+            Err(e) => return Err(handle_error(e))
+        };
+        history::record_config(&transaction, ConfigKind::Type, id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        Ok(Response::new(ConfigCreateResponse { id }))
+    }
+
+    async fn update_type_config(&self, request: Request<ConfigUpdate>)
+        -> Result<Response<ConfigChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_TYPE)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_baseline(&transaction, ConfigKind::Type, request.id).await?;
+        let result = transaction.update_type_config(
+            request.id,
+            request.name.as_deref(),
+            request.config_bytes.map(|s| {
+                DataValue::from_bytes(
+                    &s,
+                    DataType::from(request.config_type.unwrap_or_default())
+                )
+            }),
+            request.category.as_deref()
+        ).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        history::record_config(&transaction, ConfigKind::Type, request.id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
+        Ok(Response::new(ConfigChangeResponse { }))
+    }
+
+    async fn delete_type_config(&self, request: Request<ConfigId>)
+        -> Result<Response<ConfigChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_TYPE)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        // deletion is stored as the last history version of the config
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_delete(&transaction, ConfigKind::Type, request.id, &subject).await?;
+        let result = transaction.delete_type_config(request.id).await;
+        match result {
+            Ok(_) => (),
+            Err(e) => return Err(handle_error(e))
+        };
+        transaction.commit().await
+            .map_err(handle_error)?;
+        Ok(Response::new(ConfigChangeResponse { }))
+    }
+}
+
+fn resolved(kind: ConfigKind, source_id: Uuid, config_id: i32, name: String, value: DataValue, category: String)
+    -> ConfigResolved
+{
+    ConfigResolved {
+        name,
+        config_bytes: value.to_bytes(),
+        config_type: value.get_type().into(),
+        category,
+        source: kind.into(),
+        source_id: source_id.as_bytes().to_vec(),
+        config_id
+    }
+}
+
+async fn resolve_config(resource_db: &Resource, device_id: Uuid)
+    -> Result<Vec<ConfigResolved>, Status>
+{
+    let device = resource_db.read_device(device_id).await
+        .map_err(|e| handle_error(e))?;
+    // configs of each model are kept apart since models of a type may use the same config name,
+    // such as scale or offset of two sensors, so they are identified by model id and name
+    let mut results: Vec<ConfigResolved> = Vec::new();
+    let models = resource_db.list_model_by_type(device.type_id).await
+        .map_err(|e| handle_error(e))?;
+    for model in models {
+        let configs = resource_db.list_model_config_by_model(model.id).await
+            .map_err(|e| handle_error(e))?;
+        for c in configs {
+            let source_id = model.id.as_bytes().to_vec();
+            results.retain(|r| r.source_id != source_id || r.name != c.name);
+            results.push(resolved(ConfigKind::Model, model.id, c.id, c.name, c.value, c.category));
+        }
+    }
+    // type, gateway, and device configs override in this order, a config replaces every config
+    // of the same name in lower layers including the same name in several models
+    let mut layers: Vec<ConfigResolved> = Vec::new();
+    let configs = resource_db.list_type_config_by_type(device.type_id).await
+        .map_err(|e| handle_error(e))?;
+    for c in configs {
+        layers.push(resolved(ConfigKind::Type, device.type_id, c.id, c.name, c.value, c.category));
+    }
+    let configs = resource_db.list_gateway_config_by_gateway(device.gateway_id).await
+        .map_err(|e| handle_error(e))?;
+    for c in configs {
+        layers.push(resolved(ConfigKind::Gateway, device.gateway_id, c.id, c.name, c.value, c.category));
+    }
+    let configs = resource_db.list_device_config_by_device(device.id).await
+        .map_err(|e| handle_error(e))?;
+    for c in configs {
+        layers.push(resolved(ConfigKind::Device, device.id, c.id, c.name, c.value, c.category));
+    }
+    for config in layers {
+        results.retain(|r| r.name != config.name);
+        results.push(config);
+    }
+    Ok(results)
 }
 
 impl AccessValidator for DeviceServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE, DELETE_DEVICE,
-            READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG, DELETE_DEVICE_CONFIG,
-            READ_TYPE, CREATE_TYPE, UPDATE_TYPE, DELETE_TYPE, CHANGE_TYPE_MODEL
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for DeviceServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
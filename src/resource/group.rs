@@ -8,24 +8,29 @@ use rmcs_resource_api::group::{
     GroupModelReadResponse, GroupModelListResponse, GroupCreateResponse, GroupChangeResponse,
     GroupDeviceReadResponse, GroupDeviceListResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_GROUP, CREATE_GROUP, UPDATE_GROUP, DELETE_GROUP, CHANGE_GROUP_MEMBER
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_GROUP, CREATE_GROUP, UPDATE_GROUP, DELETE_GROUP, CHANGE_GROUP_MEMBER
+];
+
 pub struct GroupServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl GroupServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -37,8 +42,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupModelReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_group_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.read_group_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -50,8 +56,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_model_by_name(&request.name).await;
+        let result = resource_db.list_group_model_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -63,8 +70,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_model_by_ids(
+        let result = resource_db.list_group_model_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -78,8 +86,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_model_by_category(&request.category).await;
+        let result = resource_db.list_group_model_by_category(&request.category).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -91,8 +100,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_model_option(
+        let result = resource_db.list_group_model_option(
             request.name.as_deref(),
             request.category.as_deref()
         ).await;
@@ -107,8 +117,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_group_model(
+        let result = resource_db.create_group_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             &request.category,
@@ -125,8 +136,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_group_model(
+        let result = resource_db.update_group_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.category.as_deref(),
@@ -143,8 +155,9 @@ impl GroupService for GroupServer {
     -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_GROUP)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_group_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.delete_group_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -156,8 +169,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_group_model_member(
+        let result = resource_db.add_group_model_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default()
         ).await;
@@ -172,8 +186,9 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_group_model_member(
+        let result = resource_db.remove_group_model_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default()
         ).await;
@@ -188,8 +203,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_group_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_group(&request.id)?;
+        let result = resource_db.read_group_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -201,12 +219,17 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_device_by_ids(
+        let result = resource_db.list_group_device_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -216,10 +239,15 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_device_by_name(&request.name).await;
+        let result = resource_db.list_group_device_by_name(&request.name).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -229,10 +257,15 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_device_by_category(&request.category).await;
+        let result = resource_db.list_group_device_by_category(&request.category).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -242,13 +275,18 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_device_option(
+        let result = resource_db.list_group_device_option(
             request.name.as_deref(),
             request.category.as_deref()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -258,8 +296,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_group_device(
+        scope.check_unrestricted()?;
+        let result = resource_db.create_group_device(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             &request.category,
@@ -276,8 +317,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_group_device(
+        scope.check_group(&request.id)?;
+        let result = resource_db.update_group_device(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.category.as_deref(),
@@ -294,8 +338,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_group_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_group(&request.id)?;
+        let result = resource_db.delete_group_device(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -307,8 +354,12 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_group_device_member(
+        scope.check_group(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.add_group_device_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default()
         ).await;
@@ -323,8 +374,12 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_group_device_member(
+        scope.check_group(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.remove_group_device_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default()
         ).await;
@@ -339,8 +394,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_group_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_group(&request.id)?;
+        let result = resource_db.read_group_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -352,12 +410,17 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_gateway_by_ids(
+        let result = resource_db.list_group_gateway_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -367,10 +430,15 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_gateway_by_name(&request.name).await;
+        let result = resource_db.list_group_gateway_by_name(&request.name).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -380,10 +448,15 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_gateway_by_category(&request.category).await;
+        let result = resource_db.list_group_gateway_by_category(&request.category).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -393,13 +466,18 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupDeviceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_group_gateway_option(
+        let result = resource_db.list_group_gateway_option(
             request.name.as_deref(),
             request.category.as_deref()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_group(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(GroupDeviceListResponse { results }))
@@ -409,8 +487,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_group_gateway(
+        scope.check_unrestricted()?;
+        let result = resource_db.create_group_gateway(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             &request.category,
@@ -427,8 +508,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_group_gateway(
+        scope.check_group(&request.id)?;
+        let result = resource_db.update_group_gateway(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.category.as_deref(),
@@ -445,8 +529,11 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_GROUP)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_group_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_group(&request.id)?;
+        let result = resource_db.delete_group_gateway(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -458,8 +545,12 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_group_gateway_member(
+        scope.check_group(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.add_group_gateway_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default()
         ).await;
@@ -474,8 +565,12 @@ impl GroupService for GroupServer {
         -> Result<Response<GroupChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_GROUP_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_group_gateway_member(
+        scope.check_group(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.remove_group_gateway_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default()
         ).await;
@@ -490,21 +585,25 @@ impl GroupService for GroupServer {
 
 impl AccessValidator for GroupServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_GROUP, CREATE_GROUP, UPDATE_GROUP, DELETE_GROUP, CHANGE_GROUP_MEMBER
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for GroupServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
@@ -0,0 +1,123 @@
+use tonic::Status;
+use rmcs_resource_db::{Resource, ConfigKind, ConfigHistorySchema, DataValue};
+use crate::utility::handle_error;
+use super::CONFIG_VERSION_NOT_FOUND;
+
+pub(crate) struct ConfigDiff {
+    pub version_1: ConfigHistorySchema,
+    pub version_2: ConfigHistorySchema,
+    pub fields: Vec<String>
+}
+
+async fn read_config(resource_db: &Resource, kind: ConfigKind, id: i32)
+    -> Result<(String, DataValue, String), Status>
+{
+    let config = match kind {
+        ConfigKind::Model => {
+            let config = resource_db.read_model_config(id).await.map_err(handle_error)?;
+            (config.name, config.value, config.category)
+        },
+        ConfigKind::Device => {
+            let config = resource_db.read_device_config(id).await.map_err(handle_error)?;
+            (config.name, config.value, config.category)
+        },
+        ConfigKind::Gateway => {
+            let config = resource_db.read_gateway_config(id).await.map_err(handle_error)?;
+            (config.name, config.value, config.category)
+        },
+        ConfigKind::Type => {
+            let config = resource_db.read_type_config(id).await.map_err(handle_error)?;
+            (config.name, config.value, config.category)
+        }
+    };
+    Ok(config)
+}
+
+pub(crate) async fn record_config(resource_db: &Resource, kind: ConfigKind, id: i32, subject: &str)
+    -> Result<(), Status>
+{
+    // read current state of a config and store it as a new version
+    let (name, value, category) = read_config(resource_db, kind, id).await?;
+    resource_db.create_config_history(kind, id, &name, value, &category, subject).await
+        .map_err(handle_error)?;
+    Ok(())
+}
+
+pub(crate) async fn record_config_delete(resource_db: &Resource, kind: ConfigKind, id: i32, subject: &str)
+    -> Result<(), Status>
+{
+    // last state of a deleted config is stored as a version marked deleted, so history of
+    // a deleted config still show who deleted it
+    record_config_baseline(resource_db, kind, id).await?;
+    let (name, value, category) = read_config(resource_db, kind, id).await?;
+    resource_db.create_config_history_delete(kind, id, &name, value, &category, subject).await
+        .map_err(handle_error)?;
+    Ok(())
+}
+
+pub(crate) async fn record_config_baseline(resource_db: &Resource, kind: ConfigKind, id: i32)
+    -> Result<(), Status>
+{
+    // store config created before history was kept as first version without subject
+    let histories = resource_db.list_config_history(kind, id).await
+        .map_err(handle_error)?;
+    if histories.is_empty() {
+        record_config(resource_db, kind, id, "").await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn diff_config(resource_db: &Resource, kind: ConfigKind, id: i32, version_1: i32, version_2: i32)
+    -> Result<ConfigDiff, Status>
+{
+    let histories = resource_db.list_config_history(kind, id).await
+        .map_err(handle_error)?;
+    let find = |version: i32| histories.iter()
+        .find(|h| h.version == version)
+        .cloned()
+        .ok_or(Status::not_found(CONFIG_VERSION_NOT_FOUND));
+    let version_1 = find(version_1)?;
+    let version_2 = find(version_2)?;
+    let mut fields = Vec::new();
+    if version_1.name != version_2.name {
+        fields.push(String::from("name"));
+    }
+    if version_1.value != version_2.value {
+        fields.push(String::from("value"));
+    }
+    if version_1.category != version_2.category {
+        fields.push(String::from("category"));
+    }
+    if version_1.deleted != version_2.deleted {
+        fields.push(String::from("deleted"));
+    }
+    Ok(ConfigDiff { version_1, version_2, fields })
+}
+
+pub(crate) async fn rollback_config(resource_db: &Resource, kind: ConfigKind, id: i32, version: i32, subject: &str)
+    -> Result<(), Status>
+{
+    // write back values of selected version, the rollback itself is stored as a new version
+    record_config_baseline(resource_db, kind, id).await?;
+    let history = resource_db.list_config_history(kind, id).await
+        .map_err(handle_error)?
+        .into_iter()
+        .find(|h| h.version == version)
+        .ok_or(Status::not_found(CONFIG_VERSION_NOT_FOUND))?;
+    let result = match kind {
+        ConfigKind::Model => resource_db.update_model_config(
+            id, Some(&history.name), Some(history.value), Some(&history.category)
+        ).await,
+        ConfigKind::Device => resource_db.update_device_config(
+            id, Some(&history.name), Some(history.value), Some(&history.category)
+        ).await,
+        ConfigKind::Gateway => resource_db.update_gateway_config(
+            id, Some(&history.name), Some(history.value), Some(&history.category)
+        ).await,
+        ConfigKind::Type => resource_db.update_type_config(
+            id, Some(&history.name), Some(history.value), Some(&history.category)
+        ).await
+    };
+    result.map_err(handle_error)?;
+    record_config(resource_db, kind, id, subject).await
+}
@@ -9,25 +9,30 @@ use rmcs_resource_api::log::{
     LogUpdate, LogUpdateTime,
     LogReadResponse, LogListResponse, LogCreateResponse, LogChangeResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_LOG, CREATE_LOG, UPDATE_LOG, DELETE_LOG
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_LOG, CREATE_LOG, UPDATE_LOG, DELETE_LOG
+];
+
 #[derive(Debug)]
 pub struct LogServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl LogServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -39,12 +44,17 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log(
+        let result = resource_db.read_log(
             request.id
         ).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                value.device_id.map(|id| scope.check_device_id(id)).unwrap_or(scope.check_unrestricted())?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(LogReadResponse { result }))
@@ -54,8 +64,11 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log_by_time(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.read_log_by_time(
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -72,12 +85,17 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_by_ids(
+        let result = resource_db.list_log_by_ids(
             &request.ids
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| e.device_id.map(|id| scope.contains_device(id)).unwrap_or(!scope.is_restricted()))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(LogListResponse { results }))
@@ -87,8 +105,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_by_time(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_by_time(
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -105,8 +126,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_by_latest(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_by_latest(
             Utc.timestamp_nanos(request.latest * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -123,8 +147,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_by_range(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_by_range(
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -142,8 +169,11 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log_first(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.read_log_first(
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.tag.map(|t| t as i16)
@@ -159,8 +189,11 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log_last(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.read_log_last(
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.tag.map(|t| t as i16)
@@ -176,8 +209,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_first(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_first(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -194,8 +230,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_first_offset(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_first_offset(
             request.number as usize,
             request.offset as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -213,8 +252,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_last(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_last(
             request.number as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -231,8 +273,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_last_offset(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_log_last_offset(
             request.number as usize,
             request.offset as usize,
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -250,8 +295,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_by_time(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_by_time(
             Utc.timestamp_nanos(request.timestamp * 1000),
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
@@ -268,8 +316,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_by_latest(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_by_latest(
             Utc.timestamp_nanos(request.latest * 1000),
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
@@ -286,8 +337,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_by_range(
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000),
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
@@ -305,8 +359,11 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log_group_first(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_log_group_first(
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             request.tag.map(|t| t as i16)
@@ -322,8 +379,11 @@ impl LogService for LogServer {
         -> Result<Response<LogReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_log_group_last(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.read_log_group_last(
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             request.tag.map(|t| t as i16)
@@ -339,8 +399,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_first(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_first(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -357,8 +420,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_first_offset(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_first_offset(
             request.number as usize,
             request.offset as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -376,8 +442,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_last(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_last(
             request.number as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -394,8 +463,11 @@ impl LogService for LogServer {
         -> Result<Response<LogListResponse>, Status>
     {
         self.validate(request.extensions(), READ_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_log_group_last_offset(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_log_group_last_offset(
             request.number as usize,
             request.offset as usize,
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
@@ -413,8 +485,11 @@ impl LogService for LogServer {
         -> Result<Response<LogCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_log(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.create_log(
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -435,8 +510,14 @@ impl LogService for LogServer {
     -> Result<Response<LogChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_log(
+        if scope.is_restricted() {
+            let value = resource_db.read_log(request.id).await.map_err(|e| handle_error(e))?;
+            value.device_id.map(|id| scope.check_device_id(id)).unwrap_or(scope.check_unrestricted())?;
+        }
+        let result = resource_db.update_log(
             request.id,
             request.log_bytes.map(|s| {
                 DataValue::from_bytes(
@@ -457,8 +538,11 @@ impl LogService for LogServer {
     -> Result<Response<LogChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_log_by_time(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.update_log_by_time(
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -481,8 +565,14 @@ impl LogService for LogServer {
         -> Result<Response<LogChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_log(
+        if scope.is_restricted() {
+            let value = resource_db.read_log(request.id).await.map_err(|e| handle_error(e))?;
+            value.device_id.map(|id| scope.check_device_id(id)).unwrap_or(scope.check_unrestricted())?;
+        }
+        let result = resource_db.delete_log(
             request.id
         ).await;
         match result {
@@ -496,8 +586,11 @@ impl LogService for LogServer {
         -> Result<Response<LogChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_LOG)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_log_by_time(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.delete_log_by_time(
             Utc.timestamp_nanos(request.timestamp * 1000),
             request.device_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.model_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
@@ -514,21 +607,25 @@ impl LogService for LogServer {
 
 impl AccessValidator for LogServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_LOG, CREATE_LOG, UPDATE_LOG, DELETE_LOG
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for LogServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
@@ -6,6 +6,12 @@ pub mod data;
 pub mod buffer;
 pub mod slice;
 pub mod log;
+pub mod command;
+pub mod provision;
+pub mod catalog;
+mod history;
+mod scope;
+mod config_change;
 
 // model service procedure names
 const READ_MODEL: &str = "read_model";
@@ -61,3 +67,35 @@ const READ_LOG: &str = "read_log";
 const CREATE_LOG: &str = "create_log";
 const UPDATE_LOG: &str = "update_log";
 const DELETE_LOG: &str = "delete_log";
+// command service procedure names
+const READ_COMMAND: &str = "read_command";
+const CREATE_COMMAND: &str = "create_command";
+const UPDATE_COMMAND: &str = "update_command";
+const DELETE_COMMAND: &str = "delete_command";
+
+/// All procedure names used by resource services, built from procedures of each service
+pub fn procedures() -> Vec<&'static str> {
+    let services = [
+        model::PROCEDURES, device::PROCEDURES, group::PROCEDURES, set::PROCEDURES,
+        data::PROCEDURES, buffer::PROCEDURES, slice::PROCEDURES, log::PROCEDURES,
+        command::PROCEDURES, provision::PROCEDURES, catalog::PROCEDURES
+    ];
+    let mut procedures: Vec<&'static str> = Vec::new();
+    for &procedure in services.iter().flat_map(|p| p.iter()) {
+        if !procedures.contains(&procedure) {
+            procedures.push(procedure);
+        }
+    }
+    procedures
+}
+
+// operation error message
+const CONFIG_VERSION_NOT_FOUND: &str = "requested config version not found";
+const CONFIG_STREAM_LAGGED: &str = "config change notifications are lost, reload configs";
+const COMMAND_STATUS_INVALID: &str = "command acknowledge status must be succeeded or failed";
+const PROVISION_ID_EMPTY: &str = "provision manifest entry must have a valid id";
+const CATALOG_INVALID: &str = "invalid catalog document";
+const SCOPE_DEVICE_DENIED: &str = "device is outside the scope of the token";
+const SCOPE_GROUP_DENIED: &str = "group is outside the scope of the token";
+const SCOPE_SET_DENIED: &str = "set is outside the scope of the token";
+const SCOPE_DEVICE_REQUIRED: &str = "token scope requires a device to be selected";
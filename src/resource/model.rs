@@ -1,35 +1,43 @@
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
-use rmcs_resource_db::{Resource, DataType, DataValue};
+use rmcs_resource_db::{Resource, DataType, DataValue, ConfigKind};
 use rmcs_resource_api::model::model_service_server::ModelService;
 use rmcs_resource_api::model::{
     ModelSchema, ModelId, ModelIds, ModelName, ModelCategory, ModelOption, TypeId, ModelUpdate,
-    ConfigSchema, ConfigId, ConfigUpdate,
+    ConfigSchema, ConfigId, ConfigUpdate, ConfigVersion, ConfigVersionPair,
     TagSchema, TagId, TagUpdate,
     ModelReadResponse, ModelListResponse, ModelCreateResponse, ModelChangeResponse,
     ConfigReadResponse, ConfigListResponse, ConfigCreateResponse, ConfigChangeResponse,
+    ConfigHistoryListResponse, ConfigDiffResponse,
     TagReadResponse, TagListResponse, TagChangeResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
+use super::history;
 use super::{
     READ_MODEL, CREATE_MODEL, UPDATE_MODEL, DELETE_MODEL,
     READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG, DELETE_MODEL_CONFIG
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_MODEL, CREATE_MODEL, UPDATE_MODEL, DELETE_MODEL,
+    READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG, DELETE_MODEL_CONFIG
+];
+
 #[derive(Debug)]
 pub struct ModelServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl ModelServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -41,8 +49,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.read_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -54,8 +63,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_by_ids(
+        let result = resource_db.list_model_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -69,8 +79,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_by_type(
+        let result = resource_db.list_model_by_type(
             Uuid::from_slice(&request.id).unwrap_or_default()
         ).await;
         let results = match result {
@@ -84,8 +95,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_by_name(&request.name).await;
+        let result = resource_db.list_model_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -97,8 +109,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_by_category(&request.category).await;
+        let result = resource_db.list_model_by_category(&request.category).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -110,8 +123,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_option(
+        let result = resource_db.list_model_option(
             request.type_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref(),
             request.category.as_deref()
@@ -127,9 +141,10 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
         let data_type: Vec<DataType> = request.data_type.into_iter().map(|ty| DataType::from(ty)).collect();
-        let result = self.resource_db.create_model(
+        let result = resource_db.create_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &data_type,
             &request.category,
@@ -147,13 +162,14 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
         let data_type: Option<Vec<DataType>> = if request.data_type_flag {
             Some(request.data_type.into_iter().map(|ty| DataType::from(ty)).collect())
         } else {
             None
         };
-        let result = self.resource_db.update_model(
+        let result = resource_db.update_model(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             data_type.as_deref(),
             request.category.as_deref(),
@@ -171,8 +187,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ModelChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_MODEL)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.delete_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -184,8 +201,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ConfigReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_model_config(request.id).await;
+        let result = resource_db.read_model_config(request.id).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -197,8 +215,9 @@ impl ModelService for ModelServer {
         -> Result<Response<ConfigListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_model_config_by_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_model_config_by_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -210,8 +229,13 @@ impl ModelService for ModelServer {
         -> Result<Response<ConfigCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_MODEL_CONFIG)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_model_config(
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let result = transaction.create_model_config(
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.index,
             &request.name,
@@ -225,6 +249,9 @@ impl ModelService for ModelServer {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Model, id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
         Ok(Response::new(ConfigCreateResponse { id }))
     }
 
@@ -232,8 +259,14 @@ impl ModelService for ModelServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_MODEL_CONFIG)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_model_config(
+        // config and its history version are stored in the same transaction
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_baseline(&transaction, ConfigKind::Model, request.id).await?;
+        let result = transaction.update_model_config(
             request.id,
             request.name.as_deref(),
             request.config_bytes.map(|s| {
@@ -248,6 +281,9 @@ impl ModelService for ModelServer {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        history::record_config(&transaction, ConfigKind::Model, request.id, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
@@ -255,12 +291,69 @@ impl ModelService for ModelServer {
         -> Result<Response<ConfigChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_MODEL_CONFIG)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_model_config(request.id).await;
+        // deletion is stored as the last history version of the config
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::record_config_delete(&transaction, ConfigKind::Model, request.id, &subject).await?;
+        let result = transaction.delete_model_config(request.id).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
         };
+        transaction.commit().await
+            .map_err(handle_error)?;
+        Ok(Response::new(ConfigChangeResponse { }))
+    }
+
+    async fn list_model_config_history(&self, request: Request<ConfigId>)
+        -> Result<Response<ConfigHistoryListResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let result = resource_db.list_config_history(ConfigKind::Model, request.id).await;
+        let results = match result {
+            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Err(e) => return Err(handle_error(e))
+        };
+        Ok(Response::new(ConfigHistoryListResponse { results }))
+    }
+
+    async fn diff_model_config(&self, request: Request<ConfigVersionPair>)
+        -> Result<Response<ConfigDiffResponse>, Status>
+    {
+        self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let diff = history::diff_config(
+            &resource_db,
+            ConfigKind::Model,
+            request.id,
+            request.version_1,
+            request.version_2
+        ).await?;
+        Ok(Response::new(ConfigDiffResponse {
+            version_1: Some(diff.version_1.into()),
+            version_2: Some(diff.version_2.into()),
+            fields: diff.fields
+        }))
+    }
+
+    async fn rollback_model_config(&self, request: Request<ConfigVersion>)
+        -> Result<Response<ConfigChangeResponse>, Status>
+    {
+        self.validate(request.extensions(), UPDATE_MODEL_CONFIG)?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        history::rollback_config(&transaction, ConfigKind::Model, request.id, request.version, &subject).await?;
+        transaction.commit().await
+            .map_err(handle_error)?;
         Ok(Response::new(ConfigChangeResponse { }))
     }
 
@@ -268,8 +361,9 @@ impl ModelService for ModelServer {
         -> Result<Response<TagReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_tag(
+        let result = resource_db.read_tag(
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag as i16
         ).await;
@@ -284,8 +378,9 @@ impl ModelService for ModelServer {
         -> Result<Response<TagListResponse>, Status>
     {
         self.validate(request.extensions(), READ_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_tag_by_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_tag_by_model(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -297,8 +392,9 @@ impl ModelService for ModelServer {
         -> Result<Response<TagChangeResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_tag(
+        let result = resource_db.create_tag(
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag as i16,
             &request.name,
@@ -315,13 +411,14 @@ impl ModelService for ModelServer {
         -> Result<Response<TagChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
         let members = if request.members_flag {
             Some(request.members.into_iter().map(|t| t as i16).collect::<Vec<i16>>())
         } else {
             None
         };
-        let result = self.resource_db.update_tag(
+        let result = resource_db.update_tag(
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag as i16,
             request.name.as_deref(),
@@ -338,8 +435,9 @@ impl ModelService for ModelServer {
         -> Result<Response<TagChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_MODEL_CONFIG)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_tag(
+        let result = resource_db.delete_tag(
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             request.tag as i16
         ).await;
@@ -354,22 +452,25 @@ impl ModelService for ModelServer {
 
 impl AccessValidator for ModelServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_MODEL, CREATE_MODEL, UPDATE_MODEL, DELETE_MODEL,
-            READ_MODEL_CONFIG, CREATE_MODEL_CONFIG, UPDATE_MODEL_CONFIG, DELETE_MODEL_CONFIG
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for ModelServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
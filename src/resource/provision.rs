@@ -0,0 +1,322 @@
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+use rmcs_resource_db::{Resource, DataType, DataValue, ConfigKind};
+use rmcs_resource_api::device::{self, ConfigAction};
+use rmcs_resource_api::group::GroupDevice;
+use rmcs_resource_api::provision::provision_service_server::ProvisionService;
+use rmcs_resource_api::provision::{
+    ProvisionManifest, ProvisionChange, ProvisionAction, ProvisionResponse
+};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
+use super::config_change::{ConfigEntry, ConfigPlan, plan_config, apply_config, notify_config};
+use super::{
+    READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE,
+    READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG,
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, READ_GROUP, CHANGE_GROUP_MEMBER,
+    PROVISION_ID_EMPTY
+};
+use crate::utility::handle_error;
+
+const KIND_TYPE: &str = "type";
+const KIND_GATEWAY: &str = "gateway";
+const KIND_DEVICE: &str = "device";
+const KIND_DEVICE_CONFIG: &str = "device_config";
+const KIND_GATEWAY_CONFIG: &str = "gateway_config";
+const KIND_GROUP_MEMBER: &str = "group_member";
+
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE,
+    READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG,
+    READ_TYPE, CREATE_TYPE, UPDATE_TYPE, READ_GROUP, CHANGE_GROUP_MEMBER
+];
+
+#[derive(Debug)]
+pub struct ProvisionServer {
+    resource_db: Resource,
+    accesses: AccessTable
+}
+
+impl ProvisionServer {
+    pub fn new(resource_db: Resource) -> Self {
+        Self {
+            resource_db,
+            accesses: AccessTable::default()
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ProvisionService for ProvisionServer {
+
+    async fn provision(&self, request: Request<ProvisionManifest>)
+        -> Result<Response<ProvisionResponse>, Status>
+    {
+        let procedures: &[&str] = if request.get_ref().dry_run {
+            &[READ_TYPE, READ_DEVICE, READ_DEVICE_CONFIG, READ_GROUP]
+        } else {
+            &[READ_TYPE, CREATE_TYPE, UPDATE_TYPE, READ_DEVICE, CREATE_DEVICE, UPDATE_DEVICE,
+            READ_DEVICE_CONFIG, CREATE_DEVICE_CONFIG, UPDATE_DEVICE_CONFIG, READ_GROUP, CHANGE_GROUP_MEMBER]
+        };
+        for procedure in procedures {
+            self.validate(request.extensions(), procedure)?;
+        }
+        // bulk operations touch resources outside any scope so scoped token can not use them
+        self.device_scope(request.extensions()).await?.check_unrestricted()?;
+        let subject = self.token_subject(request.extensions());
+        let resource_db = self.tenant_db(request.extensions());
+        let request = request.into_inner();
+        // every change is applied in a single transaction so a failed manifest leaves the database
+        // untouched, dropping the transaction without commit rolls it back
+        let transaction = resource_db.begin().await
+            .map_err(handle_error)?;
+        let mut provision = Provision::new(&transaction, request.dry_run, &subject);
+        provision.apply(request).await?;
+        let Provision { dry_run, changes, configs, .. } = provision;
+        if dry_run {
+            return Ok(Response::new(ProvisionResponse { changes }));
+        }
+        transaction.commit().await
+            .map_err(handle_error)?;
+        // gateways streaming config changes reload provisioned configs once they are committed
+        for (kind, action, id) in configs {
+            notify_config(&resource_db, kind, action, id).await;
+        }
+        Ok(Response::new(ProvisionResponse { changes }))
+    }
+
+}
+
+struct Provision<'a> {
+    resource_db: &'a Resource,
+    dry_run: bool,
+    subject: &'a str,
+    changes: Vec<ProvisionChange>,
+    configs: Vec<(ConfigKind, ConfigAction, i32)>
+}
+
+impl<'a> Provision<'a> {
+
+    fn new(resource_db: &'a Resource, dry_run: bool, subject: &'a str) -> Self {
+        Self {
+            resource_db,
+            dry_run,
+            subject,
+            changes: Vec::new(),
+            configs: Vec::new()
+        }
+    }
+
+    fn change(&mut self, kind: &str, action: ProvisionAction, id: Uuid, name: &str) {
+        self.changes.push(ProvisionChange {
+            kind: kind.to_owned(),
+            action: action as i32,
+            id: id.as_bytes().to_vec(),
+            name: name.to_owned()
+        });
+    }
+
+    async fn apply(&mut self, manifest: ProvisionManifest) -> Result<(), Status>
+    {
+        // apply in dependency order: types, gateways, devices, then configs and group members
+        for schema in manifest.types {
+            self.apply_type(schema).await?;
+        }
+        for schema in manifest.gateways {
+            self.apply_gateway(schema).await?;
+        }
+        for schema in manifest.devices {
+            self.apply_device(schema).await?;
+        }
+        for schema in manifest.gateway_configs {
+            self.apply_config(ConfigKind::Gateway, schema).await?;
+        }
+        for schema in manifest.device_configs {
+            self.apply_config(ConfigKind::Device, schema).await?;
+        }
+        for member in manifest.group_members {
+            self.apply_group_member(member).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_type(&mut self, schema: device::TypeSchema) -> Result<(), Status>
+    {
+        let id = manifest_id(&schema.id)?;
+        match self.resource_db.read_type(id).await {
+            Ok(current) => {
+                if current.name == schema.name && current.description == schema.description {
+                    self.change(KIND_TYPE, ProvisionAction::Unchanged, id, &schema.name);
+                    return Ok(());
+                }
+                self.change(KIND_TYPE, ProvisionAction::Update, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.update_type(id, Some(&schema.name), Some(&schema.description)).await
+                        .map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_TYPE, ProvisionAction::Create, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.create_type(id, &schema.name, Some(&schema.description)).await
+                        .map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        Ok(())
+    }
+
+    async fn apply_gateway(&mut self, schema: device::GatewaySchema) -> Result<(), Status>
+    {
+        let id = manifest_id(&schema.id)?;
+        let type_id = manifest_id(&schema.gateway_type.unwrap_or_default().id)?;
+        match self.resource_db.read_gateway(id).await {
+            Ok(current) => {
+                if current.type_id == type_id && current.serial_number == schema.serial_number
+                    && current.name == schema.name && current.description == schema.description
+                {
+                    self.change(KIND_GATEWAY, ProvisionAction::Unchanged, id, &schema.name);
+                    return Ok(());
+                }
+                self.change(KIND_GATEWAY, ProvisionAction::Update, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.update_gateway(
+                        id,
+                        Some(type_id),
+                        Some(&schema.serial_number),
+                        Some(&schema.name),
+                        Some(&schema.description)
+                    ).await.map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_GATEWAY, ProvisionAction::Create, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.create_gateway(id, type_id, &schema.serial_number, &schema.name, Some(&schema.description)).await
+                        .map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        Ok(())
+    }
+
+    async fn apply_device(&mut self, schema: device::DeviceSchema) -> Result<(), Status>
+    {
+        let id = manifest_id(&schema.id)?;
+        let gateway_id = manifest_id(&schema.gateway_id)?;
+        let type_id = manifest_id(&schema.device_type.unwrap_or_default().id)?;
+        match self.resource_db.read_device(id).await {
+            Ok(current) => {
+                if current.gateway_id == gateway_id && current.type_id == type_id
+                    && current.serial_number == schema.serial_number
+                    && current.name == schema.name && current.description == schema.description
+                {
+                    self.change(KIND_DEVICE, ProvisionAction::Unchanged, id, &schema.name);
+                    return Ok(());
+                }
+                self.change(KIND_DEVICE, ProvisionAction::Update, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.update_device(
+                        id,
+                        Some(gateway_id),
+                        Some(type_id),
+                        Some(&schema.serial_number),
+                        Some(&schema.name),
+                        Some(&schema.description)
+                    ).await.map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(sqlx::Error::RowNotFound) => {
+                self.change(KIND_DEVICE, ProvisionAction::Create, id, &schema.name);
+                if !self.dry_run {
+                    self.resource_db.create_device(id, gateway_id, type_id, &schema.serial_number, &schema.name, Some(&schema.description)).await
+                        .map_err(|e| handle_error(e))?;
+                }
+            },
+            Err(e) => return Err(handle_error(e))
+        }
+        Ok(())
+    }
+
+    async fn apply_config(&mut self, kind: ConfigKind, schema: device::ConfigSchema) -> Result<(), Status>
+    {
+        // configs are identified by owner device or gateway and config name
+        let entry = ConfigEntry {
+            kind,
+            owner_id: manifest_id(&schema.device_id)?,
+            name: schema.name,
+            value: DataValue::from_bytes(&schema.config_bytes, DataType::from(schema.config_type)),
+            category: schema.category
+        };
+        let label = if kind == ConfigKind::Gateway { KIND_GATEWAY_CONFIG } else { KIND_DEVICE_CONFIG };
+        let plan = plan_config(self.resource_db, &entry).await?;
+        let action = match plan {
+            ConfigPlan::Unchanged => ProvisionAction::Unchanged,
+            ConfigPlan::Update(_) => ProvisionAction::Update,
+            ConfigPlan::Create => ProvisionAction::Create
+        };
+        self.change(label, action, entry.owner_id, &entry.name);
+        if !self.dry_run {
+            if let Some(config) = apply_config(self.resource_db, entry, plan, self.subject).await? {
+                self.configs.push(config);
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_group_member(&mut self, member: GroupDevice) -> Result<(), Status>
+    {
+        let group_id = manifest_id(&member.id)?;
+        let device_id = manifest_id(&member.device_id)?;
+        let group = self.resource_db.read_group_device(group_id).await
+            .map_err(|e| handle_error(e))?;
+        if group.devices.contains(&device_id) {
+            self.change(KIND_GROUP_MEMBER, ProvisionAction::Unchanged, device_id, &group.name);
+            return Ok(());
+        }
+        self.change(KIND_GROUP_MEMBER, ProvisionAction::Create, device_id, &group.name);
+        if !self.dry_run {
+            self.resource_db.add_group_device_member(group_id, device_id).await
+                .map_err(|e| handle_error(e))?;
+        }
+        Ok(())
+    }
+
+}
+
+fn manifest_id(id: &[u8]) -> Result<Uuid, Status>
+{
+    match Uuid::from_slice(id) {
+        Ok(value) if !value.is_nil() => Ok(value),
+        _ => Err(Status::invalid_argument(PROVISION_ID_EMPTY))
+    }
+}
+
+impl AccessValidator for ProvisionServer {
+
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
+        self
+    }
+
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
+    }
+
+    fn accesses(&self) -> Vec<AccessSchema> {
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for ProvisionServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
+    }
+
+}
@@ -0,0 +1,194 @@
+use std::sync::{OnceLock, RwLock};
+use tonic::{Status, Extensions};
+use chrono::{DateTime, Utc, Duration};
+use uuid::Uuid;
+use rmcs_resource_db::Resource;
+use crate::utility::validator::AccessValidator;
+use crate::utility::scope::TokenScope;
+use crate::utility::handle_error;
+use super::{SCOPE_DEVICE_DENIED, SCOPE_SET_DENIED, SCOPE_GROUP_DENIED, SCOPE_DEVICE_REQUIRED};
+
+const SCOPE_CACHE_DURATION: i64 = 30;
+const SCOPE_CACHE_SIZE: usize = 1024;
+
+static SCOPE_CACHE: OnceLock<ScopeCache> = OnceLock::new();
+
+/// Devices, groups, and sets which a request can access, resolved from token scope.
+/// Devices of a gateway group include the gateways and every device under them.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct DeviceScope {
+    restricted: bool,
+    devices: Vec<Uuid>,
+    groups: Vec<Uuid>,
+    sets: Vec<Uuid>
+}
+
+impl DeviceScope {
+
+    pub async fn resolve(resource_db: &Resource, scope: Option<TokenScope>) -> Result<Self, Status> {
+        let scope = match scope {
+            Some(value) => value,
+            None => return Ok(Self::default())
+        };
+        let mut devices = Vec::new();
+        for &id in &scope.dgr {
+            let group = resource_db.read_group_device(id).await.map_err(|e| handle_error(e))?;
+            devices.extend(group.devices);
+        }
+        for &id in &scope.ggr {
+            let group = resource_db.read_group_gateway(id).await.map_err(|e| handle_error(e))?;
+            for gateway_id in group.devices {
+                let members = resource_db.list_device_by_gateway(gateway_id).await
+                    .map_err(|e| handle_error(e))?;
+                devices.push(gateway_id);
+                devices.extend(members.into_iter().map(|d| d.id));
+            }
+        }
+        for &id in &scope.set {
+            let set = resource_db.read_set(id).await.map_err(|e| handle_error(e))?;
+            devices.extend(set.members.into_iter().map(|m| m.device_id));
+        }
+        devices.sort();
+        devices.dedup();
+        let mut groups = scope.dgr;
+        groups.extend(scope.ggr);
+        Ok(Self { restricted: true, devices, groups, sets: scope.set })
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.restricted
+    }
+
+    pub fn contains_device(&self, id: Uuid) -> bool {
+        !self.restricted || self.devices.contains(&id)
+    }
+
+    pub fn contains_group(&self, id: Uuid) -> bool {
+        !self.restricted || self.groups.contains(&id)
+    }
+
+    pub fn contains_set(&self, id: Uuid) -> bool {
+        !self.restricted || self.sets.contains(&id)
+    }
+
+    pub fn check_device(&self, id: &[u8]) -> Result<(), Status> {
+        self.check_device_id(Uuid::from_slice(id).unwrap_or_default())
+    }
+
+    pub fn check_device_id(&self, id: Uuid) -> Result<(), Status> {
+        if self.contains_device(id) { Ok(()) } else { Err(Status::permission_denied(SCOPE_DEVICE_DENIED)) }
+    }
+
+    pub fn check_devices(&self, ids: &[Vec<u8>]) -> Result<(), Status> {
+        ids.iter().map(|id| self.check_device(id)).collect()
+    }
+
+    /// Check an optional device filter, a restricted request must select a device
+    pub fn check_device_option(&self, id: Option<&[u8]>) -> Result<(), Status> {
+        match id {
+            Some(id) => self.check_device(id),
+            None if self.restricted => Err(Status::permission_denied(SCOPE_DEVICE_REQUIRED)),
+            None => Ok(())
+        }
+    }
+
+    pub fn check_group(&self, id: &[u8]) -> Result<(), Status> {
+        let id = Uuid::from_slice(id).unwrap_or_default();
+        if self.contains_group(id) { Ok(()) } else { Err(Status::permission_denied(SCOPE_GROUP_DENIED)) }
+    }
+
+    pub fn check_set(&self, id: &[u8]) -> Result<(), Status> {
+        self.check_set_id(Uuid::from_slice(id).unwrap_or_default())
+    }
+
+    pub fn check_set_id(&self, id: Uuid) -> Result<(), Status> {
+        if self.contains_set(id) { Ok(()) } else { Err(Status::permission_denied(SCOPE_SET_DENIED)) }
+    }
+
+    /// Check an optional set filter, a restricted request must select a set
+    pub fn check_set_option(&self, id: Option<&[u8]>) -> Result<(), Status> {
+        match id {
+            Some(id) => self.check_set(id),
+            None if self.restricted => Err(Status::permission_denied(SCOPE_SET_DENIED)),
+            None => Ok(())
+        }
+    }
+
+    /// Only an unrestricted request can use operations which are not bound to a device
+    pub fn check_unrestricted(&self) -> Result<(), Status> {
+        if self.restricted { Err(Status::permission_denied(SCOPE_DEVICE_REQUIRED)) } else { Ok(()) }
+    }
+
+}
+
+pub(crate) trait ScopeValidator: AccessValidator {
+
+    fn resource_db(&self) -> &Resource;
+
+    /// Resource database bound to tenant of the token, every query only reads and writes rows of the tenant
+    fn tenant_db(&self, extension: &Extensions) -> Resource
+    {
+        match self.token_tenant(extension) {
+            Some(tenant_id) => self.resource_db().with_tenant(tenant_id),
+            None => self.resource_db().clone()
+        }
+    }
+
+    /// Resolve device scope of the token, resolved scopes are cached for a short time since a
+    /// client send many requests with the same token
+    fn device_scope(&self, extension: &Extensions) -> impl std::future::Future<Output = Result<DeviceScope, Status>> + Send where Self: Sync
+    {async move {
+        let scope = match self.token_scope(extension) {
+            Some(value) => value,
+            None => return Ok(DeviceScope::default())
+        };
+        let tenant_id = self.token_tenant(extension);
+        if let Some(cached) = scope_cache().get(tenant_id, &scope) {
+            return Ok(cached);
+        }
+        let resolved = DeviceScope::resolve(&self.tenant_db(extension), Some(scope.clone())).await?;
+        scope_cache().insert(tenant_id, scope, resolved.clone());
+        Ok(resolved)
+    } }
+
+}
+
+#[derive(Debug, Clone)]
+struct ScopeEntry {
+    tenant_id: Option<Uuid>,
+    scope: TokenScope,
+    resolved: DeviceScope,
+    expire: DateTime<Utc>
+}
+
+/// Device scopes resolved from token scopes, so group and set members are not read from the
+/// database on every request. Membership changes apply after cache duration.
+#[derive(Debug, Default)]
+struct ScopeCache {
+    entries: RwLock<Vec<ScopeEntry>>
+}
+
+impl ScopeCache {
+
+    fn get(&self, tenant_id: Option<Uuid>, scope: &TokenScope) -> Option<DeviceScope> {
+        let now = Utc::now();
+        self.entries.read().unwrap().iter()
+            .find(|e| e.tenant_id == tenant_id && &e.scope == scope && e.expire > now)
+            .map(|e| e.resolved.clone())
+    }
+
+    fn insert(&self, tenant_id: Option<Uuid>, scope: TokenScope, resolved: DeviceScope) {
+        let now = Utc::now();
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|e| e.expire > now && !(e.tenant_id == tenant_id && e.scope == scope));
+        if entries.len() >= SCOPE_CACHE_SIZE {
+            entries.remove(0);
+        }
+        entries.push(ScopeEntry { tenant_id, scope, resolved, expire: now + Duration::seconds(SCOPE_CACHE_DURATION) });
+    }
+
+}
+
+fn scope_cache() -> &'static ScopeCache {
+    SCOPE_CACHE.get_or_init(ScopeCache::default)
+}
@@ -9,24 +9,29 @@ use rmcs_resource_api::set::{
     SetReadResponse, SetListResponse, SetCreateResponse, SetChangeResponse, 
     TemplateReadResponse, TemplateListResponse, TemplateCreateResponse, TemplateChangeResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_SET, CREATE_SET, UPDATE_SET, DELETE_SET, CHANGE_SET_MEMBER
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_SET, CREATE_SET, UPDATE_SET, DELETE_SET, CHANGE_SET_MEMBER
+];
+
 pub struct SetServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl SetServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -38,8 +43,11 @@ impl SetService for SetServer {
         -> Result<Response<SetReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_set(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_set(&request.id)?;
+        let result = resource_db.read_set(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -51,12 +59,17 @@ impl SetService for SetServer {
         -> Result<Response<SetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_by_ids(
+        let result = resource_db.list_set_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SetListResponse { results }))
@@ -66,10 +79,15 @@ impl SetService for SetServer {
         -> Result<Response<SetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_by_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.list_set_by_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SetListResponse { results }))
@@ -79,10 +97,15 @@ impl SetService for SetServer {
         -> Result<Response<SetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_by_name(&request.name).await;
+        let result = resource_db.list_set_by_name(&request.name).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SetListResponse { results }))
@@ -92,13 +115,18 @@ impl SetService for SetServer {
         -> Result<Response<SetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_option(
+        let result = resource_db.list_set_option(
             request.template_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref()
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SetListResponse { results }))
@@ -108,8 +136,11 @@ impl SetService for SetServer {
         -> Result<Response<SetCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_set(
+        scope.check_unrestricted()?;
+        let result = resource_db.create_set(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.template_id).unwrap_or_default(),
             &request.name,
@@ -126,8 +157,11 @@ impl SetService for SetServer {
         -> Result<Response<SetChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_set(
+        scope.check_set(&request.id)?;
+        let result = resource_db.update_set(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.template_id.map(|x| Uuid::from_slice(&x).unwrap_or_default()),
             request.name.as_deref(),
@@ -144,8 +178,11 @@ impl SetService for SetServer {
         -> Result<Response<SetChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_SET)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_set(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        scope.check_set(&request.id)?;
+        let result = resource_db.delete_set(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -157,8 +194,12 @@ impl SetService for SetServer {
         -> Result<Response<SetChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_set_member(
+        scope.check_set(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.add_set_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
@@ -175,8 +216,12 @@ impl SetService for SetServer {
         -> Result<Response<SetChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_set_member(
+        scope.check_set(&request.id)?;
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.remove_set_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default()
@@ -192,8 +237,13 @@ impl SetService for SetServer {
         -> Result<Response<SetChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.swap_set_member(
+        scope.check_set(&request.id)?;
+        scope.check_device(&request.device_id_1)?;
+        scope.check_device(&request.device_id_2)?;
+        let result = resource_db.swap_set_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.device_id_1).unwrap_or_default(),
             Uuid::from_slice(&request.model_id_1).unwrap_or_default(),
@@ -211,8 +261,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_set_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.read_set_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         let result = match result {
             Ok(value) => Some(value.into()),
             Err(e) => return Err(handle_error(e))
@@ -224,8 +275,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_template_by_ids(
+        let result = resource_db.list_set_template_by_ids(
             request.ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>().as_slice()
         ).await;
         let results = match result {
@@ -239,8 +291,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_template_by_name(&request.name).await;
+        let result = resource_db.list_set_template_by_name(&request.name).await;
         let results = match result {
             Ok(value) => value.into_iter().map(|e| e.into()).collect(),
             Err(e) => return Err(handle_error(e))
@@ -252,8 +305,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_set_template_option(
+        let result = resource_db.list_set_template_option(
             request.name.as_deref()
         ).await;
         let results = match result {
@@ -267,8 +321,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_set_template(
+        let result = resource_db.create_set_template(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             &request.name,
             Some(&request.description)
@@ -284,8 +339,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_set_template(
+        let result = resource_db.update_set_template(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.name.as_deref(),
             request.description.as_deref()
@@ -301,8 +357,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_SET)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_set_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
+        let result = resource_db.delete_set_template(Uuid::from_slice(&request.id).unwrap_or_default()).await;
         match result {
             Ok(_) => (),
             Err(e) => return Err(handle_error(e))
@@ -314,8 +371,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.add_set_template_member(
+        let result = resource_db.add_set_template_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             Uuid::from_slice(&request.type_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
@@ -332,8 +390,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.remove_set_template_member(
+        let result = resource_db.remove_set_template_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.template_index as usize
         ).await;
@@ -348,8 +407,9 @@ impl SetService for SetServer {
         -> Result<Response<TemplateChangeResponse>, Status>
     {
         self.validate(request.extensions(), CHANGE_SET_MEMBER)?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.swap_set_template_member(
+        let result = resource_db.swap_set_template_member(
             Uuid::from_slice(&request.id).unwrap_or_default(),
             request.template_index_1 as usize,
             request.template_index_2 as usize
@@ -365,21 +425,25 @@ impl SetService for SetServer {
 
 impl AccessValidator for SetServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_SET, CREATE_SET, UPDATE_SET, DELETE_SET, CHANGE_SET_MEMBER
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for SetServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
@@ -10,25 +10,30 @@ use rmcs_resource_api::slice::{
     SliceReadResponse, SliceListResponse, SliceCreateResponse, SliceChangeResponse,
     SliceSetReadResponse, SliceSetListResponse
 };
-use crate::utility::validator::{AccessValidator, AccessSchema};
+use crate::utility::validator::{AccessValidator, AccessSchema, AccessTable};
+use crate::utility::signing::PublicKey;
+use super::scope::ScopeValidator;
 use super::{
     READ_SLICE, CREATE_SLICE, UPDATE_SLICE, DELETE_SLICE
 };
 use crate::utility::handle_error;
 
+/// Procedures used by the service
+pub(crate) const PROCEDURES: &[&str] = &[
+    READ_SLICE, CREATE_SLICE, UPDATE_SLICE, DELETE_SLICE
+];
+
 #[derive(Debug)]
 pub struct SliceServer {
     resource_db: Resource,
-    token_key: Vec<u8>,
-    accesses: Vec<AccessSchema>
+    accesses: AccessTable
 }
 
 impl SliceServer {
     pub fn new(resource_db: Resource) -> Self {
         Self {
             resource_db,
-            token_key: Vec::new(),
-            accesses: Vec::new()
+            accesses: AccessTable::default()
         }
     }
 }
@@ -40,10 +45,15 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_slice(request.id).await;
+        let result = resource_db.read_slice(request.id).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_device_id(value.device_id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceReadResponse { result }))
@@ -53,10 +63,15 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_by_ids(&request.ids).await;
+        let result = resource_db.list_slice_by_ids(&request.ids).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.device_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceListResponse { results }))
@@ -66,8 +81,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_by_time(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_slice_by_time(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000)
@@ -83,8 +101,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_by_range(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.list_slice_by_range(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -101,13 +122,18 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_by_name_time(
+        let result = resource_db.list_slice_by_name_time(
             &request.name,
             Utc.timestamp_nanos(request.timestamp * 1000)
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.device_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceListResponse { results }))
@@ -117,14 +143,19 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_by_name_range(
+        let result = resource_db.list_slice_by_name_range(
             &request.name,
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000)
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_device(e.device_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceListResponse { results }))
@@ -134,8 +165,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_option(
+        scope.check_device_option(request.device_id.as_deref())?;
+        let result = resource_db.list_slice_option(
             request.device_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.model_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref(),
@@ -153,8 +187,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_group_by_time(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_slice_group_by_time(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.timestamp * 1000)
@@ -170,8 +207,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_group_by_range(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_slice_group_by_range(
             &request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             &request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>(),
             Utc.timestamp_nanos(request.begin * 1000),
@@ -188,8 +228,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_group_option(
+        scope.check_devices(&request.device_ids)?;
+        let result = resource_db.list_slice_group_option(
             Some(&request.device_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             Some(&request.model_ids.into_iter().map(|id| Uuid::from_slice(&id).unwrap_or_default()).collect::<Vec<Uuid>>()),
             request.name.as_deref(),
@@ -207,8 +250,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_slice(
+        scope.check_device(&request.device_id)?;
+        let result = resource_db.create_slice(
             Uuid::from_slice(&request.device_id).unwrap_or_default(),
             Uuid::from_slice(&request.model_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp_begin * 1000),
@@ -227,8 +273,14 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_slice(
+        if scope.is_restricted() {
+            let value = resource_db.read_slice(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.update_slice(
             request.id,
             request.timestamp_begin.map(|s| Utc.timestamp_nanos(s * 1000)),
             request.timestamp_end.map(|s| Utc.timestamp_nanos(s * 1000)),
@@ -246,8 +298,14 @@ impl SliceService for SliceServer {
     -> Result<Response<SliceChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_slice(request.id).await;
+        if scope.is_restricted() {
+            let value = resource_db.read_slice(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_device_id(value.device_id)?;
+        }
+        let result = resource_db.delete_slice(request.id).await;
         match result {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
@@ -259,10 +317,15 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetReadResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.read_slice_set(request.id).await;
+        let result = resource_db.read_slice_set(request.id).await;
         let result = match result {
-            Ok(value) => Some(value.into()),
+            Ok(value) => {
+                scope.check_set_id(value.set_id)?;
+                Some(value.into())
+            },
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceSetReadResponse { result }))
@@ -272,10 +335,15 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_by_ids(&request.ids).await;
+        let result = resource_db.list_slice_set_by_ids(&request.ids).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.set_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceSetListResponse { results }))
@@ -285,8 +353,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_by_time(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_slice_set_by_time(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp * 1000)
         ).await;
@@ -301,8 +372,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_by_range(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.list_slice_set_by_range(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000)
@@ -318,13 +392,18 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_by_name_time(
+        let result = resource_db.list_slice_set_by_name_time(
             &request.name,
             Utc.timestamp_nanos(request.timestamp * 1000)
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.set_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceSetListResponse { results }))
@@ -334,14 +413,19 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_by_name_range(
+        let result = resource_db.list_slice_set_by_name_range(
             &request.name,
             Utc.timestamp_nanos(request.begin * 1000),
             Utc.timestamp_nanos(request.end * 1000)
         ).await;
         let results = match result {
-            Ok(value) => value.into_iter().map(|e| e.into()).collect(),
+            Ok(value) => value.into_iter()
+                .filter(|e| scope.contains_set(e.set_id))
+                .map(|e| e.into())
+                .collect(),
             Err(e) => return Err(handle_error(e))
         };
         Ok(Response::new(SliceSetListResponse { results }))
@@ -351,8 +435,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceSetListResponse>, Status>
     {
         self.validate(request.extensions(), READ_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.list_slice_set_option(
+        scope.check_set_option(request.set_id.as_deref())?;
+        let result = resource_db.list_slice_set_option(
             request.set_id.map(|id| Uuid::from_slice(&id).unwrap_or_default()),
             request.name.as_deref(),
             request.begin.map(|t| Utc.timestamp_nanos(t * 1000)),
@@ -369,8 +456,11 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceCreateResponse>, Status>
     {
         self.validate(request.extensions(), CREATE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.create_slice_set(
+        scope.check_set(&request.set_id)?;
+        let result = resource_db.create_slice_set(
             Uuid::from_slice(&request.set_id).unwrap_or_default(),
             Utc.timestamp_nanos(request.timestamp_begin * 1000),
             Utc.timestamp_nanos(request.timestamp_end * 1000),
@@ -388,8 +478,14 @@ impl SliceService for SliceServer {
         -> Result<Response<SliceChangeResponse>, Status>
     {
         self.validate(request.extensions(), UPDATE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.update_slice_set(
+        if scope.is_restricted() {
+            let value = resource_db.read_slice_set(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_set_id(value.set_id)?;
+        }
+        let result = resource_db.update_slice_set(
             request.id,
             request.timestamp_begin.map(|s| Utc.timestamp_nanos(s * 1000)),
             request.timestamp_end.map(|s| Utc.timestamp_nanos(s * 1000)),
@@ -407,8 +503,14 @@ impl SliceService for SliceServer {
     -> Result<Response<SliceChangeResponse>, Status>
     {
         self.validate(request.extensions(), DELETE_SLICE)?;
+        let scope = self.device_scope(request.extensions()).await?;
+        let resource_db = self.tenant_db(request.extensions());
         let request = request.into_inner();
-        let result = self.resource_db.delete_slice_set(request.id).await;
+        if scope.is_restricted() {
+            let value = resource_db.read_slice_set(request.id).await.map_err(|e| handle_error(e))?;
+            scope.check_set_id(value.set_id)?;
+        }
+        let result = resource_db.delete_slice_set(request.id).await;
         match result {
             Ok(value) => value,
             Err(e) => return Err(handle_error(e))
@@ -420,21 +522,25 @@ impl SliceService for SliceServer {
 
 impl AccessValidator for SliceServer {
 
-    fn with_validator(mut self, token_key: &[u8], accesses: &[AccessSchema]) -> Self {
-        const PROCEDURES: &[&str] = &[
-            READ_SLICE, CREATE_SLICE, UPDATE_SLICE, DELETE_SLICE
-        ];
-        self.token_key = token_key.to_owned();
-        self.accesses = Self::construct_accesses(accesses, PROCEDURES);
+    fn with_validator(mut self, table: &AccessTable) -> Self {
+        self.accesses = table.with_procedures(PROCEDURES);
         self
     }
 
-    fn token_key(&self) -> Vec<u8> {
-        self.token_key.clone()
+    fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.accesses.verifying_keys()
     }
 
     fn accesses(&self) -> Vec<AccessSchema> {
-        self.accesses.clone()
+        self.accesses.accesses()
+    }
+
+}
+
+impl ScopeValidator for SliceServer {
+
+    fn resource_db(&self) -> &Resource {
+        &self.resource_db
     }
 
 }
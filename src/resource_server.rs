@@ -8,6 +8,9 @@ use rmcs_resource_api::data::data_service_server::DataServiceServer;
 use rmcs_resource_api::buffer::buffer_service_server::BufferServiceServer;
 use rmcs_resource_api::slice::slice_service_server::SliceServiceServer;
 use rmcs_resource_api::log::log_service_server::LogServiceServer;
+use rmcs_resource_api::command::command_service_server::CommandServiceServer;
+use rmcs_resource_api::provision::provision_service_server::ProvisionServiceServer;
+use rmcs_resource_api::catalog::catalog_service_server::CatalogServiceServer;
 use rmcs_resource_api::descriptor;
 use rmcs_api_server::resource::model::ModelServer;
 use rmcs_api_server::resource::device::DeviceServer;
@@ -17,18 +20,24 @@ use rmcs_api_server::resource::data::DataServer;
 use rmcs_api_server::resource::buffer::BufferServer;
 use rmcs_api_server::resource::slice::SliceServer;
 use rmcs_api_server::resource::log::LogServer;
-use rmcs_api_server::utility::interceptor::interceptor;
-use rmcs_api_server::utility::validator::{AccessValidator, AccessSchema};
-use rmcs_api_server::utility::config::{ROOT_DATA, RootData};
-use rmcs_api_server::utility::auth::api_login;
+use rmcs_api_server::resource::command::CommandServer;
+use rmcs_api_server::resource::provision::ProvisionServer;
+use rmcs_api_server::resource::catalog::CatalogServer;
+use rmcs_api_server::utility::interceptor::AccessInterceptor;
+use rmcs_api_server::utility::validator::{AccessValidator, AccessSchema, ParentSchema, AccessTable};
+use rmcs_api_server::resource::procedures;
+use rmcs_api_server::utility::auth::{api_login, api_access, signing_keys, register_procedures, receive_revoked_tokens, resolve_api_keys, refresh_accesses};
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use http::{header::HeaderName, Method};
 use tower_http::cors::{CorsLayer, Any};
 
+const DEF_REFRESH_INTERVAL: u64 = 60;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
     let url = std::env::var("DATABASE_URL_RESOURCE").unwrap();
     let addr = std::env::var("BIND_ADDRESS_RESOURCE").unwrap().parse()?;
     let auth_addr = std::env::var("SERVER_ADDRESS_AUTH").unwrap();
@@ -39,47 +48,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let api_id = std::env::var("API_ID").unwrap().parse()?;
     let password = std::env::var("API_PASSWORD").unwrap();
 
-    let response = api_login(&auth_addr, api_id, &password).await
+    let mut response = api_login(&auth_addr, api_id, &password).await
         .expect("Failed to get api definition from Auth server");
-    let token_key = response.access_key;
-    let root_key = response.root_key;
+    // register procedures which are not defined in Auth server using the API credential
+    let missing: Vec<&str> = procedures().into_iter()
+        .filter(|&p| !response.access_procedures.iter().any(|a| a.procedure == p))
+        .collect();
+    if !missing.is_empty() {
+        match register_procedures(&auth_addr, api_id, &password, &missing).await {
+            Some(created) => {
+                tracing::info!("Registered procedures to Auth server: {}", created.join(", "));
+                response = api_access(&auth_addr, api_id, &password).await
+                    .expect("Failed to get api definition from Auth server");
+            },
+            None => tracing::warn!("Procedures not registered in Auth server and will deny every access: {}", missing.join(", "))
+        }
+    }
     let accesses: Vec<AccessSchema> = response.access_procedures
         .into_iter()
         .map(|s| s.into())
         .collect();
-
-    let root_pw = std::env::var("ROOT_PASSWORD");
-    let root_ad = std::env::var("ROOT_ACCESS_DURATION");
-    let root_rd = std::env::var("ROOT_REFRESH_DURATION");
-    if let (Ok(password), Ok(access_duration), Ok(refresh_duration)) = (root_pw, root_ad, root_rd) {
-        ROOT_DATA.set(RootData::new_with_key(
-            &password, 
-            access_duration.parse()?, 
-            refresh_duration.parse()?, 
-            &root_key
-        )).unwrap();
-    }
+    let parents: Vec<ParentSchema> = response.role_parents
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
+    // access tokens are verified using public keys of auth server so no signing secret is kept here
+    let keys = signing_keys(&auth_addr).await
+        .expect("Failed to get token signing keys from Auth server");
 
     let resource_db = Resource::new_with_url(&url).await;
     migrate(&resource_db.pool).await.unwrap();
 
-    let model_server = ModelServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let device_server = DeviceServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let group_server = GroupServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let set_server = SetServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let data_server = DataServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let buffer_server = BufferServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let slice_server = SliceServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let log_server = LogServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
+    // keep revoked access tokens from auth server so they are rejected before expired
+    let revoke_addr = auth_addr.clone();
+    tokio::spawn(async move {
+        receive_revoked_tokens(&revoke_addr).await;
+    });
+
+    // exchange API keys of service accounts which are sent in place of access tokens
+    let key_addr = auth_addr.clone();
+    tokio::spawn(async move {
+        resolve_api_keys(&key_addr, api_id).await;
+    });
+
+    // refresh signing keys and procedure accesses periodically so role access changes and
+    // signing key rotation apply without restarting the server
+    let table = AccessTable::new(&keys, &accesses, &parents);
+    let refresh_table = table.clone();
+    let refresh_interval = std::env::var("ACCESS_REFRESH_INTERVAL").ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEF_REFRESH_INTERVAL);
+    tokio::spawn(async move {
+        refresh_accesses(&auth_addr, api_id, &password, &refresh_table, refresh_interval).await;
+    });
+
+    let model_server = ModelServer::new(resource_db.clone()).with_validator(&table);
+    let device_server = DeviceServer::new(resource_db.clone()).with_validator(&table);
+    let group_server = GroupServer::new(resource_db.clone()).with_validator(&table);
+    let set_server = SetServer::new(resource_db.clone()).with_validator(&table);
+    let data_server = DataServer::new(resource_db.clone()).with_validator(&table);
+    let buffer_server = BufferServer::new(resource_db.clone()).with_validator(&table);
+    let slice_server = SliceServer::new(resource_db.clone()).with_validator(&table);
+    let log_server = LogServer::new(resource_db.clone()).with_validator(&table);
+    let command_server = CommandServer::new(resource_db.clone()).with_validator(&table);
+    let provision_server = ProvisionServer::new(resource_db.clone()).with_validator(&table);
+    let catalog_server = CatalogServer::new(resource_db.clone()).with_validator(&table);
 
-    let model_server = ModelServiceServer::with_interceptor(model_server, interceptor);
-    let device_server = DeviceServiceServer::with_interceptor(device_server, interceptor);
-    let group_server = GroupServiceServer::with_interceptor(group_server, interceptor);
-    let set_server = SetServiceServer::with_interceptor(set_server, interceptor);
-    let data_server = DataServiceServer::with_interceptor(data_server, interceptor);
-    let buffer_server = BufferServiceServer::with_interceptor(buffer_server, interceptor);
-    let slice_server = SliceServiceServer::with_interceptor(slice_server, interceptor);
-    let log_server = LogServiceServer::with_interceptor(log_server, interceptor);
+    // access token is decoded once by the interceptor and validators read the caller from extensions
+    let interceptor = AccessInterceptor::new(&table);
+    let model_server = ModelServiceServer::with_interceptor(model_server, interceptor.clone());
+    let device_server = DeviceServiceServer::with_interceptor(device_server, interceptor.clone());
+    let group_server = GroupServiceServer::with_interceptor(group_server, interceptor.clone());
+    let set_server = SetServiceServer::with_interceptor(set_server, interceptor.clone());
+    let data_server = DataServiceServer::with_interceptor(data_server, interceptor.clone());
+    let buffer_server = BufferServiceServer::with_interceptor(buffer_server, interceptor.clone());
+    let slice_server = SliceServiceServer::with_interceptor(slice_server, interceptor.clone());
+    let log_server = LogServiceServer::with_interceptor(log_server, interceptor.clone());
+    let command_server = CommandServiceServer::with_interceptor(command_server, interceptor.clone());
+    let provision_server = ProvisionServiceServer::with_interceptor(provision_server, interceptor.clone());
+    let catalog_server = CatalogServiceServer::with_interceptor(catalog_server, interceptor);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(descriptor::model::DESCRIPTOR_SET)
@@ -90,6 +137,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .register_encoded_file_descriptor_set(descriptor::buffer::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::slice::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::log::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::command::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::provision::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::catalog::DESCRIPTOR_SET)
         .build_v1();
 
     Server::builder()
@@ -109,6 +159,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_service(buffer_server)
         .add_service(slice_server)
         .add_service(log_server)
+        .add_service(command_server)
+        .add_service(provision_server)
+        .add_service(catalog_server)
         .add_service(reflection_service?)
         .serve(addr)
         .await?;
@@ -4,6 +4,12 @@ use rmcs_auth_api::role::role_service_server::RoleServiceServer;
 use rmcs_auth_api::user::user_service_server::UserServiceServer;
 use rmcs_auth_api::profile::profile_service_server::ProfileServiceServer;
 use rmcs_auth_api::token::token_service_server::TokenServiceServer;
+use rmcs_auth_api::service_account::service_account_service_server::ServiceAccountServiceServer;
+use rmcs_auth_api::tenant::tenant_service_server::TenantServiceServer;
+use rmcs_auth_api::session::session_service_server::SessionServiceServer;
+use rmcs_auth_api::lockout::lockout_service_server::LockoutServiceServer;
+use rmcs_auth_api::mfa::mfa_service_server::MfaServiceServer;
+use rmcs_auth_api::policy::policy_service_server::PolicyServiceServer;
 use rmcs_auth_api::auth::auth_service_server::AuthServiceServer;
 use rmcs_auth_api::descriptor;
 use rmcs_api_server::auth::api::ApiServer;
@@ -11,9 +17,17 @@ use rmcs_api_server::auth::role::RoleServer;
 use rmcs_api_server::auth::user::UserServer;
 use rmcs_api_server::auth::profile::ProfileServer;
 use rmcs_api_server::auth::token::TokenServer;
+use rmcs_api_server::auth::service_account::ServiceAccountServer;
+use rmcs_api_server::auth::tenant::TenantServer;
+use rmcs_api_server::auth::session::SessionServer;
+use rmcs_api_server::auth::lockout::LockoutServer;
+use rmcs_api_server::auth::mfa::MfaServer;
+use rmcs_api_server::auth::policy::PolicyServer;
 use rmcs_api_server::auth::auth::AuthServer;
 use rmcs_api_server::utility::interceptor::interceptor;
+use rmcs_api_server::utility::signing::signing_keys;
 use rmcs_api_server::utility::validator::AuthValidator;
+use rmcs_api_server::utility::notifier::{set_notifier, Channel, FileNotifier};
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use http::{header::HeaderName, Method};
@@ -34,7 +48,11 @@ struct Args {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
     let args = Args::parse();
+    // tokens sent by test server are read from standard output
+    set_notifier(Channel::Email, Box::new(FileNotifier::new(None)));
+    set_notifier(Channel::Sms, Box::new(FileNotifier::new(None)));
     let db_url = match args.db_url {
         Some(value) => value,
         None => std::env::var("DATABASE_URL_AUTH_TEST").unwrap()
@@ -56,11 +74,18 @@ async fn auth_server(db_url: String, address: String) -> Result<(), Box<dyn std:
     let addr = address.parse()?;
 
     let auth_db = Auth::new_with_url(&db_url).await;
+    signing_keys().load(&auth_db).await?;
     let api_server = ApiServer::new(auth_db.clone());
     let role_server = RoleServer::new(auth_db.clone());
     let user_server = UserServer::new(auth_db.clone());
     let profile_server = ProfileServer::new(auth_db.clone());
     let token_server = TokenServer::new(auth_db.clone());
+    let service_account_server = ServiceAccountServer::new(auth_db.clone());
+    let tenant_server = TenantServer::new(auth_db.clone());
+    let session_server = SessionServer::new(auth_db.clone());
+    let lockout_server = LockoutServer::new(auth_db.clone());
+    let mfa_server = MfaServer::new(auth_db.clone());
+    let policy_server = PolicyServer::new(auth_db.clone());
     let auth_server = AuthServer::new(auth_db.clone());
 
     let api_server = ApiServiceServer::new(api_server);
@@ -68,6 +93,12 @@ async fn auth_server(db_url: String, address: String) -> Result<(), Box<dyn std:
     let user_server = UserServiceServer::new(user_server);
     let profile_server = ProfileServiceServer::new(profile_server);
     let token_server = TokenServiceServer::new(token_server);
+    let service_account_server = ServiceAccountServiceServer::new(service_account_server);
+    let tenant_server = TenantServiceServer::new(tenant_server);
+    let session_server = SessionServiceServer::new(session_server);
+    let lockout_server = LockoutServiceServer::new(lockout_server);
+    let mfa_server = MfaServiceServer::new(mfa_server);
+    let policy_server = PolicyServiceServer::new(policy_server);
     let auth_server = AuthServiceServer::new(auth_server);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
@@ -76,6 +107,12 @@ async fn auth_server(db_url: String, address: String) -> Result<(), Box<dyn std:
         .register_encoded_file_descriptor_set(descriptor::user::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::profile::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::token::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::service_account::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::tenant::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::session::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::lockout::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::mfa::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::policy::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::auth::DESCRIPTOR_SET)
         .build_v1();
 
@@ -93,6 +130,12 @@ async fn auth_server(db_url: String, address: String) -> Result<(), Box<dyn std:
         .add_service(user_server)
         .add_service(profile_server)
         .add_service(token_server)
+        .add_service(service_account_server)
+        .add_service(tenant_server)
+        .add_service(session_server)
+        .add_service(lockout_server)
+        .add_service(mfa_server)
+        .add_service(policy_server)
         .add_service(auth_server)
         .add_service(reflection_service?)
         .serve(addr)
@@ -106,11 +149,18 @@ async fn auth_server_secured(db_url: String, address: String) -> Result<(), Box<
     let addr = address.parse()?;
 
     let auth_db = Auth::new_with_url(&db_url).await;
+    signing_keys().load(&auth_db).await?;
     let api_server = ApiServer::new(auth_db.clone()).with_validator();
     let role_server = RoleServer::new(auth_db.clone()).with_validator();
     let user_server = UserServer::new(auth_db.clone()).with_validator();
     let profile_server = ProfileServer::new(auth_db.clone()).with_validator();
     let token_server = TokenServer::new(auth_db.clone()).with_validator();
+    let service_account_server = ServiceAccountServer::new(auth_db.clone()).with_validator();
+    let tenant_server = TenantServer::new(auth_db.clone()).with_validator();
+    let session_server = SessionServer::new(auth_db.clone()).with_validator();
+    let lockout_server = LockoutServer::new(auth_db.clone()).with_validator();
+    let mfa_server = MfaServer::new(auth_db.clone()).with_validator();
+    let policy_server = PolicyServer::new(auth_db.clone()).with_validator();
     let auth_server = AuthServer::new(auth_db.clone());
 
     let api_server = ApiServiceServer::with_interceptor(api_server, interceptor);
@@ -118,6 +168,12 @@ async fn auth_server_secured(db_url: String, address: String) -> Result<(), Box<
     let user_server = UserServiceServer::with_interceptor(user_server, interceptor);
     let profile_server = ProfileServiceServer::with_interceptor(profile_server, interceptor);
     let token_server = TokenServiceServer::with_interceptor(token_server, interceptor);
+    let service_account_server = ServiceAccountServiceServer::with_interceptor(service_account_server, interceptor);
+    let tenant_server = TenantServiceServer::with_interceptor(tenant_server, interceptor);
+    let session_server = SessionServiceServer::with_interceptor(session_server, interceptor);
+    let lockout_server = LockoutServiceServer::with_interceptor(lockout_server, interceptor);
+    let mfa_server = MfaServiceServer::with_interceptor(mfa_server, interceptor);
+    let policy_server = PolicyServiceServer::with_interceptor(policy_server, interceptor);
     let auth_server = AuthServiceServer::new(auth_server);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
@@ -126,6 +182,12 @@ async fn auth_server_secured(db_url: String, address: String) -> Result<(), Box<
         .register_encoded_file_descriptor_set(descriptor::user::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::profile::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::token::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::service_account::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::tenant::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::session::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::lockout::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::mfa::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::policy::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::auth::DESCRIPTOR_SET)
         .build_v1();
 
@@ -143,6 +205,12 @@ async fn auth_server_secured(db_url: String, address: String) -> Result<(), Box<
         .add_service(user_server)
         .add_service(profile_server)
         .add_service(token_server)
+        .add_service(service_account_server)
+        .add_service(tenant_server)
+        .add_service(session_server)
+        .add_service(lockout_server)
+        .add_service(mfa_server)
+        .add_service(policy_server)
         .add_service(auth_server)
         .add_service(reflection_service?)
         .serve(addr)
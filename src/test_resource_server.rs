@@ -7,6 +7,9 @@ use rmcs_resource_api::data::data_service_server::DataServiceServer;
 use rmcs_resource_api::buffer::buffer_service_server::BufferServiceServer;
 use rmcs_resource_api::slice::slice_service_server::SliceServiceServer;
 use rmcs_resource_api::log::log_service_server::LogServiceServer;
+use rmcs_resource_api::command::command_service_server::CommandServiceServer;
+use rmcs_resource_api::provision::provision_service_server::ProvisionServiceServer;
+use rmcs_resource_api::catalog::catalog_service_server::CatalogServiceServer;
 use rmcs_resource_api::descriptor;
 use rmcs_api_server::resource::model::ModelServer;
 use rmcs_api_server::resource::device::DeviceServer;
@@ -16,9 +19,12 @@ use rmcs_api_server::resource::data::DataServer;
 use rmcs_api_server::resource::buffer::BufferServer;
 use rmcs_api_server::resource::slice::SliceServer;
 use rmcs_api_server::resource::log::LogServer;
-use rmcs_api_server::utility::interceptor::interceptor;
-use rmcs_api_server::utility::validator::{AccessValidator, AccessSchema};
-use rmcs_api_server::utility::auth::api_login;
+use rmcs_api_server::resource::command::CommandServer;
+use rmcs_api_server::resource::provision::ProvisionServer;
+use rmcs_api_server::resource::catalog::CatalogServer;
+use rmcs_api_server::utility::interceptor::AccessInterceptor;
+use rmcs_api_server::utility::validator::{AccessValidator, AccessSchema, ParentSchema, AccessTable};
+use rmcs_api_server::utility::auth::{api_login, signing_keys, receive_revoked_tokens, resolve_api_keys, refresh_accesses};
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
 use http::{header::HeaderName, Method};
@@ -26,6 +32,9 @@ use tower_http::cors::{CorsLayer, Any};
 use uuid::Uuid;
 use clap::Parser;
 
+/// Short refresh interval so access changes made by a test apply quickly
+const REFRESH_INTERVAL: u64 = 1;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -46,6 +55,7 @@ struct Args {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
     let args = Args::parse();
     let db_url = match args.db_url {
         Some(value) => value,
@@ -92,6 +102,9 @@ async fn resource_server(db_url: String, address: String) -> Result<(), Box<dyn
     let buffer_server = BufferServer::new(resource_db.clone());
     let slice_server = SliceServer::new(resource_db.clone());
     let log_server = LogServer::new(resource_db.clone());
+    let command_server = CommandServer::new(resource_db.clone());
+    let provision_server = ProvisionServer::new(resource_db.clone());
+    let catalog_server = CatalogServer::new(resource_db.clone());
 
     let model_server = ModelServiceServer::new(model_server);
     let device_server = DeviceServiceServer::new(device_server);
@@ -101,6 +114,9 @@ async fn resource_server(db_url: String, address: String) -> Result<(), Box<dyn
     let buffer_server = BufferServiceServer::new(buffer_server);
     let slice_server = SliceServiceServer::new(slice_server);
     let log_server = LogServiceServer::new(log_server);
+    let command_server = CommandServiceServer::new(command_server);
+    let provision_server = ProvisionServiceServer::new(provision_server);
+    let catalog_server = CatalogServiceServer::new(catalog_server);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(descriptor::model::DESCRIPTOR_SET)
@@ -111,6 +127,9 @@ async fn resource_server(db_url: String, address: String) -> Result<(), Box<dyn
         .register_encoded_file_descriptor_set(descriptor::buffer::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::slice::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::log::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::command::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::provision::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::catalog::DESCRIPTOR_SET)
         .build_v1();
 
     Server::builder()
@@ -130,6 +149,9 @@ async fn resource_server(db_url: String, address: String) -> Result<(), Box<dyn
         .add_service(buffer_server)
         .add_service(slice_server)
         .add_service(log_server)
+        .add_service(command_server)
+        .add_service(provision_server)
+        .add_service(catalog_server)
         .add_service(reflection_service?)
         .serve(addr)
         .await?;
@@ -144,30 +166,55 @@ async fn resource_server_secured(db_url: String, address: String, auth_address:
 
     let response = api_login(&auth_address, api_id, &password).await
         .expect("Failed to get api definition from Auth server");
-    let token_key = response.access_key;
     let accesses: Vec<AccessSchema> = response.access_procedures
         .into_iter()
         .map(|s| s.into())
         .collect();
+    let parents: Vec<ParentSchema> = response.role_parents
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
+    let keys = signing_keys(&auth_address).await
+        .expect("Failed to get token signing keys from Auth server");
+    let table = AccessTable::new(&keys, &accesses, &parents);
+    let revoke_addr = auth_address.clone();
+    tokio::spawn(async move {
+        receive_revoked_tokens(&revoke_addr).await;
+    });
+    let key_addr = auth_address.clone();
+    tokio::spawn(async move {
+        resolve_api_keys(&key_addr, api_id).await;
+    });
+    let refresh_table = table.clone();
+    tokio::spawn(async move {
+        refresh_accesses(&auth_address, api_id, &password, &refresh_table, REFRESH_INTERVAL).await;
+    });
 
     let resource_db = Resource::new_with_url(&db_url).await;
-    let model_server = ModelServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let device_server = DeviceServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let group_server = GroupServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let set_server = SetServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let data_server = DataServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let buffer_server = BufferServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let slice_server = SliceServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-    let log_server = LogServer::new(resource_db.clone()).with_validator(&token_key, &accesses);
-
-    let model_server = ModelServiceServer::with_interceptor(model_server, interceptor);
-    let device_server = DeviceServiceServer::with_interceptor(device_server, interceptor);
-    let group_server = GroupServiceServer::with_interceptor(group_server, interceptor);
-    let set_server = SetServiceServer::with_interceptor(set_server, interceptor);
-    let data_server = DataServiceServer::with_interceptor(data_server, interceptor);
-    let buffer_server = BufferServiceServer::with_interceptor(buffer_server, interceptor);
-    let slice_server = SliceServiceServer::with_interceptor(slice_server, interceptor);
-    let log_server = LogServiceServer::with_interceptor(log_server, interceptor);
+    let model_server = ModelServer::new(resource_db.clone()).with_validator(&table);
+    let device_server = DeviceServer::new(resource_db.clone()).with_validator(&table);
+    let group_server = GroupServer::new(resource_db.clone()).with_validator(&table);
+    let set_server = SetServer::new(resource_db.clone()).with_validator(&table);
+    let data_server = DataServer::new(resource_db.clone()).with_validator(&table);
+    let buffer_server = BufferServer::new(resource_db.clone()).with_validator(&table);
+    let slice_server = SliceServer::new(resource_db.clone()).with_validator(&table);
+    let log_server = LogServer::new(resource_db.clone()).with_validator(&table);
+    let command_server = CommandServer::new(resource_db.clone()).with_validator(&table);
+    let provision_server = ProvisionServer::new(resource_db.clone()).with_validator(&table);
+    let catalog_server = CatalogServer::new(resource_db.clone()).with_validator(&table);
+
+    let interceptor = AccessInterceptor::new(&table);
+    let model_server = ModelServiceServer::with_interceptor(model_server, interceptor.clone());
+    let device_server = DeviceServiceServer::with_interceptor(device_server, interceptor.clone());
+    let group_server = GroupServiceServer::with_interceptor(group_server, interceptor.clone());
+    let set_server = SetServiceServer::with_interceptor(set_server, interceptor.clone());
+    let data_server = DataServiceServer::with_interceptor(data_server, interceptor.clone());
+    let buffer_server = BufferServiceServer::with_interceptor(buffer_server, interceptor.clone());
+    let slice_server = SliceServiceServer::with_interceptor(slice_server, interceptor.clone());
+    let log_server = LogServiceServer::with_interceptor(log_server, interceptor.clone());
+    let command_server = CommandServiceServer::with_interceptor(command_server, interceptor.clone());
+    let provision_server = ProvisionServiceServer::with_interceptor(provision_server, interceptor.clone());
+    let catalog_server = CatalogServiceServer::with_interceptor(catalog_server, interceptor);
 
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(descriptor::model::DESCRIPTOR_SET)
@@ -178,6 +225,9 @@ async fn resource_server_secured(db_url: String, address: String, auth_address:
         .register_encoded_file_descriptor_set(descriptor::buffer::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::slice::DESCRIPTOR_SET)
         .register_encoded_file_descriptor_set(descriptor::log::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::command::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::provision::DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(descriptor::catalog::DESCRIPTOR_SET)
         .build_v1();
 
     Server::builder()
@@ -197,6 +247,9 @@ async fn resource_server_secured(db_url: String, address: String, auth_address:
         .add_service(buffer_server)
         .add_service(slice_server)
         .add_service(log_server)
+        .add_service(command_server)
+        .add_service(provision_server)
+        .add_service(catalog_server)
         .add_service(reflection_service?)
         .serve(addr)
         .await?;
@@ -0,0 +1,239 @@
+use std::sync::RwLock;
+use std::net::IpAddr;
+use chrono::{DateTime, Utc, Duration};
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+use super::config::API_KEY_TOKENS;
+
+/// API key format is `rmk_<prefix>_<secret>`, the prefix identify the key without revealing the secret
+pub const API_KEY_PREFIX: &str = "rmk";
+const PREFIX_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 40;
+const DEF_CACHE_DURATION: i64 = 60;
+const PENDING_CHANNEL_SIZE: usize = 256;
+const REJECT_DURATION: i64 = 30;
+const MAX_REJECTED_ENTRIES: usize = 4096;
+
+fn random_string(length: usize) -> String
+{
+    thread_rng().sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Generate a new API key and return its prefix and the full key
+pub fn generate_api_key() -> (String, String)
+{
+    let prefix = random_string(PREFIX_LENGTH);
+    let key = format!("{}_{}_{}", API_KEY_PREFIX, prefix, random_string(SECRET_LENGTH));
+    (prefix, key)
+}
+
+pub fn is_api_key(value: &str) -> bool
+{
+    api_key_prefix(value).is_some()
+}
+
+/// Get prefix of an API key, return none if the value is not in API key format
+pub fn api_key_prefix(key: &str) -> Option<&str>
+{
+    let mut parts = key.splitn(3, '_');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(API_KEY_PREFIX), Some(prefix), Some(secret))
+            if prefix.len() == PREFIX_LENGTH && secret.len() == SECRET_LENGTH => Some(prefix),
+        _ => None
+    }
+}
+
+/// Hash of an API key which is stored instead of the key
+pub fn hash_api_key(key: &str) -> String
+{
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Compare an API key with a stored hash in constant time
+pub fn verify_api_key(key: &str, hash: &str) -> bool
+{
+    hash_api_key(key).as_bytes().ct_eq(hash.as_bytes()).into()
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    hash: Vec<u8>,
+    token: ApiKeyToken,
+    fetched: DateTime<Utc>
+}
+
+/// Access token exchanged from an API key with its refresh token, so the token is renewed
+/// using refresh instead of a new API key login
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiKeyToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Client address which the key is first received from, forwarded to auth server
+    pub ip: Option<IpAddr>
+}
+
+/// An API key which need to be exchanged or refreshed by the resolver
+#[derive(Debug, Clone)]
+pub struct KeyRequest {
+    pub key: String,
+    pub ip: Option<IpAddr>,
+    /// Current token of the key if it is stale
+    pub current: Option<ApiKeyToken>
+}
+
+#[derive(Debug, Clone)]
+struct Rejected {
+    hash: Vec<u8>,
+    until: DateTime<Utc>
+}
+
+fn key_hash(key: &str) -> Vec<u8>
+{
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Access tokens exchanged from API keys so the interceptor can accept an API key in place of
+/// an access token. Unknown and stale keys are sent to a resolver task which exchanges them
+/// through auth server, a key without resolver is not accepted. Keys refused by auth server are
+/// remembered for a while so they are not sent to auth server on every request.
+#[derive(Debug, Default)]
+pub struct ApiKeyTokens {
+    entries: RwLock<Vec<Entry>>,
+    rejected: RwLock<Vec<Rejected>>,
+    sender: RwLock<Option<mpsc::Sender<KeyRequest>>>,
+    duration: i64
+}
+
+impl ApiKeyTokens {
+
+    pub fn new(duration: i64) -> Self {
+        Self {
+            entries: RwLock::new(Vec::new()),
+            rejected: RwLock::new(Vec::new()),
+            sender: RwLock::new(None),
+            duration
+        }
+    }
+
+    /// Get access token of an API key, a stale token is still returned while it is refreshed
+    pub fn token(&self, key: &str, ip: Option<IpAddr>) -> Option<String> {
+        let hash = key_hash(key);
+        let entry = self.entries.read().unwrap().iter()
+            .find(|e| bool::from(e.hash.ct_eq(&hash)))
+            .cloned()?;
+        if entry.fetched + Duration::seconds(self.duration) <= Utc::now() {
+            self.send(KeyRequest { key: key.to_owned(), ip, current: Some(entry.token.clone()) });
+        }
+        Some(entry.token.access_token)
+    }
+
+    /// Check whether an API key is recently refused by auth server
+    pub fn is_rejected(&self, key: &str) -> bool {
+        let hash = key_hash(key);
+        let now = Utc::now();
+        self.rejected.read().unwrap().iter()
+            .any(|r| r.until > now && bool::from(r.hash.ct_eq(&hash)))
+    }
+
+    /// Ask the resolver to exchange an API key, return false if there is no resolver
+    pub fn request(&self, key: &str, ip: Option<IpAddr>) -> bool {
+        self.send(KeyRequest { key: key.to_owned(), ip, current: None })
+    }
+
+    fn send(&self, request: KeyRequest) -> bool {
+        match self.sender.read().unwrap().as_ref() {
+            Some(sender) => {
+                sender.try_send(request).ok();
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn insert(&self, key: &str, token: ApiKeyToken) {
+        let hash = key_hash(key);
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|e| e.hash != hash);
+        entries.push(Entry { hash, token, fetched: Utc::now() });
+    }
+
+    pub fn remove(&self, key: &str) {
+        let hash = key_hash(key);
+        self.entries.write().unwrap().retain(|e| e.hash != hash);
+    }
+
+    /// Remove an API key and refuse it without asking auth server until rejection duration pass
+    pub fn reject(&self, key: &str) {
+        self.remove(key);
+        let hash = key_hash(key);
+        let now = Utc::now();
+        let mut rejected = self.rejected.write().unwrap();
+        rejected.retain(|r| r.until > now && r.hash != hash);
+        if rejected.len() >= MAX_REJECTED_ENTRIES {
+            rejected.remove(0);
+        }
+        rejected.push(Rejected { hash, until: now + Duration::seconds(REJECT_DURATION) });
+    }
+
+    /// Register the resolver and get API keys which need to be exchanged
+    pub fn subscribe(&self) -> mpsc::Receiver<KeyRequest> {
+        let (sender, receiver) = mpsc::channel(PENDING_CHANNEL_SIZE);
+        *self.sender.write().unwrap() = Some(sender);
+        receiver
+    }
+
+}
+
+pub fn api_key_tokens() -> &'static ApiKeyTokens {
+    API_KEY_TOKENS.get_or_init(|| {
+        let duration = std::env::var("API_KEY_CACHE_DURATION").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_CACHE_DURATION);
+        ApiKeyTokens::new(duration)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_key_has_prefix() {
+        let (prefix, key) = generate_api_key();
+        assert!(is_api_key(&key));
+        assert_eq!(api_key_prefix(&key), Some(prefix.as_str()));
+    }
+
+    #[test]
+    fn api_key_prefix_rejects_other_format() {
+        assert_eq!(api_key_prefix("eyJhbGciOiJSUzI1NiJ9.e30.sig"), None);
+        assert_eq!(api_key_prefix("rmk_short_secret"), None);
+        assert_eq!(api_key_prefix(&format!("abc_{}_{}", "a".repeat(8), "b".repeat(40))), None);
+        assert_eq!(api_key_prefix(&format!("rmk_{}_{}", "a".repeat(8), "b".repeat(40))), Some("aaaaaaaa"));
+    }
+
+    #[test]
+    fn verify_api_key_compares_hash() {
+        let (_, key) = generate_api_key();
+        let hash = hash_api_key(&key);
+        assert!(verify_api_key(&key, &hash));
+        assert!(!verify_api_key(&key, &hash_api_key("rmk_other")));
+    }
+
+    #[test]
+    fn rejected_key_is_removed_and_refused() {
+        let tokens = ApiKeyTokens::new(60);
+        let (_, key) = generate_api_key();
+        tokens.insert(&key, ApiKeyToken { access_token: String::from("token"), ..Default::default() });
+        assert_eq!(tokens.token(&key, None), Some(String::from("token")));
+        tokens.reject(&key);
+        assert_eq!(tokens.token(&key, None), None);
+        assert!(tokens.is_rejected(&key));
+    }
+
+}
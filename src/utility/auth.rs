@@ -1,36 +1,274 @@
-use tonic::{Request, transport::Channel};
+use std::time::Duration;
+use std::net::IpAddr;
+use tonic::{Request, Status, Code, transport::Channel, metadata::MetadataValue};
+use chrono::{Utc, TimeZone};
 use uuid::Uuid;
 use rmcs_auth_api::auth::auth_service_client::AuthServiceClient;
 use rmcs_auth_api::auth::{
-    ApiKeyRequest, ApiLoginRequest, ApiLoginResponse
+    ApiKeyRequest, ApiLoginRequest, ApiLoginResponse,
+    UserKeyRequest, UserLoginRequest, UserLoginResponse, RevokedTokenRequest, SigningKeyRequest,
+    ApiKeyLoginRequest, ApiProcedureRequest, UserRefreshRequest, UserRefreshResponse
 };
-use super::{generate_transport_keys, export_public_key, import_public_key, encrypt_message, decrypt_message};
+use super::revoke::revoked_tokens;
+use super::api_key::{api_key_tokens, ApiKeyToken};
+use super::network::FORWARDED_HEADER;
+use super::signing::{PublicKey, SigningAlgorithm};
+use super::validator::{AccessTable, AccessSchema, ParentSchema};
+use super::{import_public_key, encrypt_message};
+
+const REVOKE_RECONNECT_DELAY: u64 = 5;
 
 pub async fn api_login(addr: &str, api_id: Uuid, password: &str)
     -> Option<ApiLoginResponse>
 {
+    api_request(addr, api_id, password, true).await
+}
+
+/// Get procedures access of an API, the same as login since no API key is issued on login
+pub async fn api_access(addr: &str, api_id: Uuid, password: &str)
+    -> Option<ApiLoginResponse>
+{
+    api_request(addr, api_id, password, false).await
+}
+
+async fn api_request(addr: &str, api_id: Uuid, password: &str, login: bool)
+    -> Option<ApiLoginResponse>
+{
+    // return none instead of panic when auth server is unreachable, so periodic refresh can retry
     let channel = Channel::from_shared(addr.to_owned())
         .expect("Invalid address")
         .connect()
         .await
-        .expect(&format!("Error making channel to {}", addr));
+        .ok()?;
     let mut client = AuthServiceClient::new(channel.to_owned());
+    let passhash = api_password(&mut client, password).await?;
+    // request procedures access from server, tokens are verified using published public keys
+    // so no key is received
+    let request = Request::new(ApiLoginRequest {
+        api_id: api_id.as_bytes().to_vec(),
+        password: passhash
+    });
+    let response = if login {
+        client.api_login(request).await.ok()?.into_inner()
+    } else {
+        client.api_access(request).await.ok()?.into_inner()
+    };
+    Some(response)
+}
+
+/// Get transport public key of API login and encrypt the password
+async fn api_password(client: &mut AuthServiceClient<Channel>, password: &str)
+    -> Option<Vec<u8>>
+{
     let request = Request::new(ApiKeyRequest {
     });
-    // get transport public key of requested API and encrypt the password
     let response = client.api_login_key(request).await.ok()?.into_inner();
     let pub_key = import_public_key(response.public_key.as_slice()).ok()?;
+    encrypt_message(password.as_bytes(), pub_key).ok()
+}
+
+/// Get public keys used to verify access tokens signed by auth server
+pub async fn signing_keys(addr: &str)
+    -> Option<Vec<PublicKey>>
+{
+    let channel = Channel::from_shared(addr.to_owned())
+        .expect("Invalid address")
+        .connect()
+        .await
+        .ok()?;
+    let mut client = AuthServiceClient::new(channel);
+    let request = Request::new(SigningKeyRequest {
+    });
+    let response = client.list_signing_key(request).await.ok()?.into_inner();
+    let keys = response.keys.into_iter().map(|k| PublicKey {
+        kid: k.kid,
+        algorithm: SigningAlgorithm::from(k.algorithm.as_str()),
+        modulus: k.modulus,
+        exponent: k.exponent,
+        public_key: k.public_key,
+        expire: if k.expire == 0 { None } else { Some(Utc.timestamp_nanos(k.expire * 1000)) }
+    })
+    .collect();
+    Some(keys)
+}
+
+pub async fn user_login(addr: &str, username: &str, password: &str)
+    -> Option<UserLoginResponse>
+{
+    let channel = Channel::from_shared(addr.to_owned())
+        .expect("Invalid address")
+        .connect()
+        .await
+        .ok()?;
+    let mut client = AuthServiceClient::new(channel.to_owned());
+    let request = Request::new(UserKeyRequest {
+    });
+    // get transport public key of user login and encrypt the password
+    let response = client.user_login_key(request).await.ok()?.into_inner();
+    let pub_key = import_public_key(response.public_key.as_slice()).ok()?;
     let passhash = encrypt_message(password.as_bytes(), pub_key).ok()?;
-    // request API key and procedures access from server
-    let (priv_key, pub_key) = generate_transport_keys().ok()?;
-    let pub_der = export_public_key(pub_key).ok()?;
-    let request = Request::new(ApiLoginRequest {
-        api_id: api_id.as_bytes().to_vec(),
+    let request = Request::new(UserLoginRequest {
+        username: username.to_owned(),
         password: passhash,
-        public_key: pub_der
+        roles: Vec::new()
     });
-    let mut response = client.api_login(request).await.ok()?.into_inner();
-    response.root_key = decrypt_message(&response.root_key, priv_key.clone()).ok()?;
-    response.access_key = decrypt_message(&response.access_key, priv_key).ok()?;
+    let response = client.user_login(request).await.ok()?.into_inner();
     Some(response)
 }
+
+/// Set client address of a request made on behalf of the client, auth server use it for login
+/// throttle and role IP allowlist when this server is one of its trusted proxies
+fn forward_ip<T>(request: &mut Request<T>, ip: Option<IpAddr>)
+{
+    if let Some(value) = ip.and_then(|ip| MetadataValue::try_from(ip.to_string()).ok()) {
+        request.metadata_mut().insert(FORWARDED_HEADER, value);
+    }
+}
+
+/// Exchange an API key of a service account for access tokens
+pub async fn api_key_login(addr: &str, key: &str, ip: Option<IpAddr>)
+    -> Result<UserLoginResponse, Status>
+{
+    let channel = Channel::from_shared(addr.to_owned())
+        .expect("Invalid address")
+        .connect()
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+    let mut client = AuthServiceClient::new(channel);
+    let mut request = Request::new(ApiKeyLoginRequest {
+        key: key.to_owned(),
+        roles: Vec::new()
+    });
+    forward_ip(&mut request, ip);
+    let response = client.api_key_login(request).await?.into_inner();
+    Ok(response)
+}
+
+/// Refresh an access token exchanged from an API key
+pub async fn api_key_refresh(addr: &str, api_id: Uuid, token: &ApiKeyToken)
+    -> Result<UserRefreshResponse, Status>
+{
+    let channel = Channel::from_shared(addr.to_owned())
+        .expect("Invalid address")
+        .connect()
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+    let mut client = AuthServiceClient::new(channel);
+    let mut request = Request::new(UserRefreshRequest {
+        api_id: api_id.as_bytes().to_vec(),
+        access_token: token.access_token.clone(),
+        refresh_token: token.refresh_token.clone()
+    });
+    forward_ip(&mut request, token.ip);
+    let response = client.user_refresh(request).await?.into_inner();
+    Ok(response)
+}
+
+/// Exchange API keys received by the interceptor for access tokens of an API and keep them in
+/// local API key token list. A stale token is refreshed, and the key is only sent to auth server
+/// again when refresh fails. A key refused by auth server is rejected for a while, and a key
+/// which can not be exchanged because auth server is unreachable is only removed.
+pub async fn resolve_api_keys(addr: &str, api_id: Uuid)
+{
+    let mut receiver = api_key_tokens().subscribe();
+    while let Some(request) = receiver.recv().await {
+        if let Some(current) = request.current {
+            if let Ok(response) = api_key_refresh(addr, api_id, &current).await {
+                let token = ApiKeyToken {
+                    access_token: response.access_token,
+                    refresh_token: response.refresh_token,
+                    ip: current.ip
+                };
+                api_key_tokens().insert(&request.key, token);
+                continue;
+            }
+        }
+        let result = api_key_login(addr, &request.key, request.ip).await
+            .map(|response| response.access_tokens.into_iter()
+                .find(|t| t.api_id == api_id.as_bytes())
+            );
+        match result {
+            Ok(Some(value)) => {
+                let token = ApiKeyToken {
+                    access_token: value.access_token,
+                    refresh_token: value.refresh_token,
+                    ip: request.ip
+                };
+                api_key_tokens().insert(&request.key, token);
+            },
+            Err(e) if e.code() == Code::Unavailable => api_key_tokens().remove(&request.key),
+            _ => api_key_tokens().reject(&request.key)
+        }
+    }
+}
+
+/// Register procedures of an API using the API credential, return names of created procedures
+pub async fn register_procedures(addr: &str, api_id: Uuid, password: &str, procedures: &[&str])
+    -> Option<Vec<String>>
+{
+    let channel = Channel::from_shared(addr.to_owned())
+        .expect("Invalid address")
+        .connect()
+        .await
+        .ok()?;
+    let mut client = AuthServiceClient::new(channel);
+    let passhash = api_password(&mut client, password).await?;
+    let request = Request::new(ApiProcedureRequest {
+        api_id: api_id.as_bytes().to_vec(),
+        password: passhash,
+        procedures: procedures.iter().map(|&p| p.to_owned()).collect()
+    });
+    let response = client.api_register_procedure(request).await.ok()?.into_inner();
+    Some(response.procedures)
+}
+
+/// Refresh signing keys and procedure accesses of an access table periodically so role access
+/// changes and signing key rotation apply without restarting the server, this function never return
+pub async fn refresh_accesses(addr: &str, api_id: Uuid, password: &str, table: &AccessTable, interval: u64)
+{
+    let mut interval = tokio::time::interval(Duration::from_secs(interval));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let response = api_access(addr, api_id, password).await;
+        let keys = signing_keys(addr).await;
+        match (response, keys) {
+            (Some(response), Some(keys)) => {
+                let accesses: Vec<AccessSchema> = response.access_procedures
+                    .into_iter()
+                    .map(|s| s.into())
+                    .collect();
+                let parents: Vec<ParentSchema> = response.role_parents
+                    .into_iter()
+                    .map(|s| s.into())
+                    .collect();
+                table.update(&keys, &accesses, &parents);
+            },
+            _ => tracing::warn!("Failed to refresh procedure accesses from Auth server")
+        }
+    }
+}
+
+/// Receive revoked access tokens from auth server and keep them in local revoked token list,
+/// the stream is reconnected when broken so this function never return
+pub async fn receive_revoked_tokens(addr: &str)
+{
+    loop {
+        let channel = Channel::from_shared(addr.to_owned())
+            .expect("Invalid address")
+            .connect()
+            .await;
+        if let Ok(channel) = channel {
+            let mut client = AuthServiceClient::new(channel);
+            let request = Request::new(RevokedTokenRequest {
+            });
+            if let Ok(response) = client.stream_revoked_token(request).await {
+                let mut stream = response.into_inner();
+                while let Ok(Some(token)) = stream.message().await {
+                    revoked_tokens().insert(token.jti, Utc.timestamp_nanos(token.expire * 1000));
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(REVOKE_RECONNECT_DELAY)).await;
+    }
+}
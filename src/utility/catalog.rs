@@ -0,0 +1,323 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use rmcs_resource_db::{DataType, DataValue};
+
+/// Version of catalog document format, increased when the document layout changes
+pub const CATALOG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogFormat {
+    Json,
+    Yaml
+}
+
+impl From<i32> for CatalogFormat {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Yaml,
+            _ => Self::Json
+        }
+    }
+}
+
+impl From<CatalogFormat> for i32 {
+    fn from(value: CatalogFormat) -> Self {
+        match value {
+            CatalogFormat::Json => 0,
+            CatalogFormat::Yaml => 1
+        }
+    }
+}
+
+/// Config value written with its data type name, e.g. `type: u16` and `value: 502`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum CatalogValue {
+    #[default]
+    Null,
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    /// Value of a data type without plain form, kept as data type code and encoded bytes
+    Encoded { data_type: i32, bytes: Vec<u8> }
+}
+
+impl From<DataValue> for CatalogValue {
+    fn from(value: DataValue) -> Self {
+        match value {
+            DataValue::Null => Self::Null,
+            DataValue::I8(v) => Self::I8(v),
+            DataValue::I16(v) => Self::I16(v),
+            DataValue::I32(v) => Self::I32(v),
+            DataValue::I64(v) => Self::I64(v),
+            DataValue::U8(v) => Self::U8(v),
+            DataValue::U16(v) => Self::U16(v),
+            DataValue::U32(v) => Self::U32(v),
+            DataValue::U64(v) => Self::U64(v),
+            DataValue::F32(v) => Self::F32(v),
+            DataValue::F64(v) => Self::F64(v),
+            DataValue::Bool(v) => Self::Bool(v),
+            DataValue::Char(v) => Self::Char(v),
+            DataValue::String(v) => Self::String(v),
+            value => Self::Encoded { data_type: value.get_type().into(), bytes: value.to_bytes() }
+        }
+    }
+}
+
+impl From<CatalogValue> for DataValue {
+    fn from(value: CatalogValue) -> Self {
+        match value {
+            CatalogValue::Null => Self::Null,
+            CatalogValue::I8(v) => Self::I8(v),
+            CatalogValue::I16(v) => Self::I16(v),
+            CatalogValue::I32(v) => Self::I32(v),
+            CatalogValue::I64(v) => Self::I64(v),
+            CatalogValue::U8(v) => Self::U8(v),
+            CatalogValue::U16(v) => Self::U16(v),
+            CatalogValue::U32(v) => Self::U32(v),
+            CatalogValue::U64(v) => Self::U64(v),
+            CatalogValue::F32(v) => Self::F32(v),
+            CatalogValue::F64(v) => Self::F64(v),
+            CatalogValue::Bool(v) => Self::Bool(v),
+            CatalogValue::Char(v) => Self::Char(v),
+            CatalogValue::String(v) => Self::String(v),
+            CatalogValue::Encoded { data_type, bytes } => Self::from_bytes(&bytes, DataType::from(data_type))
+        }
+    }
+}
+
+/// Data type written by the same name as the `type` of a config value, types without
+/// a name are written by their code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CatalogDataType(pub i32);
+
+impl CatalogDataType {
+    fn names() -> Vec<(&'static str, i32)> {
+        [
+            ("null", CatalogValue::Null),
+            ("i8", CatalogValue::I8(0)),
+            ("i16", CatalogValue::I16(0)),
+            ("i32", CatalogValue::I32(0)),
+            ("i64", CatalogValue::I64(0)),
+            ("u8", CatalogValue::U8(0)),
+            ("u16", CatalogValue::U16(0)),
+            ("u32", CatalogValue::U32(0)),
+            ("u64", CatalogValue::U64(0)),
+            ("f32", CatalogValue::F32(0.0)),
+            ("f64", CatalogValue::F64(0.0)),
+            ("bool", CatalogValue::Bool(false)),
+            ("char", CatalogValue::Char(' ')),
+            ("string", CatalogValue::String(String::new()))
+        ]
+        .into_iter()
+        .map(|(name, value)| (name, DataValue::from(value).get_type().into()))
+        .collect()
+    }
+}
+
+impl From<DataType> for CatalogDataType {
+    fn from(value: DataType) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<CatalogDataType> for DataType {
+    fn from(value: CatalogDataType) -> Self {
+        DataType::from(value.0)
+    }
+}
+
+impl From<CatalogDataType> for String {
+    fn from(value: CatalogDataType) -> Self {
+        match CatalogDataType::names().into_iter().find(|(_, code)| *code == value.0) {
+            Some((name, _)) => name.to_owned(),
+            None => value.0.to_string()
+        }
+    }
+}
+
+impl TryFrom<String> for CatalogDataType {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match Self::names().into_iter().find(|(name, _)| *name == value) {
+            Some((_, code)) => Ok(Self(code)),
+            None => value.parse().map(Self).map_err(|_| format!("unknown data type '{}'", value))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogConfig {
+    pub name: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(flatten)]
+    pub value: CatalogValue
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogModelConfig {
+    pub index: i32,
+    #[serde(flatten)]
+    pub config: CatalogConfig
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogTag {
+    pub tag: i16,
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<i16>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogModel {
+    pub id: Uuid,
+    pub category: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub data_type: Vec<CatalogDataType>,
+    #[serde(default)]
+    pub tags: Vec<CatalogTag>,
+    #[serde(default)]
+    pub configs: Vec<CatalogModelConfig>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogType {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub models: Vec<Uuid>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogGateway {
+    pub id: Uuid,
+    pub type_id: Uuid,
+    pub serial_number: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub configs: Vec<CatalogConfig>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogDevice {
+    pub id: Uuid,
+    pub gateway_id: Uuid,
+    pub type_id: Uuid,
+    pub serial_number: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub configs: Vec<CatalogConfig>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogGroup {
+    pub id: Uuid,
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub members: Vec<Uuid>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogSetMember {
+    pub device_id: Uuid,
+    pub model_id: Uuid,
+    pub data_index: Vec<u8>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogSet {
+    pub id: Uuid,
+    pub template_id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub members: Vec<CatalogSetMember>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogTemplateMember {
+    pub type_id: Uuid,
+    pub model_id: Uuid,
+    pub data_index: Vec<u8>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogTemplate {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub members: Vec<CatalogTemplateMember>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    pub version: u32,
+    #[serde(default)]
+    pub models: Vec<CatalogModel>,
+    #[serde(default)]
+    pub types: Vec<CatalogType>,
+    #[serde(default)]
+    pub gateways: Vec<CatalogGateway>,
+    #[serde(default)]
+    pub devices: Vec<CatalogDevice>,
+    #[serde(default)]
+    pub group_models: Vec<CatalogGroup>,
+    #[serde(default)]
+    pub group_devices: Vec<CatalogGroup>,
+    #[serde(default)]
+    pub group_gateways: Vec<CatalogGroup>,
+    #[serde(default)]
+    pub set_templates: Vec<CatalogTemplate>,
+    #[serde(default)]
+    pub sets: Vec<CatalogSet>
+}
+
+impl Catalog {
+
+    pub fn encode(&self, format: CatalogFormat) -> Result<String, String> {
+        match format {
+            CatalogFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            CatalogFormat::Yaml => serde_yaml::to_string(self).map_err(|e| e.to_string())
+        }
+    }
+
+    pub fn decode(content: &str, format: CatalogFormat) -> Result<Self, String> {
+        let catalog: Self = match format {
+            CatalogFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+            CatalogFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?
+        };
+        if catalog.version != CATALOG_VERSION {
+            return Err(format!("unsupported catalog version {}, expected {}", catalog.version, CATALOG_VERSION));
+        }
+        Ok(catalog)
+    }
+
+}
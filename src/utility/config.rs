@@ -2,6 +2,17 @@ use std::sync::OnceLock;
 use uuid::Uuid;
 use rsa::RsaPrivateKey;
 use super::{generate_transport_keys, export_public_key};
+use super::revoke::RevokedTokens;
+use super::signing::SigningKeys;
+use super::mfa::MfaChallenges;
+use super::throttle::LoginThrottle;
+use super::password::PasswordPolicy;
+use super::notifier::Notifier;
+use super::verification::Verifications;
+use super::api_key::ApiKeyTokens;
+use ipnet::IpNet;
+use tokio::sync::broadcast;
+use rmcs_resource_api::device::ConfigChange;
 use rmcs_auth_db::schema::auth_user::{UserSchema, UserRoleSchema};
 use rmcs_auth_db::utility::generate_access_key;
 
@@ -11,6 +22,18 @@ pub static ROOT_DATA: OnceLock<RootData> = OnceLock::new();
 
 pub static API_KEY: OnceLock<TransportKey> = OnceLock::new();
 pub static USER_KEY: OnceLock<TransportKey> = OnceLock::new();
+pub static REVOKED_TOKENS: OnceLock<RevokedTokens> = OnceLock::new();
+pub static SIGNING_KEYS: OnceLock<SigningKeys> = OnceLock::new();
+pub static MFA_CHALLENGES: OnceLock<MfaChallenges> = OnceLock::new();
+pub static MFA_KEY: OnceLock<Option<[u8; 32]>> = OnceLock::new();
+pub static LOGIN_THROTTLE: OnceLock<LoginThrottle> = OnceLock::new();
+pub static PASSWORD_POLICY: OnceLock<PasswordPolicy> = OnceLock::new();
+pub static NOTIFIER: OnceLock<Box<dyn Notifier>> = OnceLock::new();
+pub static SMS_NOTIFIER: OnceLock<Box<dyn Notifier>> = OnceLock::new();
+pub static VERIFICATIONS: OnceLock<Verifications> = OnceLock::new();
+pub static TRUSTED_PROXIES: OnceLock<Vec<IpNet>> = OnceLock::new();
+pub static API_KEY_TOKENS: OnceLock<ApiKeyTokens> = OnceLock::new();
+pub static CONFIG_CHANGES: OnceLock<broadcast::Sender<ConfigChange>> = OnceLock::new();
 
 const DEF_ROOT_PW: &str = "r0ot_P4s5w0rd";
 const DEF_ACC_DUR: i32 = 300;
@@ -22,7 +45,9 @@ pub struct RootData {
     pub password: String,
     pub access_duration: i32,
     pub refresh_duration: i32,
-    pub access_key: Vec<u8>
+    pub access_key: Vec<u8>,
+    /// Base32 TOTP secret, root login requires OTP when it is set
+    pub mfa_secret: Option<String>
 }
 
 impl Default for RootData {
@@ -31,7 +56,8 @@ impl Default for RootData {
             password: String::from(DEF_ROOT_PW),
             access_duration: DEF_ACC_DUR,
             refresh_duration: DEF_REF_DUR,
-            access_key: DEF_ACC_KEY.to_vec()
+            access_key: DEF_ACC_KEY.to_vec(),
+            mfa_secret: None
         }
     }
 }
@@ -42,7 +68,8 @@ impl RootData {
             password: String::from(password),
             access_duration,
             refresh_duration,
-            access_key: generate_access_key()
+            access_key: generate_access_key(),
+            mfa_secret: None
         }
     }
     pub fn new_with_key(password: &str, access_duration: i32, refresh_duration: i32, root_key: &[u8]) -> Self {
@@ -50,9 +77,14 @@ impl RootData {
             password: String::from(password),
             access_duration,
             refresh_duration,
-            access_key: root_key.to_vec()
+            access_key: root_key.to_vec(),
+            mfa_secret: None
         }
     }
+    pub fn with_mfa_secret(mut self, secret: &str) -> Self {
+        self.mfa_secret = Some(secret.to_owned());
+        self
+    }
 }
 
 impl Into<UserSchema> for RootData {
@@ -63,11 +95,14 @@ impl Into<UserSchema> for RootData {
             email: String::new(),
             phone: String::new(),
             password: String::from(self.password),
+            tenant_id: None,
+            service: false,
             roles: vec![UserRoleSchema {
                 api_id: ROOT_ID,
                 role: ROOT_NAME.to_owned(),
                 multi: false,
                 ip_lock: true,
+                ip_allow: Vec::new(),
                 access_duration: self.access_duration,
                 refresh_duration: self.refresh_duration,
                 access_key: self.access_key.to_vec()
@@ -1,4 +1,7 @@
 use tonic::{Status, Request, service::Interceptor, metadata::MetadataValue};
+use super::network::{remote_ip, RemoteIp};
+use super::api_key::{is_api_key, api_key_tokens};
+use super::validator::{AccessTable, Caller, verify_token};
 
 #[derive(Debug, Clone)]
 pub struct TokenInterceptor(pub String);
@@ -26,6 +29,51 @@ pub fn interceptor(mut request: Request<()>) -> Result<Request<()>, Status>
         Some(value) => value.to_owned(),
         None => return Err(Status::unauthenticated("authorization header must in format 'Bearer <TOKEN>'"))
     };
+    let ip = remote_ip(&request);
+    // replace API key with its exchanged access token, an unknown key is exchanged in background
+    // so the client should retry shortly
+    let token = if is_api_key(&token) {
+        if api_key_tokens().is_rejected(&token) {
+            return Err(Status::unauthenticated("API key is not accepted by this server"));
+        }
+        match api_key_tokens().token(&token, ip) {
+            Some(value) => value,
+            None if api_key_tokens().request(&token, ip) => return Err(Status::unavailable("API key is being verified, retry the request")),
+            None => return Err(Status::unauthenticated("API key is not accepted by this server"))
+        }
+    } else {
+        token
+    };
     request.extensions_mut().insert(token);
+    // keep client address for validators which check role IP allowlist
+    if let Some(ip) = ip {
+        request.extensions_mut().insert(RemoteIp(ip));
+    }
     Ok(request)
 }
+
+/// Interceptor of resource services which decode the access token once per request with verifying
+/// keys of the shared access table and insert the caller to request extensions. A token which can
+/// not be verified is left for validator of the service to report the error.
+#[derive(Debug, Clone)]
+pub struct AccessInterceptor {
+    table: AccessTable
+}
+
+impl AccessInterceptor {
+    pub fn new(table: &AccessTable) -> Self {
+        Self { table: table.clone() }
+    }
+}
+
+impl Interceptor for AccessInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let mut request = interceptor(request)?;
+        let token = request.extensions().get::<String>().cloned().unwrap_or_default();
+        let ip = request.extensions().get::<RemoteIp>().copied();
+        if let Ok(claims) = verify_token(&token, &self.table.verifying_keys(), ip) {
+            request.extensions_mut().insert(Caller::from(claims));
+        }
+        Ok(request)
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use uuid::Uuid;
+use rmcs_resource_db::DataValue;
+use rmcs_resource_api::device::{TypeSchema, GatewaySchema, DeviceSchema, ConfigSchema};
+use rmcs_resource_api::group::GroupDevice;
+use rmcs_resource_api::provision::ProvisionManifest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Csv
+}
+
+impl ManifestFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Some(Self::Json),
+            Some("csv") => Some(Self::Csv),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestType {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub description: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestGateway {
+    pub id: Uuid,
+    pub type_id: Uuid,
+    pub serial_number: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestDevice {
+    pub id: Uuid,
+    pub gateway_id: Uuid,
+    pub type_id: Uuid,
+    pub serial_number: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestConfig {
+    pub owner_id: Uuid,
+    pub name: String,
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub category: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestMember {
+    pub group_id: Uuid,
+    pub device_id: Uuid
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub types: Vec<ManifestType>,
+    #[serde(default)]
+    pub gateways: Vec<ManifestGateway>,
+    #[serde(default)]
+    pub devices: Vec<ManifestDevice>,
+    #[serde(default)]
+    pub gateway_configs: Vec<ManifestConfig>,
+    #[serde(default)]
+    pub device_configs: Vec<ManifestConfig>,
+    #[serde(default)]
+    pub group_members: Vec<ManifestMember>
+}
+
+/// A row of CSV manifest, the `record` column select which entry kind the row describe
+#[derive(Debug, Deserialize)]
+struct ManifestRecord {
+    record: String,
+    #[serde(default)]
+    id: Option<Uuid>,
+    #[serde(default)]
+    type_id: Option<Uuid>,
+    #[serde(default)]
+    gateway_id: Option<Uuid>,
+    #[serde(default)]
+    device_id: Option<Uuid>,
+    #[serde(default)]
+    group_id: Option<Uuid>,
+    #[serde(default)]
+    serial_number: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    category: String
+}
+
+impl Manifest {
+
+    pub fn read(path: &Path, format: ManifestFormat) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        match format {
+            ManifestFormat::Json => Self::from_json(&content),
+            ManifestFormat::Csv => Self::from_csv(&content)
+        }
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        serde_json::from_str(content).map_err(|e| format!("invalid JSON manifest: {}", e))
+    }
+
+    pub fn from_csv(content: &str) -> Result<Self, String> {
+        let mut manifest = Self::default();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes());
+        for (i, row) in reader.deserialize::<ManifestRecord>().enumerate() {
+            // line number is offset by header row
+            let line = i + 2;
+            let row = row.map_err(|e| format!("invalid CSV manifest at line {}: {}", line, e))?;
+            let required = |value: Option<Uuid>, column: &str| value
+                .ok_or(format!("missing {} column at line {}", column, line));
+            match row.record.as_str() {
+                "type" => manifest.types.push(ManifestType {
+                    id: required(row.id, "id")?,
+                    name: row.name,
+                    description: row.description
+                }),
+                "gateway" => manifest.gateways.push(ManifestGateway {
+                    id: required(row.id, "id")?,
+                    type_id: required(row.type_id, "type_id")?,
+                    serial_number: row.serial_number,
+                    name: row.name,
+                    description: row.description
+                }),
+                "device" => manifest.devices.push(ManifestDevice {
+                    id: required(row.id, "id")?,
+                    gateway_id: required(row.gateway_id, "gateway_id")?,
+                    type_id: required(row.type_id, "type_id")?,
+                    serial_number: row.serial_number,
+                    name: row.name,
+                    description: row.description
+                }),
+                "gateway_config" => manifest.gateway_configs.push(ManifestConfig {
+                    owner_id: required(row.gateway_id, "gateway_id")?,
+                    name: row.name,
+                    value: csv_value(&row.value),
+                    category: row.category
+                }),
+                "device_config" => manifest.device_configs.push(ManifestConfig {
+                    owner_id: required(row.device_id, "device_id")?,
+                    name: row.name,
+                    value: csv_value(&row.value),
+                    category: row.category
+                }),
+                "group_member" => manifest.group_members.push(ManifestMember {
+                    group_id: required(row.group_id, "group_id")?,
+                    device_id: required(row.device_id, "device_id")?
+                }),
+                other => return Err(format!("unknown record kind '{}' at line {}", other, line))
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn into_request(self, dry_run: bool) -> Result<ProvisionManifest, String> {
+        let schema_type = |id: Uuid| TypeSchema { id: id.as_bytes().to_vec(), ..Default::default() };
+        let configs = |configs: Vec<ManifestConfig>| configs.into_iter()
+            .map(|c| {
+                let value = config_value(&c.value)
+                    .ok_or(format!("unsupported value of config '{}'", c.name))?;
+                Ok(ConfigSchema {
+                    device_id: c.owner_id.as_bytes().to_vec(),
+                    name: c.name,
+                    config_bytes: value.to_bytes(),
+                    config_type: value.get_type().into(),
+                    category: c.category,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<ConfigSchema>, String>>();
+        Ok(ProvisionManifest {
+            types: self.types.into_iter().map(|t| TypeSchema {
+                id: t.id.as_bytes().to_vec(),
+                name: t.name,
+                description: t.description,
+                ..Default::default()
+            }).collect(),
+            gateways: self.gateways.into_iter().map(|g| GatewaySchema {
+                id: g.id.as_bytes().to_vec(),
+                gateway_type: Some(schema_type(g.type_id)),
+                serial_number: g.serial_number,
+                name: g.name,
+                description: g.description,
+                ..Default::default()
+            }).collect(),
+            devices: self.devices.into_iter().map(|d| DeviceSchema {
+                id: d.id.as_bytes().to_vec(),
+                gateway_id: d.gateway_id.as_bytes().to_vec(),
+                device_type: Some(schema_type(d.type_id)),
+                serial_number: d.serial_number,
+                name: d.name,
+                description: d.description,
+                ..Default::default()
+            }).collect(),
+            gateway_configs: configs(self.gateway_configs)?,
+            device_configs: configs(self.device_configs)?,
+            group_members: self.group_members.into_iter().map(|m| GroupDevice {
+                id: m.group_id.as_bytes().to_vec(),
+                device_id: m.device_id.as_bytes().to_vec()
+            }).collect(),
+            dry_run
+        })
+    }
+
+}
+
+fn csv_value(value: &str) -> serde_json::Value {
+    // CSV cell has no type, so numbers and booleans are detected from the text
+    serde_json::from_str(value).unwrap_or(serde_json::Value::String(value.to_owned()))
+}
+
+fn config_value(value: &serde_json::Value) -> Option<DataValue> {
+    match value {
+        serde_json::Value::Bool(v) => Some(DataValue::Bool(*v)),
+        serde_json::Value::Number(v) => v.as_i64().map(|n| DataValue::I64(n))
+            .or(v.as_f64().map(|n| DataValue::F64(n))),
+        serde_json::Value::String(v) => Some(DataValue::String(v.to_owned())),
+        serde_json::Value::Null => Some(DataValue::Null),
+        _ => None
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc, Duration};
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
+use totp_rs::{TOTP, Algorithm, Secret};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, KeyInit};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_user::MfaChallengeSchema;
+use super::config::{MFA_CHALLENGES, MFA_KEY};
+use super::password::{hash_password, verify_password};
+
+const TOTP_ISSUER: &str = "RMCS";
+const TOTP_DIGITS: usize = 6;
+const TOTP_SKEW: u8 = 1;
+const TOTP_STEP: u64 = 30;
+const RECOVERY_CODE_NUMBER: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+/// Separator of recovery code hashes, PHC hash strings contain commas but never semicolons
+const RECOVERY_SEPARATOR: char = ';';
+const CHALLENGE_DURATION: i64 = 300;
+const NONCE_LENGTH: usize = 12;
+
+/// Profile name to mark a user or a role which requires MFA
+pub const MFA_REQUIRED: &str = "mfa_required";
+/// Profiles with this prefix are MFA data and never put on token claims
+pub const MFA_PROFILE_PREFIX: &str = "mfa_";
+
+/// Last accepted time step of root OTP, root secret is not stored in database
+static ROOT_STEP: AtomicU64 = AtomicU64::new(0);
+
+pub fn generate_secret() -> String
+{
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+fn totp(secret: &str, account: &str) -> Option<TOTP>
+{
+    let secret = Secret::Encoded(secret.to_owned()).to_bytes().ok()?;
+    TOTP::new(Algorithm::SHA1, TOTP_DIGITS, TOTP_SKEW, TOTP_STEP, secret, Some(TOTP_ISSUER.to_owned()), account.to_owned()).ok()
+}
+
+pub fn otpauth_url(secret: &str, account: &str) -> String
+{
+    totp(secret, account).map(|t| t.get_url()).unwrap_or_default()
+}
+
+/// Verify an OTP and return its time step so a caller can reject a step which is already used
+pub fn verify_code(secret: &str, code: &str) -> Option<u64>
+{
+    let totp = totp(secret, "")?;
+    let step = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() / TOTP_STEP;
+    let skew = TOTP_SKEW as u64;
+    (step.saturating_sub(skew)..=step + skew)
+        .find(|s| bool::from(totp.generate(s * TOTP_STEP).as_bytes().ct_eq(code.as_bytes())))
+}
+
+/// Accept a root OTP time step only once
+pub fn accept_root_step(step: u64) -> bool
+{
+    ROOT_STEP.fetch_max(step, Ordering::SeqCst) < step
+}
+
+pub fn is_mfa_profile(name: &str) -> bool
+{
+    name.starts_with(MFA_PROFILE_PREFIX) && name != MFA_REQUIRED
+}
+
+fn mfa_key() -> Option<&'static [u8; 32]>
+{
+    MFA_KEY.get_or_init(|| {
+        std::env::var("MFA_ENCRYPTION_KEY").ok()
+            .filter(|k| !k.is_empty())
+            .map(|k| Sha256::digest(k.as_bytes()).into())
+    })
+    .as_ref()
+}
+
+/// Encrypt a TOTP secret using the MFA encryption key, the nonce is prepended to the cipher text.
+/// Return none if the key is not configured.
+pub fn encrypt_secret(secret: &str) -> Option<Vec<u8>>
+{
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(mfa_key()?));
+    let mut nonce = [0u8; NONCE_LENGTH];
+    thread_rng().fill(&mut nonce);
+    let mut data = nonce.to_vec();
+    data.extend(cipher.encrypt(Nonce::from_slice(&nonce), secret.as_bytes()).ok()?);
+    Some(data)
+}
+
+pub fn decrypt_secret(data: &[u8]) -> Option<String>
+{
+    if data.len() < NONCE_LENGTH {
+        return None;
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(mfa_key()?));
+    let (nonce, cipher_text) = data.split_at(NONCE_LENGTH);
+    let plain = cipher.decrypt(Nonce::from_slice(nonce), cipher_text).ok()?;
+    String::from_utf8(plain).ok()
+}
+
+/// Generate recovery codes and return them with joined code hashes to be stored, codes are
+/// hashed with a salt like passwords
+pub fn generate_recovery_codes() -> Result<(Vec<String>, String), argon2::password_hash::Error>
+{
+    let codes: Vec<String> = (0..RECOVERY_CODE_NUMBER).map(|_| {
+        thread_rng().sample_iter(&Alphanumeric)
+            .take(RECOVERY_CODE_LENGTH)
+            .map(char::from)
+            .collect()
+    })
+    .collect();
+    let hashes = codes.iter()
+        .map(|c| hash_password(c.as_bytes()))
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok((codes, hashes.join(&RECOVERY_SEPARATOR.to_string())))
+}
+
+/// Check a recovery code against stored hashes and return the remaining hashes if the code is valid
+pub fn use_recovery_code(hashes: &str, code: &str) -> Option<String>
+{
+    // an OTP is never checked against every salted hash
+    if code.len() != RECOVERY_CODE_LENGTH {
+        return None;
+    }
+    let mut hashes: Vec<&str> = hashes.split(RECOVERY_SEPARATOR).filter(|h| !h.is_empty()).collect();
+    let index = hashes.iter().position(|h| verify_password(code.as_bytes(), h).is_ok())?;
+    hashes.remove(index);
+    Some(hashes.join(&RECOVERY_SEPARATOR.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MfaChallenge {
+    pub username: String,
+    pub remote_ip: Vec<u8>,
+    /// Role names requested on login
+    pub roles: Vec<String>,
+    pub expire: DateTime<Utc>
+}
+
+impl From<MfaChallengeSchema> for MfaChallenge {
+    fn from(value: MfaChallengeSchema) -> Self {
+        Self {
+            username: value.username,
+            remote_ip: value.remote_ip,
+            roles: value.roles,
+            expire: value.expire
+        }
+    }
+}
+
+/// Hash of a challenge id which is stored instead of the id
+fn challenge_hash(id: &str) -> Vec<u8>
+{
+    Sha256::digest(id.as_bytes()).to_vec()
+}
+
+/// Pending logins which password is verified but OTP is not yet submitted. Challenges are stored
+/// in auth database so the OTP can be submitted to any replica.
+#[derive(Debug, Default)]
+pub struct MfaChallenges {
+    duration: i64
+}
+
+impl MfaChallenges {
+
+    pub fn new(duration: i64) -> Self {
+        Self { duration }
+    }
+
+    pub async fn create(&self, auth_db: &Auth, username: &str, remote_ip: &[u8], roles: &[String]) -> Result<String, sqlx::Error> {
+        let now = Utc::now();
+        auth_db.delete_mfa_challenge_expired(now).await?;
+        let id = Uuid::new_v4().to_string();
+        let expire = now + Duration::seconds(self.duration);
+        auth_db.create_mfa_challenge(&challenge_hash(&id), username, remote_ip, roles, expire).await?;
+        Ok(id)
+    }
+
+    /// Remove a challenge and return it if not expired, a challenge can only be used once
+    pub async fn take(&self, auth_db: &Auth, id: &str) -> Option<MfaChallenge> {
+        // the row is deleted and returned in one statement so concurrent submits can not share it
+        let challenge: MfaChallenge = auth_db.take_mfa_challenge(&challenge_hash(id)).await.ok().flatten()?.into();
+        if challenge.expire > Utc::now() { Some(challenge) } else { None }
+    }
+
+}
+
+pub fn mfa_challenges() -> &'static MfaChallenges {
+    MFA_CHALLENGES.get_or_init(|| MfaChallenges::new(CHALLENGE_DURATION))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_code_is_used_once() {
+        let (codes, hashes) = generate_recovery_codes().unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_NUMBER);
+        let remaining = use_recovery_code(&hashes, &codes[3]).unwrap();
+        assert_eq!(remaining.split(RECOVERY_SEPARATOR).count(), RECOVERY_CODE_NUMBER - 1);
+        assert!(use_recovery_code(&remaining, &codes[3]).is_none());
+        assert!(use_recovery_code(&remaining, &codes[4]).is_some());
+    }
+
+    #[test]
+    fn recovery_code_hashes_are_salted() {
+        let (_, first) = generate_recovery_codes().unwrap();
+        let hash = first.split(RECOVERY_SEPARATOR).next().unwrap();
+        assert!(hash.starts_with("$argon2"));
+    }
+
+    #[test]
+    fn otp_is_not_a_recovery_code() {
+        let (_, hashes) = generate_recovery_codes().unwrap();
+        assert!(use_recovery_code(&hashes, "123456").is_none());
+    }
+
+}
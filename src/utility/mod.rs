@@ -3,12 +3,24 @@ pub mod config;
 pub mod validator;
 pub mod interceptor;
 pub mod auth;
+pub mod manifest;
+pub mod catalog;
+pub mod policy;
+pub mod revoke;
+pub mod signing;
+pub mod mfa;
+pub mod throttle;
+pub mod password;
+pub mod notifier;
+pub mod verification;
+pub mod network;
+pub mod scope;
+pub mod api_key;
 pub mod test;
 
 use sha2::Sha256;
 use rsa::{RsaPrivateKey, RsaPublicKey, Oaep};
 use pkcs8::{DecodePublicKey, EncodePublicKey};
-use argon2::{Argon2, PasswordVerifier, password_hash::PasswordHash};
 use rand::thread_rng;
 
 pub fn generate_transport_keys() -> Result<(RsaPrivateKey, RsaPublicKey), rsa::Error>
@@ -44,11 +56,12 @@ pub fn encrypt_message(message: &[u8], pub_key: RsaPublicKey) -> Result<Vec<u8>,
     pub_key.encrypt(&mut thread_rng(), padding, message)
 }
 
-pub(crate) fn verify_password(password: &[u8], hash: &str) -> Result<(), argon2::password_hash::Error>
-{
-    let argon2 = Argon2::default();
-    let parsed_hash = PasswordHash::new(&hash)?;
-    argon2.verify_password(password, &parsed_hash)
+pub(crate) use password::verify_password;
+
+/// Report a security relevant event, such as a stolen token, with `security` log target so it can
+/// be filtered and forwarded separately from other logs
+pub(crate) fn security_event(event: &str, detail: &str) {
+    tracing::warn!(target: "security", detail, "{}", event);
 }
 
 pub(crate) fn handle_error(e: sqlx::Error) -> tonic::Status {
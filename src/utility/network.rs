@@ -0,0 +1,179 @@
+use std::net::IpAddr;
+use tonic::Request;
+use ipnet::IpNet;
+use super::config::TRUSTED_PROXIES;
+
+pub const FORWARDED_HEADER: &str = "x-forwarded-for";
+
+/// Remote address of a request, inserted to request extensions by interceptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteIp(pub IpAddr);
+
+/// Convert IPv4-mapped IPv6 address to IPv4 address
+pub fn canonical_ip(ip: IpAddr) -> IpAddr
+{
+    match ip {
+        IpAddr::V6(v) => match v.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v)
+        },
+        IpAddr::V4(v) => IpAddr::V4(v)
+    }
+}
+
+pub fn ip_octets(ip: IpAddr) -> Vec<u8>
+{
+    match canonical_ip(ip) {
+        IpAddr::V4(v) => v.octets().to_vec(),
+        IpAddr::V6(v) => v.octets().to_vec()
+    }
+}
+
+pub fn ip_from_octets(octets: &[u8]) -> Option<IpAddr>
+{
+    match octets.len() {
+        4 => <[u8; 4]>::try_from(octets).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(octets).ok().map(|o| canonical_ip(IpAddr::from(o))),
+        _ => None
+    }
+}
+
+pub fn parse_cidrs(cidrs: &[String]) -> Result<Vec<IpNet>, String>
+{
+    cidrs.iter()
+        .map(|s| {
+            // single address is accepted as a full length prefix
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("invalid CIDR {}", s))
+        })
+        .collect()
+}
+
+/// Check an address against CIDR ranges, empty ranges allow every address
+pub fn ip_allowed(ip: IpAddr, cidrs: &[IpNet]) -> bool
+{
+    let ip = canonical_ip(ip);
+    cidrs.is_empty() || cidrs.iter().any(|net| net.contains(&ip))
+}
+
+/// Proxies whose X-Forwarded-For header is trusted, configured from TRUSTED_PROXIES environment
+/// variable as comma separated CIDR ranges
+pub fn trusted_proxies() -> &'static [IpNet] {
+    TRUSTED_PROXIES.get_or_init(|| {
+        let value = std::env::var("TRUSTED_PROXIES").unwrap_or_default();
+        let cidrs: Vec<String> = value.split(',')
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect();
+        parse_cidrs(&cidrs).unwrap_or_else(|e| {
+            tracing::error!("Failed to parse trusted proxies: {}", e);
+            Vec::new()
+        })
+    })
+}
+
+/// Get client address of a request. When the connection comes from a trusted proxy, the last
+/// address in X-Forwarded-For which is not a trusted proxy is used.
+pub fn remote_ip<T>(request: &Request<T>) -> Option<IpAddr>
+{
+    let peer = canonical_ip(request.remote_addr()?.ip());
+    let proxies = trusted_proxies();
+    if proxies.is_empty() || !ip_allowed(peer, proxies) {
+        return Some(peer);
+    }
+    let forwarded: Vec<IpAddr> = request.metadata()
+        .get_all(FORWARDED_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|s| s.trim().parse::<IpAddr>().ok())
+        .collect();
+    Some(forwarded_ip(peer, &forwarded, proxies))
+}
+
+/// Last forwarded address which is not a trusted proxy, or the peer address if every forwarded
+/// address is a trusted proxy
+pub fn forwarded_ip(peer: IpAddr, forwarded: &[IpAddr], proxies: &[IpNet]) -> IpAddr
+{
+    forwarded.iter()
+        .rev()
+        .map(|&ip| canonical_ip(ip))
+        .find(|&ip| !ip_allowed(ip, proxies))
+        .unwrap_or(peer)
+}
+
+/// Client address octets of a request, or empty if the address is unknown
+pub fn remote_octets<T>(request: &Request<T>) -> Vec<u8>
+{
+    remote_ip(request).map(ip_octets).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn cidrs(values: &[&str]) -> Vec<IpNet> {
+        parse_cidrs(&values.iter().map(|v| v.to_string()).collect::<Vec<String>>()).unwrap()
+    }
+
+    #[test]
+    fn parse_cidrs_accepts_ranges_and_single_addresses() {
+        let nets = cidrs(&["10.0.0.0/8", "192.168.1.7", "2001:db8::/32"]);
+        assert_eq!(nets.len(), 3);
+        assert_eq!(nets[1].to_string(), "192.168.1.7/32");
+    }
+
+    #[test]
+    fn parse_cidrs_rejects_invalid_range() {
+        let result = parse_cidrs(&[String::from("10.0.0.0/33")]);
+        assert_eq!(result, Err(String::from("invalid CIDR 10.0.0.0/33")));
+    }
+
+    #[test]
+    fn ip_allowed_checks_ranges() {
+        let nets = cidrs(&["10.0.0.0/8"]);
+        assert!(ip_allowed(ip("10.1.2.3"), &nets));
+        assert!(!ip_allowed(ip("11.1.2.3"), &nets));
+        assert!(ip_allowed(ip("11.1.2.3"), &[]));
+    }
+
+    #[test]
+    fn ip_allowed_matches_ipv4_mapped_address() {
+        let nets = cidrs(&["10.0.0.0/8"]);
+        assert!(ip_allowed(ip("::ffff:10.1.2.3"), &nets));
+    }
+
+    #[test]
+    fn ip_octets_round_trip() {
+        assert_eq!(ip_octets(ip("::ffff:10.1.2.3")), vec![10, 1, 2, 3]);
+        assert_eq!(ip_from_octets(&[10, 1, 2, 3]), Some(ip("10.1.2.3")));
+        assert_eq!(ip_from_octets(&[10, 1, 2]), None);
+    }
+
+    #[test]
+    fn remote_ip_without_connection_is_unknown() {
+        let request = Request::new(());
+        assert_eq!(remote_ip(&request), None);
+        assert!(remote_octets(&request).is_empty());
+    }
+
+    #[test]
+    fn forwarded_ip_skips_trusted_proxies() {
+        let proxies = cidrs(&["127.0.0.1/32", "10.0.0.0/8"]);
+        let forwarded = [ip("203.0.113.9"), ip("198.51.100.4"), ip("10.0.0.2")];
+        assert_eq!(forwarded_ip(ip("127.0.0.1"), &forwarded, &proxies), ip("198.51.100.4"));
+    }
+
+    #[test]
+    fn forwarded_ip_falls_back_to_peer() {
+        let proxies = cidrs(&["127.0.0.1/32", "10.0.0.0/8"]);
+        assert_eq!(forwarded_ip(ip("127.0.0.1"), &[ip("10.0.0.2")], &proxies), ip("127.0.0.1"));
+        assert_eq!(forwarded_ip(ip("127.0.0.1"), &[], &proxies), ip("127.0.0.1"));
+    }
+
+}
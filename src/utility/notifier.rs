@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::transport::smtp::authentication::Credentials;
+use super::config::{NOTIFIER, SMS_NOTIFIER};
+
+/// Channel of a notification, destination is an email address or a phone number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Email,
+    Sms
+}
+
+/// Notification sent to an email address or a phone number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub destination: String,
+    pub subject: String,
+    pub body: String
+}
+
+/// Deliver notification such as password reset and verification token to a user
+#[tonic::async_trait]
+pub trait Notifier: Send + Sync {
+
+    async fn notify(&self, notification: &Notification) -> Result<(), String>;
+
+}
+
+/// Send email notification through an SMTP server
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String
+}
+
+impl SmtpNotifier {
+    pub fn new(host: &str, port: u16, username: &str, password: &str, from: &str) -> Result<Self, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+            .map_err(|e| e.to_string())?
+            .port(port)
+            .credentials(Credentials::new(username.to_owned(), password.to_owned()))
+            .build();
+        Ok(Self { transport, from: from.to_owned() })
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for SmtpNotifier {
+
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let message = Message::builder()
+            .from(self.from.parse().map_err(|e: lettre::address::AddressError| e.to_string())?)
+            .to(notification.destination.parse().map_err(|e: lettre::address::AddressError| e.to_string())?)
+            .subject(notification.subject.clone())
+            .body(notification.body.clone())
+            .map_err(|e| e.to_string())?;
+        self.transport.send(message).await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+}
+
+/// Send SMS notification by posting destination and body as JSON to an SMS gateway
+pub struct HttpSmsNotifier {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>
+}
+
+impl HttpSmsNotifier {
+    pub fn new(url: &str, token: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_owned(),
+            token: token.map(|t| t.to_owned())
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for HttpSmsNotifier {
+
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let body = serde_json::json!({
+            "to": notification.destination,
+            "body": notification.body
+        });
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+}
+
+/// Write notification to a file or to standard output, used for testing and development
+pub struct FileNotifier {
+    path: Option<PathBuf>
+}
+
+impl FileNotifier {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+}
+
+#[tonic::async_trait]
+impl Notifier for FileNotifier {
+
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let line = format!("{} {}: {}\n", notification.destination, notification.subject, notification.body);
+        match &self.path {
+            Some(path) => {
+                let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await
+                    .map_err(|e| e.to_string())?;
+                file.write_all(line.as_bytes()).await.map_err(|e| e.to_string())
+            },
+            None => {
+                print!("{}", line);
+                Ok(())
+            }
+        }
+    }
+
+}
+
+/// Create notifier of this server from NOTIFIER environment variable which is either `file` or
+/// `smtp`. File notifier write to NOTIFIER_FILE or standard output, it must be chosen explicitly
+/// so a production server never write reset tokens to a log by accident. Notification is disabled
+/// when NOTIFIER is not set, and only requests which send a notification are rejected.
+pub fn init_notifier() -> Result<(), String>
+{
+    let notifier: Box<dyn Notifier> = match std::env::var("NOTIFIER").unwrap_or_default().as_str() {
+        "smtp" => {
+            let host = std::env::var("NOTIFIER_SMTP_HOST")
+                .map_err(|_| String::from("NOTIFIER_SMTP_HOST is required for smtp notifier"))?;
+            let port = std::env::var("NOTIFIER_SMTP_PORT").ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(587);
+            let username = std::env::var("NOTIFIER_SMTP_USERNAME").unwrap_or_default();
+            let password = std::env::var("NOTIFIER_SMTP_PASSWORD").unwrap_or_default();
+            let from = std::env::var("NOTIFIER_FROM").unwrap_or_default();
+            Box::new(SmtpNotifier::new(&host, port, &username, &password, &from)?)
+        },
+        "file" => {
+            let path = std::env::var("NOTIFIER_FILE").ok().map(PathBuf::from);
+            Box::new(FileNotifier::new(path))
+        },
+        "" => {
+            tracing::warn!("NOTIFIER is not set, password reset and verification are disabled");
+            return Ok(());
+        },
+        other => return Err(format!("NOTIFIER must be file or smtp, found '{}'", other))
+    };
+    set_notifier(Channel::Email, notifier);
+    init_sms_notifier()
+}
+
+/// Create SMS notifier of this server from SMS_NOTIFIER environment variable which is either `file`
+/// or `http`. HTTP notifier post to SMS_NOTIFIER_URL with optional SMS_NOTIFIER_TOKEN bearer token.
+/// Phone verification is disabled when SMS_NOTIFIER is not set.
+fn init_sms_notifier() -> Result<(), String>
+{
+    let notifier: Box<dyn Notifier> = match std::env::var("SMS_NOTIFIER").unwrap_or_default().as_str() {
+        "http" => {
+            let url = std::env::var("SMS_NOTIFIER_URL")
+                .map_err(|_| String::from("SMS_NOTIFIER_URL is required for http SMS notifier"))?;
+            let token = std::env::var("SMS_NOTIFIER_TOKEN").ok();
+            Box::new(HttpSmsNotifier::new(&url, token.as_deref()))
+        },
+        "file" => {
+            let path = std::env::var("SMS_NOTIFIER_FILE").ok().map(PathBuf::from);
+            Box::new(FileNotifier::new(path))
+        },
+        "" => return Ok(()),
+        other => return Err(format!("SMS_NOTIFIER must be file or http, found '{}'", other))
+    };
+    set_notifier(Channel::Sms, notifier);
+    Ok(())
+}
+
+/// Set notifier of a channel, only the first notifier of a channel is used
+pub fn set_notifier(channel: Channel, notifier: Box<dyn Notifier>)
+{
+    let cell = match channel {
+        Channel::Email => &NOTIFIER,
+        Channel::Sms => &SMS_NOTIFIER
+    };
+    cell.set(notifier).ok();
+}
+
+/// Notifier of a channel, none if it is not initialized
+pub fn notifier(channel: Channel) -> Option<&'static dyn Notifier>
+{
+    let cell = match channel {
+        Channel::Email => &NOTIFIER,
+        Channel::Sms => &SMS_NOTIFIER
+    };
+    cell.get().map(|n| n.as_ref())
+}
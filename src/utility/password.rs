@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{PasswordHash, SaltString, rand_core::OsRng};
+use super::config::PASSWORD_POLICY;
+
+const DEF_MIN_LENGTH: usize = 8;
+const DEF_CLASSES: &str = "lower,upper,digit";
+const DEF_HISTORY: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol
+}
+
+impl CharacterClass {
+    fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "lower" => Some(Self::Lower),
+            "upper" => Some(Self::Upper),
+            "digit" => Some(Self::Digit),
+            "symbol" => Some(Self::Symbol),
+            _ => None
+        }
+    }
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Lower => c.is_lowercase(),
+            Self::Upper => c.is_uppercase(),
+            Self::Digit => c.is_ascii_digit(),
+            Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace()
+        }
+    }
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Lower => "lowercase letter",
+            Self::Upper => "uppercase letter",
+            Self::Digit => "digit",
+            Self::Symbol => "symbol"
+        }
+    }
+}
+
+/// Password rules checked before a password is stored. Breached passwords are read from a local file
+/// with one password per line, and history is the number of previous passwords which can not be reused.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub classes: Vec<CharacterClass>,
+    pub history: usize,
+    pub breached: HashSet<String>
+}
+
+impl PasswordPolicy {
+
+    pub fn new(min_length: usize, classes: &[CharacterClass], history: usize) -> Self {
+        Self {
+            min_length,
+            classes: classes.to_vec(),
+            history,
+            breached: HashSet::new()
+        }
+    }
+
+    pub fn with_breached_file(mut self, path: &str) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        self.breached = content.lines()
+            .map(|l| l.trim().to_owned())
+            .filter(|l| !l.is_empty())
+            .collect();
+        Ok(self)
+    }
+
+    /// Check password strength and return the broken rule
+    pub fn check(&self, password: &str) -> Result<(), String> {
+        if password.chars().count() < self.min_length {
+            return Err(format!("password must have at least {} characters", self.min_length));
+        }
+        for class in &self.classes {
+            if !password.chars().any(|c| class.contains(c)) {
+                return Err(format!("password must contain a {}", class.name()));
+            }
+        }
+        if self.breached.contains(password) {
+            return Err(String::from("password is found in breached password list"));
+        }
+        Ok(())
+    }
+
+    /// Check password against current and previous password hashes
+    pub fn check_history(&self, password: &str, hashes: &[&str]) -> Result<(), String> {
+        let reused = hashes.iter()
+            .take(self.history)
+            .any(|h| verify_password(password.as_bytes(), h).is_ok());
+        if reused {
+            return Err(format!("password must not be one of the last {} passwords", self.history));
+        }
+        Ok(())
+    }
+
+    /// Number of previous password hashes kept in password history, the current password is
+    /// checked from the user itself
+    pub fn history_kept(&self) -> usize {
+        self.history.saturating_sub(1)
+    }
+
+}
+
+/// Password policy of this server configured from PASSWORD_MIN_LENGTH, PASSWORD_CLASSES,
+/// PASSWORD_HISTORY, and PASSWORD_BREACHED_FILE environment variables
+pub fn password_policy() -> &'static PasswordPolicy {
+    PASSWORD_POLICY.get_or_init(|| {
+        let min_length = std::env::var("PASSWORD_MIN_LENGTH").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_MIN_LENGTH);
+        let classes: Vec<CharacterClass> = std::env::var("PASSWORD_CLASSES")
+            .unwrap_or(DEF_CLASSES.to_owned())
+            .split(',')
+            .filter_map(|s| CharacterClass::from_str(s))
+            .collect();
+        let history = std::env::var("PASSWORD_HISTORY").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_HISTORY);
+        let policy = PasswordPolicy::new(min_length, &classes, history);
+        match std::env::var("PASSWORD_BREACHED_FILE") {
+            Ok(path) => match policy.clone().with_breached_file(&path) {
+                Ok(policy) => policy,
+                Err(e) => {
+                    tracing::error!("Failed to read breached password file {}: {}", path, e);
+                    policy
+                }
+            },
+            Err(_) => policy
+        }
+    })
+}
+
+pub(crate) fn verify_password(password: &[u8], hash: &str) -> Result<(), argon2::password_hash::Error>
+{
+    let argon2 = Argon2::default();
+    let parsed_hash = PasswordHash::new(&hash)?;
+    argon2.verify_password(password, &parsed_hash)
+}
+
+/// Hash a password or another secret with a random salt into a PHC string
+pub(crate) fn hash_password(password: &[u8]) -> Result<String, argon2::password_hash::Error>
+{
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password, &salt)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(password: &str) -> String {
+        hash_password(password.as_bytes()).unwrap()
+    }
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::new(8, &[CharacterClass::Lower, CharacterClass::Upper, CharacterClass::Digit], 3)
+    }
+
+    #[test]
+    fn check_rejects_short_password() {
+        assert!(policy().check("Ab1").is_err());
+    }
+
+    #[test]
+    fn check_requires_every_class() {
+        assert_eq!(policy().check("abcdefgh1"), Err(String::from("password must contain a uppercase letter")));
+        assert_eq!(policy().check("Abcdefgh"), Err(String::from("password must contain a digit")));
+        assert!(policy().check("Abcdefgh1").is_ok());
+    }
+
+    #[test]
+    fn check_rejects_breached_password() {
+        let mut policy = policy();
+        policy.breached.insert(String::from("Password1"));
+        assert!(policy.check("Password1").is_err());
+    }
+
+    #[test]
+    fn check_history_rejects_reused_password() {
+        let hashes = [hash("Current1"), hash("Previous1")];
+        let hashes: Vec<&str> = hashes.iter().map(|h| h.as_str()).collect();
+        assert!(policy().check_history("Previous1", &hashes).is_err());
+        assert!(policy().check_history("Another1", &hashes).is_ok());
+    }
+
+    #[test]
+    fn check_history_only_checks_configured_number() {
+        let policy = PasswordPolicy::new(8, &[], 1);
+        let hashes = [hash("Current1"), hash("Previous1")];
+        let hashes: Vec<&str> = hashes.iter().map(|h| h.as_str()).collect();
+        assert!(policy.check_history("Previous1", &hashes).is_ok());
+    }
+
+    #[test]
+    fn history_kept_excludes_current_password() {
+        assert_eq!(policy().history_kept(), 2);
+        assert_eq!(PasswordPolicy::new(8, &[], 0).history_kept(), 0);
+    }
+
+}
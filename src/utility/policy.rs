@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use super::validator::{ParentSchema, role_ancestors};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyFormat {
+    Json,
+    Yaml
+}
+
+impl From<i32> for PolicyFormat {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Yaml,
+            _ => Self::Json
+        }
+    }
+}
+
+impl From<PolicyFormat> for i32 {
+    fn from(value: PolicyFormat) -> Self {
+        match value {
+            PolicyFormat::Json => 0,
+            PolicyFormat::Yaml => 1
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRole {
+    pub name: String,
+    #[serde(default)]
+    pub multi: bool,
+    #[serde(default)]
+    pub ip_lock: bool,
+    pub access_duration: i32,
+    pub refresh_duration: i32,
+    /// CIDR ranges the role may be used from, an empty list allow every address
+    #[serde(default)]
+    pub ip_allow: Vec<String>,
+    /// Name of a role of the same API the role inherits accesses from
+    #[serde(default)]
+    pub parent: Option<String>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyApi {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    pub name: String,
+    #[serde(default)]
+    pub address: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub description: String,
+    /// Only used when the API is created, existing API password is never changed
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<PolicyRole>,
+    /// Procedure names of the API mapped to role names which have access to it
+    #[serde(default)]
+    pub accesses: BTreeMap<String, Vec<String>>
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub apis: Vec<PolicyApi>
+}
+
+impl Policy {
+
+    pub fn decode(content: &str, format: PolicyFormat) -> Result<Self, String> {
+        let policy: Self = match format {
+            PolicyFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+            PolicyFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?
+        };
+        // every role referenced in accesses must be declared
+        for api in &policy.apis {
+            for (procedure, roles) in &api.accesses {
+                for role in roles {
+                    if !api.roles.iter().any(|r| &r.name == role) {
+                        return Err(format!("role {} of procedure {} is not declared in api {}", role, procedure, api.name));
+                    }
+                }
+            }
+            // parent must not inherit from the role, a parent which is not declared may be an existing
+            // role of the API and is resolved when the policy is applied
+            let parents: Vec<ParentSchema> = api.roles.iter()
+                .filter_map(|r| r.parent.as_ref().map(|p| ParentSchema { role: r.name.clone(), parent: p.clone() }))
+                .collect();
+            for p in &parents {
+                if role_ancestors(&p.parent, &parents).contains(&p.role) {
+                    return Err(format!("role {} inherits from itself in api {}", p.role, api.name));
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+}
@@ -0,0 +1,104 @@
+use std::sync::RwLock;
+use chrono::{DateTime, Utc};
+use sha2::{Sha256, Digest};
+use tokio::sync::broadcast;
+use rmcs_auth_db::Auth;
+use super::config::REVOKED_TOKENS;
+
+const REVOKE_CHANNEL_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevokedToken {
+    pub jti: i32,
+    pub expire: DateTime<Utc>
+}
+
+/// Access token ids which are revoked before expired, an entry is kept until the token expire.
+/// Auth server store entries in auth database on logout and token deletion so every replica and
+/// restarted server keep rejecting them, and resource server receive them through revoked token stream.
+#[derive(Debug)]
+pub struct RevokedTokens {
+    tokens: RwLock<Vec<RevokedToken>>,
+    sender: broadcast::Sender<RevokedToken>
+}
+
+impl RevokedTokens {
+
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(REVOKE_CHANNEL_SIZE);
+        Self {
+            tokens: RwLock::new(Vec::new()),
+            sender
+        }
+    }
+
+    /// Store a revoked token in auth database and add it to the local list
+    pub async fn revoke(&self, auth_db: &Auth, jti: i32, expire: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        if expire <= Utc::now() {
+            return Ok(());
+        }
+        auth_db.create_revoked_token(jti, expire).await?;
+        self.insert(jti, expire);
+        Ok(())
+    }
+
+    /// Add a revoked token to the local list and publish it to revoked token streams
+    pub fn insert(&self, jti: i32, expire: DateTime<Utc>) {
+        let now = Utc::now();
+        if expire <= now {
+            return;
+        }
+        let mut tokens = self.tokens.write().unwrap();
+        tokens.retain(|t| t.expire > now);
+        if tokens.iter().any(|t| t.jti == jti) {
+            return;
+        }
+        let token = RevokedToken { jti, expire };
+        tokens.push(token);
+        self.sender.send(token).ok();
+    }
+
+    /// Delete expired revoked and used refresh tokens from auth database and load tokens revoked by
+    /// other replicas, called on startup and periodically
+    pub async fn load(&self, auth_db: &Auth) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        auth_db.delete_revoked_token_expired(now).await?;
+        auth_db.delete_used_refresh_token_expired(now).await?;
+        for token in auth_db.list_revoked_token().await? {
+            self.insert(token.access_id, token.expire);
+        }
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, jti: i32) -> bool {
+        self.tokens.read().unwrap().iter().any(|t| t.jti == jti)
+    }
+
+    pub fn list(&self) -> Vec<RevokedToken> {
+        let now = Utc::now();
+        self.tokens.read().unwrap().iter().filter(|t| t.expire > now).cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RevokedToken> {
+        self.sender.subscribe()
+    }
+
+}
+
+pub fn revoked_tokens() -> &'static RevokedTokens {
+    REVOKED_TOKENS.get_or_init(RevokedTokens::new)
+}
+
+/// Refresh tokens which are already rotated are stored as hash in auth database until the refresh
+/// token expire. Presenting one of them again means the token family is stolen.
+pub fn refresh_token_hash(refresh_token: &str) -> Vec<u8> {
+    Sha256::digest(refresh_token.as_bytes()).to_vec()
+}
+
+pub async fn is_refresh_token_used(auth_db: &Auth, refresh_token: &str, jti: i32) -> Result<bool, sqlx::Error> {
+    match auth_db.read_used_refresh_token(jti, &refresh_token_hash(refresh_token)).await {
+        Ok(_) => Ok(true),
+        Err(sqlx::Error::RowNotFound) => Ok(false),
+        Err(e) => Err(e)
+    }
+}
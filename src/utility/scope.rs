@@ -0,0 +1,87 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+/// Kind of resource which a role or a user can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    DeviceGroup,
+    GatewayGroup,
+    Set
+}
+
+impl ScopeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DeviceGroup => "DEVICE_GROUP",
+            Self::GatewayGroup => "GATEWAY_GROUP",
+            Self::Set => "SET"
+        }
+    }
+}
+
+impl From<&str> for ScopeKind {
+    fn from(value: &str) -> Self {
+        match value.to_uppercase().as_str() {
+            "GATEWAY_GROUP" => Self::GatewayGroup,
+            "SET" => Self::Set,
+            _ => Self::DeviceGroup
+        }
+    }
+}
+
+impl From<i32> for ScopeKind {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::GatewayGroup,
+            2 => Self::Set,
+            _ => Self::DeviceGroup
+        }
+    }
+}
+
+impl From<ScopeKind> for i32 {
+    fn from(value: ScopeKind) -> Self {
+        match value {
+            ScopeKind::DeviceGroup => 0,
+            ScopeKind::GatewayGroup => 1,
+            ScopeKind::Set => 2
+        }
+    }
+}
+
+/// Device groups, gateway groups, and sets which a token can access, carried in access token claims.
+/// A token without scope can access every resource allowed by its procedure accesses.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenScope {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dgr: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ggr: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub set: Vec<Uuid>
+}
+
+impl TokenScope {
+
+    pub fn push(&mut self, kind: ScopeKind, id: Uuid) {
+        let ids = match kind {
+            ScopeKind::DeviceGroup => &mut self.dgr,
+            ScopeKind::GatewayGroup => &mut self.ggr,
+            ScopeKind::Set => &mut self.set
+        };
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    pub fn extend(&mut self, other: TokenScope) {
+        other.dgr.into_iter().for_each(|id| self.push(ScopeKind::DeviceGroup, id));
+        other.ggr.into_iter().for_each(|id| self.push(ScopeKind::GatewayGroup, id));
+        other.set.into_iter().for_each(|id| self.push(ScopeKind::Set, id));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dgr.is_empty() && self.ggr.is_empty() && self.set.is_empty()
+    }
+
+}
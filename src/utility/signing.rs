@@ -0,0 +1,227 @@
+use std::sync::RwLock;
+use chrono::{DateTime, Utc, Duration};
+use uuid::Uuid;
+use rand::thread_rng;
+use rsa::{RsaPrivateKey, RsaPublicKey, traits::PublicKeyParts, pkcs1::{EncodeRsaPrivateKey, DecodeRsaPrivateKey}};
+use ed25519_dalek::pkcs8::{EncodePrivateKey, DecodePrivateKey};
+use rmcs_auth_db::Auth;
+use jsonwebtoken::{Algorithm, EncodingKey, DecodingKey};
+use super::config::SIGNING_KEYS;
+
+const RSA_KEY_BITS: usize = 2048;
+const ALG_RS256: &str = "RS256";
+const ALG_EDDSA: &str = "EdDSA";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SigningAlgorithm {
+    #[default]
+    Rs256,
+    EdDsa
+}
+
+impl SigningAlgorithm {
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::Rs256 => Algorithm::RS256,
+            Self::EdDsa => Algorithm::EdDSA
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rs256 => ALG_RS256,
+            Self::EdDsa => ALG_EDDSA
+        }
+    }
+}
+
+impl From<&str> for SigningAlgorithm {
+    fn from(value: &str) -> Self {
+        if value.eq_ignore_ascii_case(ALG_EDDSA) {
+            Self::EdDsa
+        } else {
+            Self::Rs256
+        }
+    }
+}
+
+/// Public part of a token signing key, RSA keys use modulus and exponent and EdDSA keys use public key.
+/// Key without expire is the current or the next signing key.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub kid: String,
+    pub algorithm: SigningAlgorithm,
+    pub modulus: Vec<u8>,
+    pub exponent: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub expire: Option<DateTime<Utc>>
+}
+
+impl PublicKey {
+    pub fn decoding_key(&self) -> DecodingKey {
+        match self.algorithm {
+            SigningAlgorithm::Rs256 => DecodingKey::from_rsa_raw_components(&self.modulus, &self.exponent),
+            SigningAlgorithm::EdDsa => DecodingKey::from_ed_der(&self.public_key)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Signer {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey
+}
+
+struct KeyPair {
+    public: PublicKey,
+    encoding_key: EncodingKey,
+    created: DateTime<Utc>
+}
+
+impl KeyPair {
+
+    /// Generate a new key and return it along with its private key DER to be stored
+    fn generate(algorithm: SigningAlgorithm) -> Result<(Self, Vec<u8>), String> {
+        let der = match algorithm {
+            SigningAlgorithm::Rs256 => {
+                let private_key = RsaPrivateKey::new(&mut thread_rng(), RSA_KEY_BITS)
+                    .map_err(|e| e.to_string())?;
+                private_key.to_pkcs1_der().map_err(|e| e.to_string())?.as_bytes().to_vec()
+            },
+            SigningAlgorithm::EdDsa => {
+                let private_key = ed25519_dalek::SigningKey::generate(&mut thread_rng());
+                private_key.to_pkcs8_der().map_err(|e| e.to_string())?.as_bytes().to_vec()
+            }
+        };
+        let key = Self::from_der(&Uuid::new_v4().to_string(), algorithm, &der, None, Utc::now())?;
+        Ok((key, der))
+    }
+
+    /// Construct a key from private key DER, PKCS#1 for RSA and PKCS#8 for EdDSA
+    fn from_der(kid: &str, algorithm: SigningAlgorithm, der: &[u8], expire: Option<DateTime<Utc>>, created: DateTime<Utc>)
+        -> Result<Self, String>
+    {
+        let mut public = PublicKey {
+            kid: kid.to_owned(),
+            algorithm,
+            expire,
+            ..Default::default()
+        };
+        let encoding_key = match algorithm {
+            SigningAlgorithm::Rs256 => {
+                let private_key = RsaPrivateKey::from_pkcs1_der(der).map_err(|e| e.to_string())?;
+                let public_key = RsaPublicKey::from(&private_key);
+                public.modulus = public_key.n().to_bytes_be();
+                public.exponent = public_key.e().to_bytes_be();
+                EncodingKey::from_rsa_der(der)
+            },
+            SigningAlgorithm::EdDsa => {
+                let private_key = ed25519_dalek::SigningKey::from_pkcs8_der(der).map_err(|e| e.to_string())?;
+                public.public_key = private_key.verifying_key().to_bytes().to_vec();
+                EncodingKey::from_ed_der(der)
+            }
+        };
+        Ok(Self { public, encoding_key, created })
+    }
+
+}
+
+/// Token signing keys of auth server stored in auth database, so keys survive restart and every
+/// replica sign with the same key. Keys without expire are the current key followed by the next key,
+/// the next key is published before it is used so resource servers already know the key when rotation
+/// happen. Retired keys are still published until the overlap duration ends, so tokens signed before
+/// rotation stay verifiable.
+pub struct SigningKeys {
+    algorithm: SigningAlgorithm,
+    keys: RwLock<Vec<KeyPair>>
+}
+
+impl SigningKeys {
+
+    pub fn new(algorithm: SigningAlgorithm) -> Self {
+        Self {
+            algorithm,
+            keys: RwLock::new(Vec::new())
+        }
+    }
+
+    /// Load keys from auth database, the current and next key are generated and stored when missing
+    pub async fn load(&self, auth_db: &Auth) -> Result<(), String> {
+        self.update(auth_db, None).await
+    }
+
+    /// Reload keys stored by other replicas, and retire current key when it is older than rotation
+    /// interval so the next key is used and a new next key is generated
+    pub async fn sync(&self, auth_db: &Auth, interval: Duration, overlap: Duration) -> Result<(), String> {
+        self.update(auth_db, Some((interval, overlap))).await
+    }
+
+    async fn update(&self, auth_db: &Auth, rotation: Option<(Duration, Duration)>) -> Result<(), String> {
+        // replicas loading or rotating keys at the same time are serialized by an advisory lock held
+        // until the transaction ends, so keys are only generated when still missing after other
+        // replicas committed and the current key is retired once
+        let transaction = auth_db.begin().await
+            .map_err(|e| e.to_string())?;
+        transaction.lock_signing_key().await
+            .map_err(|e| e.to_string())?;
+        let now = Utc::now();
+        transaction.delete_signing_key_expired(now).await
+            .map_err(|e| e.to_string())?;
+        let mut keys = Vec::new();
+        for key in transaction.list_signing_key().await.map_err(|e| e.to_string())? {
+            let algorithm = SigningAlgorithm::from(key.algorithm.as_str());
+            keys.push(KeyPair::from_der(&key.kid, algorithm, &key.private_key, key.expire, key.created)?);
+        }
+        if let Some((interval, overlap)) = rotation {
+            let current = keys.iter_mut().find(|k| k.public.expire.is_none());
+            if let Some(key) = current.filter(|k| k.created + interval <= now) {
+                transaction.update_signing_key_expire(&key.public.kid, now + overlap).await
+                    .map_err(|e| e.to_string())?;
+                key.public.expire = Some(now + overlap);
+            }
+        }
+        let active = keys.iter().filter(|k| k.public.expire.is_none()).count();
+        for _ in active..2 {
+            let (key, der) = KeyPair::generate(self.algorithm)?;
+            transaction.create_signing_key(&key.public.kid, self.algorithm.name(), &der, key.created).await
+                .map_err(|e| e.to_string())?;
+            keys.push(key);
+        }
+        transaction.commit().await
+            .map_err(|e| e.to_string())?;
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Current signing key, none if keys are not loaded yet
+    pub fn current(&self) -> Option<Signer> {
+        let keys = self.keys.read().unwrap();
+        keys.iter()
+            .find(|k| k.public.expire.is_none())
+            .map(|key| Signer {
+                kid: key.public.kid.clone(),
+                algorithm: key.public.algorithm.algorithm(),
+                encoding_key: key.encoding_key.clone()
+            })
+    }
+
+    pub fn public_keys(&self) -> Vec<PublicKey> {
+        let now = Utc::now();
+        self.keys.read().unwrap()
+            .iter()
+            .filter(|k| k.public.expire.map(|e| e > now).unwrap_or(true))
+            .map(|k| k.public.clone())
+            .collect()
+    }
+
+}
+
+/// Signing keys of this server, the algorithm of generated keys is read from TOKEN_ALGORITHM environment variable
+pub fn signing_keys() -> &'static SigningKeys {
+    SIGNING_KEYS.get_or_init(|| {
+        let algorithm = std::env::var("TOKEN_ALGORITHM")
+            .map(|s| SigningAlgorithm::from(s.as_str()))
+            .unwrap_or_default();
+        SigningKeys::new(algorithm)
+    })
+}
@@ -57,8 +57,8 @@ impl TestServer {
     {
         let pool = PgPoolOptions::new().connect(self.db_url.as_str()).await?;
         let sql = match self.kind {
-            TestServerKind::Auth => "TRUNCATE TABLE \"profile_user\", \"profile_role\", \"token\", \"user_role\", \"user\", \"role_access\", \"role\", \"api_procedure\", \"api\";",
-            TestServerKind::Resource => "TRUNCATE TABLE \"system_log\", \"slice_data_set\", \"slice_data\", \"data_buffer\", \"data\", \"set_map\", \"set_template_map\", \"set\", \"set_template\", \"group_model_map\", \"group_device_map\", \"group_model\", \"group_device\", \"device_config\", \"device\", \"device_type_model\", \"device_type\", \"model_tag\", \"model_config\", \"model\";"
+            TestServerKind::Auth => "TRUNCATE TABLE \"login_failure\", \"revoked_token\", \"used_refresh_token\", \"password_history\", \"user_mfa\", \"mfa_challenge\", \"verification\", \"signing_key\", \"user_scope\", \"role_scope\", \"profile_user\", \"profile_role\", \"token\", \"api_key\", \"user_role\", \"user\", \"role_access\", \"role\", \"api_procedure\", \"api\", \"tenant\";",
+            TestServerKind::Resource => "TRUNCATE TABLE \"device_command\", \"system_log\", \"slice_data_set\", \"slice_data\", \"data_buffer\", \"data\", \"set_map\", \"set_template_map\", \"set\", \"set_template\", \"group_model_map\", \"group_device_map\", \"group_model\", \"group_device\", \"config_history\", \"type_config\", \"device_config\", \"device\", \"device_type_model\", \"device_type\", \"model_tag\", \"model_config\", \"model\";"
         };
         sqlx::query(sql)
             .execute(&pool)
@@ -0,0 +1,179 @@
+use std::sync::RwLock;
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Utc, Duration};
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_login::LoginFailureSchema;
+use super::config::LOGIN_THROTTLE;
+
+const DEF_MAX_FAILURE: u32 = 5;
+const DEF_LOCK_DURATION: i64 = 900;
+const FAILURE_DELAY_MS: u64 = 250;
+const MAX_DELAY_MS: u64 = 4000;
+const MAX_SOURCE_ENTRIES: usize = 4096;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoginKind {
+    #[default]
+    User,
+    Api,
+    ApiKey,
+    /// Password reset requests of a user name, counted apart from login failures
+    PasswordReset,
+    /// Wrong OTP or recovery codes of a user name, not reset by a correct password
+    Mfa
+}
+
+impl From<i32> for LoginKind {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Api,
+            2 => Self::ApiKey,
+            3 => Self::PasswordReset,
+            4 => Self::Mfa,
+            _ => Self::User
+        }
+    }
+}
+
+impl From<LoginKind> for i32 {
+    fn from(value: LoginKind) -> Self {
+        match value {
+            LoginKind::User => 0,
+            LoginKind::Api => 1,
+            LoginKind::ApiKey => 2,
+            LoginKind::PasswordReset => 3,
+            LoginKind::Mfa => 4
+        }
+    }
+}
+
+/// Consecutive login failures of a user name or an API id, the address of the last failure is
+/// kept for administrators
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginFailure {
+    pub kind: LoginKind,
+    pub name: String,
+    pub ip: Vec<u8>,
+    pub count: u32,
+    pub last_failure: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>
+}
+
+impl From<LoginFailureSchema> for LoginFailure {
+    fn from(value: LoginFailureSchema) -> Self {
+        Self {
+            kind: LoginKind::from(value.kind),
+            name: value.name,
+            ip: value.ip,
+            count: value.count as u32,
+            last_failure: value.last_failure,
+            locked_until: value.locked_until
+        }
+    }
+}
+
+/// Recent failures from a remote address used to delay responses
+#[derive(Debug, Clone)]
+struct SourceFailure {
+    ip: Vec<u8>,
+    count: u32,
+    last_failure: DateTime<Utc>
+}
+
+/// Login rate limiter. An account is locked after a number of consecutive failures from any
+/// address, failures and locks are stored in the auth database so they are shared by every
+/// server and survive restart. Every failure also delays the response asynchronously by a time
+/// growing with recent failures of the remote address.
+#[derive(Debug)]
+pub struct LoginThrottle {
+    sources: RwLock<Vec<SourceFailure>>,
+    max_failure: u32,
+    lock_duration: Duration
+}
+
+impl LoginThrottle {
+
+    pub fn new(max_failure: u32, lock_duration: Duration) -> Self {
+        Self {
+            sources: RwLock::new(Vec::new()),
+            max_failure,
+            lock_duration
+        }
+    }
+
+    /// Return time until the account is unlocked if it is currently locked
+    pub async fn locked(&self, auth_db: &Auth, kind: LoginKind, name: &str) -> Option<DateTime<Utc>> {
+        let failure = auth_db.read_login_failure(kind.into(), name).await.ok().flatten()?;
+        failure.locked_until.filter(|&t| t > Utc::now())
+    }
+
+    /// Count a failed login and wait for a growing delay before the caller return the error
+    pub async fn fail(&self, auth_db: &Auth, kind: LoginKind, name: &str, ip: &[u8]) {
+        let now = Utc::now();
+        // drop failures whose lock is over or whose last failure is older than lock duration
+        if let Err(e) = auth_db.delete_login_failure_expired(now - self.lock_duration).await {
+            tracing::warn!("Failed to delete expired login failures: {}", e);
+        }
+        // counting and locking is a single statement so concurrent failures are all counted
+        let result = auth_db.add_login_failure(kind.into(), name, ip, self.max_failure as i32, now + self.lock_duration).await;
+        if let Err(e) = result {
+            tracing::error!("Failed to save login failure of {}: {}", name, e);
+        }
+        let count = self.source_fail(ip, now);
+        let delay = (FAILURE_DELAY_MS << (count.min(8) - 1)).min(MAX_DELAY_MS);
+        tokio::time::sleep(StdDuration::from_millis(delay)).await;
+    }
+
+    fn source_fail(&self, ip: &[u8], now: DateTime<Utc>) -> u32 {
+        let mut sources = self.sources.write().unwrap();
+        sources.retain(|s| s.last_failure + self.lock_duration > now);
+        let index = match sources.iter().position(|s| s.ip == ip) {
+            Some(index) => index,
+            None => {
+                // forget the oldest address rather than growing without bound
+                if sources.len() >= MAX_SOURCE_ENTRIES {
+                    sources.remove(0);
+                }
+                sources.push(SourceFailure { ip: ip.to_owned(), count: 0, last_failure: now });
+                sources.len() - 1
+            }
+        };
+        let source = &mut sources[index];
+        source.count += 1;
+        source.last_failure = now;
+        source.count
+    }
+
+    /// Reset failure counter after a successful login
+    pub async fn succeed(&self, auth_db: &Auth, kind: LoginKind, name: &str, ip: &[u8]) {
+        self.sources.write().unwrap().retain(|s| s.ip != ip);
+        if let Err(e) = auth_db.delete_login_failure(kind.into(), name).await {
+            tracing::warn!("Failed to delete login failure of {}: {}", name, e);
+        }
+    }
+
+    pub async fn list(&self, auth_db: &Auth, kind: Option<LoginKind>, name: Option<&str>) -> Result<Vec<LoginFailure>, sqlx::Error> {
+        let failures = auth_db.list_login_failure(kind.map(|k| k.into()), name).await?;
+        Ok(failures.into_iter().map(|f| f.into()).collect())
+    }
+
+    /// Remove failures and lock of a user name or an API id
+    pub async fn unlock(&self, auth_db: &Auth, kind: LoginKind, name: &str) -> Result<(), sqlx::Error> {
+        auth_db.delete_login_failure(kind.into(), name).await
+    }
+
+}
+
+/// Login throttle of this server configured from LOGIN_MAX_FAILURE and LOGIN_LOCK_DURATION
+/// environment variables
+pub fn login_throttle() -> &'static LoginThrottle {
+    LOGIN_THROTTLE.get_or_init(|| {
+        let max_failure = std::env::var("LOGIN_MAX_FAILURE").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_MAX_FAILURE);
+        let lock_duration = std::env::var("LOGIN_LOCK_DURATION").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_LOCK_DURATION);
+        LoginThrottle::new(max_failure, Duration::seconds(lock_duration))
+    })
+}
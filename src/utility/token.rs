@@ -1,42 +1,138 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
-use jsonwebtoken::{encode, decode, DecodingKey, EncodingKey, Header, Algorithm, Validation};
+use sha2::{Sha256, Digest};
+use uuid::Uuid;
+use jsonwebtoken::{encode, decode, decode_header, Header, Validation};
+use jsonwebtoken::errors::{Error, ErrorKind};
+use rmcs_resource_db::DataValue;
+use super::signing::{signing_keys, PublicKey};
+use super::network::{parse_cidrs, ip_allowed};
+use super::scope::TokenScope;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub jti: i32,
+    /// Primary role name of the token
     pub sub: String,
+    /// Every role name of the token including the primary role, empty for a single role token
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rol: Vec<String>,
+    /// User id of the token owner
+    #[serde(default)]
+    pub uid: Uuid,
+    /// API id of the token role
+    #[serde(default)]
+    pub aid: Uuid,
+    /// Tenant id of the token owner, nil for user which is not bound to a tenant
+    #[serde(default, skip_serializing_if = "Uuid::is_nil")]
+    pub tnt: Uuid,
+    /// Family id shared by access tokens generated from the same login
+    #[serde(default)]
+    pub fid: String,
     pub iat: u64,
     pub exp: u64,
+    /// Custom claims taken from user profiles which are defined in the role profiles
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ext: BTreeMap<String, serde_json::Value>,
+    /// CIDR ranges of each restricted role, the token can only be used from an address
+    /// which is allowed by every list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ipn: Vec<Vec<String>>,
+    /// Resources which the token is restricted to, none for unrestricted token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scp: Option<TokenScope>
 }
 
-pub(crate) fn generate_token(jti: i32, sub: &str, duration: i32, key: &[u8]) -> Result<String, jsonwebtoken::errors::Error>
+impl TokenClaims {
+    pub fn new(jti: i32, sub: &str, uid: Uuid, aid: Uuid, fid: &str) -> Self {
+        Self {
+            jti,
+            sub: sub.to_owned(),
+            uid,
+            aid,
+            fid: fid.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Role names of the token, primary role only for a single role token
+    pub fn roles(&self) -> Vec<String> {
+        if self.rol.is_empty() { vec![self.sub.clone()] } else { self.rol.clone() }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.sub == role || self.rol.iter().any(|r| r == role)
+    }
+
+    /// Check remote address against CIDR ranges of the token roles, ranges which can not be
+    /// parsed deny every address
+    pub fn ip_allowed(&self, ip: Option<IpAddr>) -> bool {
+        self.ipn.iter().all(|cidrs| match parse_cidrs(cidrs) {
+            Ok(cidrs) => cidrs.is_empty() || ip.map(|ip| ip_allowed(ip, &cidrs)).unwrap_or(false),
+            Err(_) => false
+        })
+    }
+}
+
+/// Family id of an auth token, the auth token itself is never put in access token
+pub(crate) fn family_id(auth_token: &str) -> String
+{
+    let hash = Sha256::digest(auth_token.as_bytes());
+    format!("{:x}", hash)[..32].to_owned()
+}
+
+pub(crate) fn claim_value(value: DataValue) -> serde_json::Value
+{
+    match value {
+        DataValue::I8(v) => v.into(),
+        DataValue::I16(v) => v.into(),
+        DataValue::I32(v) => v.into(),
+        DataValue::I64(v) => v.into(),
+        DataValue::U8(v) => v.into(),
+        DataValue::U16(v) => v.into(),
+        DataValue::U32(v) => v.into(),
+        DataValue::U64(v) => v.into(),
+        DataValue::F32(v) => v.into(),
+        DataValue::F64(v) => v.into(),
+        DataValue::Bool(v) => v.into(),
+        DataValue::Char(v) => v.to_string().into(),
+        DataValue::String(v) => v.into(),
+        _ => serde_json::Value::Null
+    }
+}
+
+pub(crate) fn generate_token(mut claims: TokenClaims, duration: i32) -> Result<String, Error>
 {
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let iat = now.as_secs();
-    let exp = iat + duration as u64;
-    let claims = TokenClaims {
-        jti,
-        sub: sub.to_owned(),
-        iat,
-        exp
-    };
-    let header = Header::new(Algorithm::HS256);
-    let encoding_key = EncodingKey::from_secret(key);
-    let token = encode(&header, &claims, &encoding_key)?;
+    claims.iat = now.as_secs();
+    claims.exp = claims.iat + duration as u64;
+    // sign with current key of the server and put its key id in header
+    let signer = signing_keys().current()
+        .ok_or(Error::from(ErrorKind::InvalidKeyFormat))?;
+    let mut header = Header::new(signer.algorithm);
+    header.kid = Some(signer.kid);
+    let token = encode(&header, &claims, &signer.encoding_key)?;
     Ok(token)
 }
 
-pub(crate) fn decode_token(token: &str, key: &[u8], exp_flag: bool) -> Result<TokenClaims, jsonwebtoken::errors::Error>
+pub(crate) fn decode_token(token: &str, keys: &[PublicKey], exp_flag: bool) -> Result<TokenClaims, Error>
 {
-    let decoding_key = DecodingKey::from_secret(key);
-    let mut validation = Validation::new(Algorithm::HS256);
+    // select public key using key id in token header
+    let header = decode_header(token)?;
+    let kid = header.kid.ok_or(Error::from(ErrorKind::InvalidToken))?;
+    let key = keys.iter()
+        .filter(|k| k.kid == kid && k.algorithm.algorithm() == header.alg)
+        .next()
+        .ok_or(Error::from(ErrorKind::InvalidSignature))?;
+    let mut validation = Validation::new(header.alg);
     let req_claim: Vec<&str> = if exp_flag {
         ["exp"].to_vec()
     } else {
         [].to_vec()
     };
     validation.set_required_spec_claims(&req_claim);
-    let token_data = decode::<TokenClaims>(token, &decoding_key, &validation)?;
+    let token_data = decode::<TokenClaims>(token, &key.decoding_key(), &validation)?;
     Ok(token_data.claims)
 }
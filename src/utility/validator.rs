@@ -1,13 +1,21 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
 use tonic::{Status, Extensions};
 use uuid::Uuid;
-use jsonwebtoken::{decode, DecodingKey, Algorithm, Validation};
-use super::token::TokenClaims;
-use super::config::{ROOT_ID, ROOT_NAME, ROOT_DATA};
+use super::token::{TokenClaims, decode_token};
+use super::signing::PublicKey;
+use super::network::RemoteIp;
+use super::scope::TokenScope;
+use super::config::{ROOT_ID, ROOT_NAME};
+use super::revoke::revoked_tokens;
+use super::handle_error;
 use rmcs_auth_db::Auth;
-use rmcs_auth_api::auth::ProcedureMap;
+use rmcs_auth_api::auth::{ProcedureMap, RoleParentMap};
 
 const EXT_NOT_FOUND: &str = "Extension not found";
 const TOKEN_EXPIRED: &str = "Token is broken or expired";
+const TOKEN_REVOKED: &str = "Token has been revoked";
+const IP_NOT_ALLOWED: &str = "Remote address is not allowed for the role";
 const PROC_NOT_FOUND: &str = "Procedure access not found";
 const USER_UNREGISTERED: &str = "user has not registered";
 const ACCESS_RIGHT_ERR: &str = "doesn't has access rights";
@@ -24,67 +32,266 @@ impl From<ProcedureMap> for AccessSchema {
     }
 }
 
+/// Parent of a role, a role inherits every procedure access of its parent and ancestors
+#[derive(Debug, Clone)]
+pub struct ParentSchema {
+    pub role: String,
+    pub parent: String
+}
+
+impl From<RoleParentMap> for ParentSchema {
+    fn from(value: RoleParentMap) -> Self {
+        Self { role: value.role, parent: value.parent }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AccessState {
+    keys: Vec<PublicKey>,
+    accesses: Vec<AccessSchema>,
+    parents: Vec<ParentSchema>
+}
+
+/// Token verifying keys and procedure accesses shared by all services of a server.
+/// Updating the table changes accesses of every service immediately.
+#[derive(Debug, Clone, Default)]
+pub struct AccessTable {
+    state: Arc<RwLock<AccessState>>,
+    procedures: Vec<String>
+}
+
+impl AccessTable {
+
+    pub fn new(keys: &[PublicKey], accesses: &[AccessSchema], parents: &[ParentSchema]) -> Self {
+        let table = Self::default();
+        table.update(keys, accesses, parents);
+        table
+    }
+
+    pub fn update(&self, keys: &[PublicKey], accesses: &[AccessSchema], parents: &[ParentSchema]) {
+        let mut state = self.state.write().unwrap();
+        state.keys = keys.to_owned();
+        state.accesses = accesses.to_owned();
+        state.parents = parents.to_owned();
+    }
+
+    /// Share the table with a service which only use listed procedures
+    pub fn with_procedures(&self, procedures: &[&str]) -> Self {
+        Self {
+            state: self.state.clone(),
+            procedures: procedures.iter().map(|&p| p.to_owned()).collect()
+        }
+    }
+
+    pub fn verifying_keys(&self) -> Vec<PublicKey> {
+        self.state.read().unwrap().keys.clone()
+    }
+
+    pub fn accesses(&self) -> Vec<AccessSchema> {
+        let state = self.state.read().unwrap();
+        let procedures: Vec<&str> = self.procedures.iter().map(|p| p.as_str()).collect();
+        construct_accesses(&state.accesses, &state.parents, &procedures)
+    }
+
+}
+
+/// Role followed by its parent, grandparent, and so on. A cycle in role parents ends the chain.
+pub fn role_ancestors(role: &str, parents: &[ParentSchema]) -> Vec<String>
+{
+    let mut ancestors = vec![role.to_owned()];
+    let mut current = role;
+    while let Some(p) = parents.iter().find(|p| p.role == current) {
+        if ancestors.contains(&p.parent) {
+            break;
+        }
+        ancestors.push(p.parent.clone());
+        current = &p.parent;
+    }
+    ancestors
+}
+
+/// Roles which have access through direct roles, including roles inheriting from one of them
+pub fn effective_roles(roles: &[String], parents: &[ParentSchema]) -> Vec<String>
+{
+    let mut effective = roles.to_vec();
+    for p in parents {
+        if effective.contains(&p.role) {
+            continue;
+        }
+        if role_ancestors(&p.role, parents).iter().any(|r| roles.contains(r)) {
+            effective.push(p.role.clone());
+        }
+    }
+    effective
+}
+
+fn construct_accesses(accesses: &[AccessSchema], parents: &[ParentSchema], procedures: &[&str]) -> Vec<AccessSchema>
+{
+    procedures.iter().map(|&s| AccessSchema {
+        procedure: s.to_owned(),
+        roles: accesses.iter()
+            .find(|&a| a.procedure == s)
+            .map(|a| effective_roles(&a.roles, parents))
+            .unwrap_or_default()
+    })
+    .collect()
+}
+
+/// Identity of the caller taken from access token claims
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Caller {
+    pub user_id: Uuid,
+    pub api_id: Uuid,
+    pub tenant_id: Uuid,
+    pub role: String,
+    pub roles: Vec<String>,
+    pub family_id: String,
+    pub scope: Option<TokenScope>,
+    pub claims: BTreeMap<String, serde_json::Value>
+}
+
+impl From<TokenClaims> for Caller {
+    fn from(value: TokenClaims) -> Self {
+        let roles = value.roles();
+        Self {
+            user_id: value.uid,
+            api_id: value.aid,
+            tenant_id: value.tnt,
+            role: value.sub,
+            roles,
+            family_id: value.fid,
+            scope: value.scp,
+            claims: value.ext
+        }
+    }
+}
+
+impl Caller {
+    pub fn is_root(&self) -> bool {
+        self.role == ROOT_NAME
+    }
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+    pub fn claim(&self, name: &str) -> Option<&serde_json::Value> {
+        self.claims.get(name)
+    }
+}
+
+/// Decode an access token with verifying keys and check it against revoked tokens and IP allowlist
+/// of the token roles
+pub fn verify_token(token: &str, keys: &[PublicKey], ip: Option<RemoteIp>) -> Result<TokenClaims, Status>
+{
+    let claims = decode_token(token, keys, true)
+        .map_err(|_| Status::unauthenticated(TOKEN_EXPIRED))?;
+    if revoked_tokens().is_revoked(claims.jti) {
+        return Err(Status::unauthenticated(TOKEN_REVOKED));
+    }
+    if !claims.ip_allowed(ip.map(|ip| ip.0)) {
+        return Err(Status::permission_denied(IP_NOT_ALLOWED));
+    }
+    Ok(claims)
+}
+
 pub trait AccessValidator {
 
-    fn with_validator(self, token_key: &[u8], accesses: &[AccessSchema]) -> Self;
+    fn with_validator(self, table: &AccessTable) -> Self;
 
-    fn token_key(&self) -> Vec<u8>;
+    fn verifying_keys(&self) -> Vec<PublicKey>;
 
     fn accesses(&self) -> Vec<AccessSchema>;
 
-    fn construct_accesses(accesses: &[AccessSchema], procedures: &[&str]) -> Vec<AccessSchema>
+    fn construct_accesses(accesses: &[AccessSchema], parents: &[ParentSchema], procedures: &[&str]) -> Vec<AccessSchema>
     {
-        procedures.into_iter().map(|&s| AccessSchema {
-            procedure: s.to_owned(),
-            roles: accesses.iter()
-                .filter(|&a| a.procedure == s)
-                .map(|a| a.roles.clone())
-                .next()
-                .unwrap_or_default()
-        })
-        .collect()
+        construct_accesses(accesses, parents, procedures)
+    }
+
+    fn token_claims(&self, extension: &Extensions) -> Result<TokenClaims, Status>
+    {
+        // decode token from request extension using public keys published by auth server and get token claims
+        let token = extension.get::<String>()
+            .ok_or(Status::unauthenticated(EXT_NOT_FOUND))?;
+        verify_token(token, &self.verifying_keys(), extension.get::<RemoteIp>().copied())
+    }
+
+    fn caller(&self, extension: &Extensions) -> Result<Caller, Status>
+    {
+        // use caller inserted by access interceptor which already decoded the token, and decode
+        // the token for services which are not behind the interceptor
+        match extension.get::<Caller>() {
+            Some(caller) => Ok(caller.clone()),
+            None => self.token_claims(extension).map(|claims| claims.into())
+        }
+    }
+
+    fn token_caller(&self, extension: &Extensions) -> Option<Caller>
+    {
+        // return none if service doesn't configured to use validation
+        if self.accesses().is_empty() {
+            return None;
+        }
+        self.caller(extension).ok()
+    }
+
+    fn token_scope(&self, extension: &Extensions) -> Option<TokenScope>
+    {
+        // return none if service doesn't configured to use validation, root and token without
+        // scope are also not restricted to any resource
+        self.token_caller(extension)
+            .filter(|caller| !caller.is_root())
+            .and_then(|caller| caller.scope)
+    }
+
+    fn token_tenant(&self, extension: &Extensions) -> Option<Uuid>
+    {
+        // return none for root and user which is not bound to a tenant
+        self.token_caller(extension)
+            .filter(|caller| !caller.is_root())
+            .map(|caller| caller.tenant_id)
+            .filter(|id| !id.is_nil())
+    }
+
+    fn token_user_id(&self, extension: &Extensions) -> Option<Uuid>
+    {
+        self.token_caller(extension)
+            .map(|caller| caller.user_id)
+            .filter(|id| !id.is_nil())
+    }
+
+    fn token_subject(&self, extension: &Extensions) -> String
+    {
+        // use caller user id as subject, or role name for token without user id
+        match self.token_caller(extension) {
+            Some(caller) if !caller.user_id.is_nil() => caller.user_id.to_string(),
+            Some(caller) => caller.role,
+            None => String::new()
+        }
     }
 
     fn validate(&self, extension: &Extensions, procedure: &str) -> Result<(), Status>
     {
         // return ok if service doesn't configured to use validation
-        if self.accesses().len() == 0 {
+        if self.accesses().is_empty() {
             return Ok(());
         }
-        // decode token from request extension using api accees key or root key and get token claims
-        let token = extension.get::<String>()
-            .ok_or(Status::unauthenticated(EXT_NOT_FOUND))?;
-        let decoding_key = DecodingKey::from_secret(self.token_key().as_slice());
-        let validation = Validation::new(Algorithm::HS256);
-        let decoded = decode::<TokenClaims>(token, &decoding_key, &validation);
-        let claims = match decoded {
-            Ok(value) => value.claims,
-            Err(_) => {
-                let root = ROOT_DATA.get().map(|x| x.to_owned()).unwrap_or_default();
-                let decoding_key = DecodingKey::from_secret(&root.access_key);
-                decode::<TokenClaims>(token, &decoding_key, &validation)
-                    .map_err(|_| Status::unauthenticated(TOKEN_EXPIRED))?
-                    .claims
-            }
-        };
+        let caller = self.caller(extension)?;
         // pass checking for root role
-        if &claims.sub == ROOT_NAME {
+        if caller.is_root() {
             return Ok(())
         }
-        // check if the role in token claims has accsess rights to the procedure
+        // check if one of the roles in token claims has accsess rights to the procedure, directly or inherited
+        // from parent roles which are resolved when accesses are constructed
         let access = self.accesses()
             .into_iter()
-            .filter(|a| a.procedure == procedure)
-            .next()
+            .find(|a| a.procedure == procedure)
             .ok_or(Status::internal(PROC_NOT_FOUND))?;
         let role = access.roles
             .into_iter()
-            .filter(|r| *r == claims.sub)
-            .next();
+            .find(|r| caller.has_role(r));
         match role {
             Some(_) => Ok(()),
             None => Err(Status::unauthenticated(
-                format!("Role {} {}", claims.sub, ACCESS_RIGHT_ERR)
+                format!("Role {} {}", caller.roles.join(","), ACCESS_RIGHT_ERR)
             ))
         }
     }
@@ -104,23 +311,45 @@ pub trait AuthValidator {
 
     fn auth_db(&self) -> &Auth;
 
-    fn validate(&self, extension: &Extensions, kind: ValidatorKind) -> impl std::future::Future<Output = Result<(), Status>> + Send where Self: Sync
+    fn user_id(&self, extension: &Extensions) -> impl std::future::Future<Output = Result<Uuid, Status>> + Send where Self: Sync
     {async move {
-        // return ok if service doesn't configured to use validation
-        if !self.validator_flag() {
-            return Ok(());
-        }
-        // get user id from extension
+        // get user id of auth token from extension
         let token = extension.get::<String>()
             .ok_or(Status::unauthenticated(EXT_NOT_FOUND))?;
         let result = self.auth_db().list_auth_token(token).await;
-        let user_id = match result {
+        match result {
             Ok(value) => match value.into_iter().next() {
-                Some(v) => v.user_id,
-                None => return Err(Status::unauthenticated(USER_UNREGISTERED))
+                Some(v) => Ok(v.user_id),
+                None => Err(Status::unauthenticated(USER_UNREGISTERED))
             },
-            Err(_) => return Err(Status::unauthenticated(USER_UNREGISTERED))
+            Err(_) => Err(Status::unauthenticated(USER_UNREGISTERED))
+        }
+    } }
+
+    fn tenant_db(&self, extension: &Extensions) -> impl std::future::Future<Output = Result<Auth, Status>> + Send where Self: Sync
+    {async move {
+        // auth database bound to tenant of the token user, every query only reads and writes users,
+        // roles, and APIs of the tenant. Root, user without tenant, and request without token are
+        // not restricted
+        let user_id = match self.user_id(extension).await {
+            Ok(value) if value != ROOT_ID => value,
+            _ => return Ok(self.auth_db().clone())
         };
+        let user = self.auth_db().read_user(user_id).await
+            .map_err(handle_error)?;
+        match user.tenant_id {
+            Some(tenant_id) => Ok(self.auth_db().with_tenant(tenant_id)),
+            None => Ok(self.auth_db().clone())
+        }
+    } }
+
+    fn validate(&self, extension: &Extensions, kind: ValidatorKind) -> impl std::future::Future<Output = Result<(), Status>> + Send where Self: Sync
+    {async move {
+        // return ok if service doesn't configured to use validation
+        if !self.validator_flag() {
+            return Ok(());
+        }
+        let user_id = self.user_id(extension).await?;
         // check input user id or root user
         if let ValidatorKind::User(id) = kind {
             if id == user_id {
@@ -135,3 +364,48 @@ pub trait AuthValidator {
     } }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent(role: &str, parent: &str) -> ParentSchema {
+        ParentSchema { role: role.to_owned(), parent: parent.to_owned() }
+    }
+
+    fn names(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn role_ancestors_follow_parent_chain() {
+        let parents = [parent("admin", "operator"), parent("operator", "viewer")];
+        assert_eq!(role_ancestors("admin", &parents), names(&["admin", "operator", "viewer"]));
+        assert_eq!(role_ancestors("viewer", &parents), names(&["viewer"]));
+    }
+
+    #[test]
+    fn role_ancestors_stop_on_cycle() {
+        let parents = [parent("a", "b"), parent("b", "a")];
+        assert_eq!(role_ancestors("a", &parents), names(&["a", "b"]));
+    }
+
+    #[test]
+    fn effective_roles_include_descendants() {
+        let parents = [parent("admin", "operator"), parent("operator", "viewer"), parent("guest", "public")];
+        let mut roles = effective_roles(&names(&["viewer"]), &parents);
+        roles.sort();
+        assert_eq!(roles, names(&["admin", "operator", "viewer"]));
+        assert_eq!(effective_roles(&names(&["admin"]), &parents), names(&["admin"]));
+    }
+
+    #[test]
+    fn construct_accesses_resolve_inherited_roles() {
+        let accesses = [AccessSchema { procedure: String::from("read"), roles: names(&["viewer"]) }];
+        let parents = [parent("admin", "viewer")];
+        let result = construct_accesses(&accesses, &parents, &["read", "write"]);
+        assert_eq!(result[0].roles, names(&["viewer", "admin"]));
+        assert!(result[1].roles.is_empty());
+    }
+
+}
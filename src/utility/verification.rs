@@ -0,0 +1,166 @@
+use chrono::{DateTime, Utc, Duration};
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Sha256, Digest};
+use uuid::Uuid;
+use rmcs_auth_db::Auth;
+use rmcs_auth_db::schema::auth_user::VerificationSchema;
+use super::config::VERIFICATIONS;
+
+const TOKEN_LENGTH: usize = 32;
+const DEF_TOKEN_DURATION: i64 = 900;
+
+/// Profile name used to store verified email, a value is verified only while it is equal to
+/// the current user email
+pub const EMAIL_VERIFIED: &str = "email_verified";
+/// Profile name used to store verified phone number, verified the same way as email
+pub const PHONE_VERIFIED: &str = "phone_verified";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationKind {
+    PasswordReset,
+    Email,
+    Phone
+}
+
+impl From<i32> for VerificationKind {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Email,
+            2 => Self::Phone,
+            _ => Self::PasswordReset
+        }
+    }
+}
+
+impl From<VerificationKind> for i32 {
+    fn from(value: VerificationKind) -> Self {
+        match value {
+            VerificationKind::PasswordReset => 0,
+            VerificationKind::Email => 1,
+            VerificationKind::Phone => 2
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub kind: VerificationKind,
+    pub user_id: Uuid,
+    /// Email or phone number which the token is sent to
+    pub target: String,
+    pub expire: DateTime<Utc>
+}
+
+impl From<VerificationSchema> for Verification {
+    fn from(value: VerificationSchema) -> Self {
+        Self {
+            kind: VerificationKind::from(value.kind),
+            user_id: value.user_id,
+            target: value.target,
+            expire: value.expire
+        }
+    }
+}
+
+impl Verification {
+    pub fn is_expired(&self) -> bool {
+        self.expire <= Utc::now()
+    }
+}
+
+fn generate_token() -> String
+{
+    thread_rng().sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Hash of a verification token which is stored instead of the token
+fn token_hash(token: &str) -> Vec<u8>
+{
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// Single use and expiring tokens for password reset and contact verification. Only token hash is
+/// stored in auth database so tokens are valid on every replica and survive restart, and a new
+/// token replace previous token of the same user and kind.
+#[derive(Debug, Default)]
+pub struct Verifications {
+    duration: i64
+}
+
+impl Verifications {
+
+    pub fn new(duration: i64) -> Self {
+        Self { duration }
+    }
+
+    pub async fn create(&self, auth_db: &Auth, kind: VerificationKind, user_id: Uuid, target: &str) -> Result<String, sqlx::Error> {
+        let token = generate_token();
+        let hash = token_hash(&token);
+        let expire = Utc::now() + Duration::seconds(self.duration);
+        auth_db.create_verification(kind.into(), user_id, target, &hash, expire).await?;
+        Ok(token)
+    }
+
+    /// Remove a token and return its verification if the kind match and not expired
+    pub async fn take(&self, auth_db: &Auth, kind: VerificationKind, token: &str) -> Option<Verification> {
+        let hash = token_hash(token);
+        // the row is deleted and returned in one statement so a token is only used once
+        let verification: Verification = auth_db.take_verification(kind.into(), &hash).await.ok().flatten()?.into();
+        if verification.is_expired() { None } else { Some(verification) }
+    }
+
+}
+
+/// Verification tokens of this server, duration is read from VERIFICATION_DURATION environment variable
+pub fn verifications() -> &'static Verifications {
+    VERIFICATIONS.get_or_init(|| {
+        let duration = std::env::var("VERIFICATION_DURATION").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEF_TOKEN_DURATION);
+        Verifications::new(duration)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_round_trip() {
+        for kind in [VerificationKind::PasswordReset, VerificationKind::Email, VerificationKind::Phone] {
+            assert_eq!(VerificationKind::from(i32::from(kind)), kind);
+        }
+    }
+
+    #[test]
+    fn generated_token_is_random_alphanumeric() {
+        let token = generate_token();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn token_hash_is_stable() {
+        assert_eq!(token_hash("token"), token_hash("token"));
+        assert_ne!(token_hash("token"), token_hash("other"));
+        assert_eq!(token_hash("token").len(), 32);
+    }
+
+    #[test]
+    fn verification_expire() {
+        let mut verification = Verification {
+            kind: VerificationKind::Email,
+            user_id: Uuid::nil(),
+            target: String::from("user@example.com"),
+            expire: Utc::now() + Duration::seconds(60)
+        };
+        assert!(!verification.is_expired());
+        verification.expire = Utc::now() - Duration::seconds(1);
+        assert!(verification.is_expired());
+    }
+
+}
@@ -1,8 +1,10 @@
 #[allow(dead_code)]
 #[cfg(test)]
 mod tests {
-    use tonic::{Request, transport::Channel};
+    use std::time::Duration;
+    use tonic::{Request, Status, Code, transport::Channel};
     use uuid::Uuid;
+    use totp_rs::{TOTP, Algorithm, Secret};
     use rmcs_auth_api::api::api_service_client::ApiServiceClient;
     use rmcs_auth_api::api::{ApiSchema, ProcedureSchema, ApiId, ProcedureId};
     use rmcs_auth_api::role::role_service_client::RoleServiceClient;
@@ -10,19 +12,47 @@ mod tests {
     use rmcs_auth_api::user::user_service_client::UserServiceClient;
     use rmcs_auth_api::user::{UserSchema, UserRole, UserId};
     use rmcs_auth_api::auth::auth_service_client::AuthServiceClient;
-    use rmcs_auth_api::auth::{UserKeyRequest, UserLoginRequest, UserRefreshRequest, UserLogoutRequest};
+    use rmcs_auth_api::auth::{
+        UserKeyRequest, UserLoginRequest, UserLoginResponse, UserMfaRequest, ApiKeyLoginRequest, UserRefreshRequest, UserLogoutRequest
+    };
+    use rmcs_auth_api::mfa::mfa_service_client::MfaServiceClient;
+    use rmcs_auth_api::mfa::{MfaEnrollRequest, MfaCodeRequest};
+    use rmcs_auth_api::lockout::lockout_service_client::LockoutServiceClient;
+    use rmcs_auth_api::lockout::{LoginLockListRequest, LoginUnlockRequest};
+    use rmcs_auth_api::service_account::service_account_service_client::ServiceAccountServiceClient;
+    use rmcs_auth_api::service_account::{ServiceAccountSchema, ApiKeyCreate, ApiKeyId};
     use rmcs_resource_api::model::model_service_client::ModelServiceClient;
-    use rmcs_resource_api::model::{ModelSchema, TagSchema, ModelId};
+    use rmcs_resource_api::model::{ModelSchema, TagSchema, ModelId, ConfigSchema as ModelConfigSchema};
+    use rmcs_resource_api::device::device_service_client::DeviceServiceClient;
+    use rmcs_resource_api::device::{
+        TypeSchema, TypeModel, TypeId, GatewaySchema, GatewayId as DeviceGatewayId, DeviceSchema, DeviceId, ConfigSchema
+    };
+    use rmcs_resource_api::command::command_service_client::CommandServiceClient;
+    use rmcs_resource_api::command::{CommandSchema, GatewayId};
+    use rmcs_resource_db::DataValue;
     use rmcs_api_server::utility::{import_public_key, encrypt_message};
     use rmcs_api_server::utility::config::{ROOT_NAME, ROOT_DATA};
     use rmcs_api_server::utility::interceptor::TokenInterceptor;
+    use rmcs_api_server::utility::throttle::LoginKind;
     use rmcs_api_server::utility::test::{TestServerKind, TestServer};
 
     const ACCESSES: &[(&str, &[&str])] = &[
         ("read_model", &["admin", "user"]),
         ("create_model", &["admin"]),
         ("delete_model", &["admin"]),
-        ("change_model_type", &["admin"])
+        ("change_model_type", &["admin"]),
+        ("create_model_config", &["admin"]),
+        ("read_device", &["admin"]),
+        ("create_device", &["admin"]),
+        ("delete_device", &["admin"]),
+        ("read_device_config", &["admin"]),
+        ("create_device_config", &["admin"]),
+        ("create_type", &["admin"]),
+        ("update_type", &["admin"]),
+        ("delete_type", &["admin"]),
+        ("change_type_model", &["admin"]),
+        ("read_command", &["admin"]),
+        ("create_command", &["admin"])
     ];
 
     const ROLES: &[&str] = &["admin", "user"];
@@ -32,12 +62,15 @@ mod tests {
     const ADMIN_PW: &str = "Adm1n_P4s5w0rd";
     const USER_PW: &str = "Us3r_P4s5w0rd";
     
+    /// Time for resource server to receive revoked tokens from auth server
+    const REVOKE_WAIT: Duration = Duration::from_millis(1000);
+
     const USERS: &[(&str, &str, &str)] = &[
         (ADMIN_NAME, ADMIN_PW, "admin"),
         (USER_NAME, USER_PW, "user")
     ];
 
-    async fn login(address: &str, username: &str, password: &str) -> (Uuid, String, String, String) {
+    async fn try_login(address: &str, username: &str, password: &str) -> Result<UserLoginResponse, Status> {
         let channel = Channel::from_shared(address.to_owned()).unwrap().connect().await.unwrap();
         let mut client = AuthServiceClient::new(channel);
         let request = Request::new(UserKeyRequest { });
@@ -48,15 +81,23 @@ mod tests {
         // request access and refresh tokens
         let request = Request::new(UserLoginRequest {
             username: username.to_owned(),
-            password: passhash
+            password: passhash,
+            roles: Vec::new()
         });
-        let response = client.user_login(request).await.unwrap().into_inner();
+        client.user_login(request).await.map(|response| response.into_inner())
+    }
+
+    fn login_tokens(response: UserLoginResponse) -> (Uuid, String, String, String) {
         let (access_token, refresh_token) = response.access_tokens.into_iter()
             .map(|a| (a.access_token, a.refresh_token)).next().unwrap();
         (Uuid::from_slice(&response.user_id).unwrap(), response.auth_token, access_token, refresh_token)
     }
 
-    async fn refresh(address: &str, api_id: Uuid, access_token: &str, refresh_token: &str) -> (String, String) {
+    async fn login(address: &str, username: &str, password: &str) -> (Uuid, String, String, String) {
+        login_tokens(try_login(address, username, password).await.unwrap())
+    }
+
+    async fn try_refresh(address: &str, api_id: Uuid, access_token: &str, refresh_token: &str) -> Result<(String, String), Status> {
         let channel = Channel::from_shared(address.to_owned()).unwrap().connect().await.unwrap();
         let mut client = AuthServiceClient::new(channel);
         let request = Request::new(UserRefreshRequest {
@@ -64,8 +105,30 @@ mod tests {
             access_token: access_token.to_owned(),
             refresh_token: refresh_token.to_owned()
         });
-        let response = client.user_refresh(request).await.unwrap().into_inner();
-        (response.access_token, response.refresh_token)
+        client.user_refresh(request).await
+            .map(|response| response.into_inner())
+            .map(|response| (response.access_token, response.refresh_token))
+    }
+
+    async fn refresh(address: &str, api_id: Uuid, access_token: &str, refresh_token: &str) -> (String, String) {
+        try_refresh(address, api_id, access_token, refresh_token).await.unwrap()
+    }
+
+    fn current_code(secret: &str) -> String {
+        let secret = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+        TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, None, String::new()).unwrap()
+            .generate_current().unwrap()
+    }
+
+    fn config_schema(device_id: &[u8], name: &str, value: DataValue) -> ConfigSchema {
+        ConfigSchema {
+            device_id: device_id.to_vec(),
+            name: name.to_owned(),
+            config_bytes: value.to_bytes(),
+            config_type: value.get_type().into(),
+            category: String::from("TEST"),
+            ..Default::default()
+        }
     }
 
     async fn logout(address: &str, user_id: Uuid, auth_token: &str) {
@@ -139,6 +202,8 @@ mod tests {
                 name: name.to_owned(),
                 multi: false,
                 ip_lock: true,
+                ip_allow: Vec::new(),
+                parent_id: None,
                 access_duration: 900,
                 refresh_duration: 43200,
                 access_key: Vec::new(),
@@ -258,6 +323,260 @@ mod tests {
         });
         model_service_user.read_model(request).await.unwrap();
 
+        // replay the rotated refresh token, every token of the login should be revoked and the
+        // refreshed access token should be rejected by resource server
+        let try_response = try_refresh(&auth_server.address, Uuid::from_slice(&api_id).unwrap(), &user_access, &user_refresh).await;
+        assert_eq!(try_response.unwrap_err().code(), Code::PermissionDenied);
+        tokio::time::sleep(REVOKE_WAIT).await;
+        let request = Request::new(ModelId {
+            id: model_id.clone()
+        });
+        let try_response = model_service_user.read_model(request).await;
+        assert!(try_response.is_err());
+        let (user_id, user_auth, _, _) = 
+            login(&auth_server.address, USER_NAME, USER_PW).await;
+
+        // construct lockout, mfa, service account, and auth service
+        let auth_channel = Channel::from_shared(auth_server.address.clone()).unwrap().connect().await.unwrap();
+        let mut lockout_service = 
+            LockoutServiceClient::with_interceptor(auth_channel.clone(), interceptor.clone());
+        let mut mfa_service = 
+            MfaServiceClient::with_interceptor(auth_channel.clone(), TokenInterceptor(user_auth.to_owned()));
+        let mut service_account_service = 
+            ServiceAccountServiceClient::with_interceptor(auth_channel.clone(), interceptor.clone());
+        let mut auth_service = AuthServiceClient::new(auth_channel.clone());
+
+        // failed logins lock the admin, even correct password should failed until root unlock it
+        let max_failure: usize = std::env::var("LOGIN_MAX_FAILURE").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5);
+        for _ in 0..max_failure {
+            let try_response = try_login(&auth_server.address, ADMIN_NAME, USER_PW).await;
+            assert!(try_response.is_err());
+        }
+        let try_response = try_login(&auth_server.address, ADMIN_NAME, ADMIN_PW).await;
+        assert_eq!(try_response.unwrap_err().code(), Code::PermissionDenied);
+        let request = Request::new(LoginLockListRequest {
+            kind: Some(LoginKind::User.into()),
+            name: Some(ADMIN_NAME.to_owned())
+        });
+        let locks = lockout_service.list_login_lock(request).await.unwrap().into_inner().results;
+        assert_eq!(locks.len(), 1);
+        let request = Request::new(LoginUnlockRequest {
+            kind: LoginKind::User.into(),
+            name: ADMIN_NAME.to_owned()
+        });
+        lockout_service.unlock_login(request).await.unwrap();
+        let (unlock_id, unlock_auth, _, _) = 
+            login(&auth_server.address, ADMIN_NAME, ADMIN_PW).await;
+        logout(&auth_server.address, unlock_id, &unlock_auth).await;
+
+        // enroll user MFA, login should return a challenge instead of tokens
+        let request = Request::new(MfaEnrollRequest {
+            user_id: user_id.as_bytes().to_vec()
+        });
+        let secret = mfa_service.enroll_mfa(request).await.unwrap().into_inner().secret;
+        let request = Request::new(MfaCodeRequest {
+            user_id: user_id.as_bytes().to_vec(),
+            code: current_code(&secret),
+            current_code: String::new()
+        });
+        let recovery_codes = mfa_service.confirm_mfa(request).await.unwrap().into_inner().recovery_codes;
+        let response = try_login(&auth_server.address, USER_NAME, USER_PW).await.unwrap();
+        assert!(response.access_tokens.is_empty());
+        // wrong code should failed and the challenge can not be used again
+        let request = Request::new(UserMfaRequest {
+            challenge: response.mfa_challenge.clone(),
+            code: String::from("000000")
+        });
+        let try_response = auth_service.user_login_mfa(request).await;
+        assert!(try_response.is_err());
+        // complete a new challenge using a recovery code
+        let response = try_login(&auth_server.address, USER_NAME, USER_PW).await.unwrap();
+        let request = Request::new(UserMfaRequest {
+            challenge: response.mfa_challenge,
+            code: recovery_codes[0].clone()
+        });
+        let response = auth_service.user_login_mfa(request).await.unwrap().into_inner();
+        let (mfa_id, mfa_auth, _, _) = login_tokens(response);
+        logout(&auth_server.address, mfa_id, &mfa_auth).await;
+        let request = Request::new(MfaCodeRequest {
+            user_id: user_id.as_bytes().to_vec(),
+            code: recovery_codes[1].clone(),
+            current_code: String::new()
+        });
+        mfa_service.disable_mfa(request).await.unwrap();
+
+        // create a service account with user role and an API key of the account
+        let request = Request::new(ServiceAccountSchema {
+            id: Uuid::new_v4().as_bytes().to_vec(),
+            name: String::from("service account"),
+            description: String::new()
+        });
+        let account_id = service_account_service.create_service_account(request).await.unwrap().into_inner().id;
+        let user_role_id = role_map.iter()
+            .filter(|(_, s)| *s == "user")
+            .map(|(i, _)| i)
+            .next().unwrap();
+        let request = Request::new(UserRole {
+            user_id: account_id.clone(),
+            role_id: user_role_id.to_owned()
+        });
+        user_service.add_user_role(request).await.unwrap();
+        let request = Request::new(ApiKeyCreate {
+            user_id: account_id.clone(),
+            name: String::from("service key"),
+            roles: vec![String::from("user")],
+            expire: 0
+        });
+        let api_key = service_account_service.create_api_key(request).await.unwrap().into_inner();
+
+        // exchange the API key for tokens and read model, the token should be rejected after the key is revoked
+        let request = Request::new(ApiKeyLoginRequest {
+            key: api_key.key.clone(),
+            roles: Vec::new()
+        });
+        let response = auth_service.api_key_login(request).await.unwrap().into_inner();
+        let (_, _, key_access, _) = login_tokens(response);
+        let mut model_service_key = 
+            ModelServiceClient::with_interceptor(channel.clone(), TokenInterceptor(key_access.to_owned()));
+        let request = Request::new(ModelId {
+            id: model_id.clone()
+        });
+        model_service_key.read_model(request).await.unwrap();
+        let request = Request::new(ApiKeyId {
+            id: api_key.id.clone()
+        });
+        service_account_service.revoke_api_key(request).await.unwrap();
+        tokio::time::sleep(REVOKE_WAIT).await;
+        let request = Request::new(ModelId {
+            id: model_id.clone()
+        });
+        let try_response = model_service_key.read_model(request).await;
+        assert!(try_response.is_err());
+
+        // remove service account
+        let request = Request::new(UserRole {
+            user_id: account_id.clone(),
+            role_id: user_role_id.to_owned()
+        });
+        user_service.remove_user_role(request).await.unwrap();
+        let request = Request::new(UserId {
+            id: account_id.clone()
+        });
+        user_service.delete_user(request).await.unwrap();
+
+        // construct device and command service for admin
+        let mut device_service = 
+            DeviceServiceClient::with_interceptor(channel.clone(), interceptor_admin.clone());
+        let mut command_service = 
+            CommandServiceClient::with_interceptor(channel.clone(), interceptor_admin.clone());
+
+        // create a type of the model, a gateway and a device of the type
+        let type_id = Uuid::new_v4().as_bytes().to_vec();
+        let request = Request::new(TypeSchema {
+            id: type_id.clone(),
+            name: String::from("device type"),
+            description: String::new(),
+            ..Default::default()
+        });
+        device_service.create_type(request).await.unwrap();
+        let request = Request::new(TypeModel {
+            id: type_id.clone(),
+            model_id: model_id.clone()
+        });
+        device_service.add_type_model(request).await.unwrap();
+        let gateway_id = Uuid::new_v4().as_bytes().to_vec();
+        let request = Request::new(GatewaySchema {
+            id: gateway_id.clone(),
+            gateway_type: Some(TypeSchema { id: type_id.clone(), ..Default::default() }),
+            serial_number: String::from("GATEWAY01"),
+            name: String::from("gateway"),
+            ..Default::default()
+        });
+        device_service.create_gateway(request).await.unwrap();
+        let device_id = Uuid::new_v4().as_bytes().to_vec();
+        let request = Request::new(DeviceSchema {
+            id: device_id.clone(),
+            gateway_id: gateway_id.clone(),
+            device_type: Some(TypeSchema { id: type_id.clone(), ..Default::default() }),
+            serial_number: String::from("DEVICE01"),
+            name: String::from("device"),
+            ..Default::default()
+        });
+        device_service.create_device(request).await.unwrap();
+
+        // create configs in every layer, higher layer should override config of the same name
+        let value = DataValue::I32(1);
+        let request = Request::new(ModelConfigSchema {
+            model_id: model_id.clone(),
+            index: 0,
+            name: String::from("scale"),
+            config_bytes: value.to_bytes(),
+            config_type: value.get_type().into(),
+            category: String::from("TEST"),
+            ..Default::default()
+        });
+        model_service_admin.create_model_config(request).await.unwrap();
+        let request = Request::new(config_schema(&type_id, "interval", DataValue::I32(10)));
+        device_service.create_type_config(request).await.unwrap();
+        let request = Request::new(config_schema(&gateway_id, "interval", DataValue::I32(20)));
+        device_service.create_gateway_config(request).await.unwrap();
+        let request = Request::new(config_schema(&gateway_id, "offset", DataValue::I32(2)));
+        device_service.create_gateway_config(request).await.unwrap();
+        let request = Request::new(config_schema(&device_id, "offset", DataValue::I32(3)));
+        device_service.create_device_config(request).await.unwrap();
+        let request = Request::new(DeviceId {
+            id: device_id.clone()
+        });
+        let results = device_service.resolve_device_config(request).await.unwrap().into_inner().results;
+        assert_eq!(results.len(), 3);
+        let resolved = |name: &str| results.iter().find(|r| r.name == name).unwrap();
+        assert_eq!(resolved("scale").source_id, model_id);
+        assert_eq!(resolved("interval").source_id, gateway_id);
+        assert_eq!(resolved("interval").config_bytes, DataValue::I32(20).to_bytes());
+        assert_eq!(resolved("offset").source_id, device_id);
+        assert_eq!(resolved("offset").config_bytes, DataValue::I32(3).to_bytes());
+
+        // stream commands of the gateway and create a command for the device
+        let request = Request::new(GatewayId {
+            id: gateway_id.clone()
+        });
+        let mut stream = command_service.stream_command(request).await.unwrap().into_inner();
+        let value = DataValue::String(String::from("restart"));
+        let request = Request::new(CommandSchema {
+            device_id: device_id.clone(),
+            name: String::from("restart"),
+            payload_bytes: value.to_bytes(),
+            payload_type: value.get_type().into(),
+            ..Default::default()
+        });
+        command_service.create_command(request).await.unwrap();
+        let command = tokio::time::timeout(Duration::from_secs(5), stream.message()).await
+            .unwrap().unwrap().unwrap();
+        assert_eq!(command.name, "restart");
+        assert_eq!(command.device_id, device_id);
+        drop(stream);
+
+        // delete device, gateway, and type
+        let request = Request::new(DeviceId {
+            id: device_id.clone()
+        });
+        device_service.delete_device(request).await.unwrap();
+        let request = Request::new(DeviceGatewayId {
+            id: gateway_id.clone()
+        });
+        device_service.delete_gateway(request).await.unwrap();
+        let request = Request::new(TypeModel {
+            id: type_id.clone(),
+            model_id: model_id.clone()
+        });
+        device_service.remove_type_model(request).await.unwrap();
+        let request = Request::new(TypeId {
+            id: type_id.clone()
+        });
+        device_service.delete_type(request).await.unwrap();
+
         // remove model type and delete model
         let id = ModelId {
             id: model_id.clone()